@@ -1,5 +1,6 @@
 use criterion::{criterion_group, criterion_main, Criterion};
-use spacey::{parser::SourceType, Vm, VmConfig, VmError};
+use spacey::{parser::SourceType, Buffering, Vm, VmConfig, VmError};
+use std::{env, fs, fs::File};
 
 pub fn single_instruction_with_param_benchmark(c: &mut Criterion) {
     let config =
@@ -99,6 +100,71 @@ pub fn primes_benchmark(c: &mut Criterion) {
     });
 }
 
+pub fn echo_benchmark(c: &mut Criterion) {
+    let mut config =
+        VmConfig::default_heap_suppressed("resources/ws/echo.ws", SourceType::Whitespace);
+    config.set_generated_input("the quick brown fox jumps over the lazy dog");
+    let mut vm = Vm::new(config).unwrap();
+    c.bench_function("echo", |b| {
+        b.iter(|| -> Result<(), VmError> {
+            vm.run()?;
+            vm.reset();
+
+            Ok(())
+        })
+    });
+}
+
+pub fn quine_buffering_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("quine output buffering");
+    for (name, buffering) in [
+        ("per char", Buffering::PerChar),
+        ("block", Buffering::Block),
+    ] {
+        let mut config = VmConfig::default_heap("resources/ws/quine.ws", SourceType::Whitespace);
+        config.set_output_buffering(buffering);
+        let mut vm = Vm::new(config).unwrap();
+        let output = File::create(env::temp_dir().join("spacey_quine_benchmark")).unwrap();
+        vm.set_output(Box::new(output));
+        group.bench_function(name, |b| {
+            b.iter(|| -> Result<(), VmError> {
+                vm.run()?;
+                vm.reset();
+
+                Ok(())
+            })
+        });
+    }
+    group.finish();
+}
+
+pub fn streaming_benchmark(c: &mut Criterion) {
+    // a straight-line program pushing and dropping a number many times
+    let mut source = b"   \t\n \n\n".repeat(100_000);
+    source.extend_from_slice(b"\n\n\n");
+    let path = env::temp_dir().join("spacey_streaming_benchmark.ws");
+    fs::write(&path, source).unwrap();
+    let path = path.to_str().unwrap();
+    let mut group = c.benchmark_group("straight-line program");
+    group.bench_function("buffered", |b| {
+        b.iter(|| -> Result<(), VmError> {
+            let config = VmConfig::default_no_heap_suppressed(path, SourceType::Whitespace);
+            Vm::new(config)?.run()?;
+
+            Ok(())
+        })
+    });
+    group.bench_function("streaming", |b| {
+        b.iter(|| -> Result<(), VmError> {
+            let config = VmConfig::default_no_heap_suppressed(path, SourceType::Whitespace);
+            Vm::new_streaming(config)?.run()?;
+
+            Ok(())
+        })
+    });
+    group.finish();
+}
+
 criterion_group!(
     vm,
     count_benchmark,
@@ -107,6 +173,9 @@ criterion_group!(
     sieve_benchmark,
     reset_vm_benchmark,
     single_instruction_with_param_benchmark,
-    primes_benchmark
+    primes_benchmark,
+    echo_benchmark,
+    quine_buffering_benchmark,
+    streaming_benchmark
 );
 criterion_main!(vm);
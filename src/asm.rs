@@ -0,0 +1,301 @@
+use crate::ir::{Label, Number};
+use crate::lint;
+use crate::parser::{ParseError, ParseErrorKind};
+use crate::ws::{WsCommandKind, LINE_FEED, SPACE, TAB};
+use crate::Instruction;
+
+/// Encodes the instructions as whitespace source
+pub fn encode(instructions: &[Instruction]) -> Vec<u8> {
+    let mut out = vec![];
+    for instr in instructions {
+        let (command, param): (&[u8], _) = match instr {
+            Instruction::PushStack(num) => (b"  ", Some(encode_number(num))),
+            Instruction::DuplicateStack => (b" \n ", None),
+            Instruction::CopyNthStack(num) => (b" \t ", Some(encode_number(num))),
+            Instruction::SwapStack => (b" \n\t", None),
+            Instruction::DiscardStack => (b" \n\n", None),
+            Instruction::SlideNStack(num) => (b" \t\n", Some(encode_number(num))),
+            Instruction::Add => (b"\t   ", None),
+            Instruction::Subtract => (b"\t  \t", None),
+            Instruction::Multiply => (b"\t  \n", None),
+            Instruction::IntegerDivision => (b"\t \t ", None),
+            Instruction::Modulo => (b"\t \t\t", None),
+            Instruction::StoreHeap => (b"\t\t ", None),
+            Instruction::RetrieveHeap => (b"\t\t\t", None),
+            Instruction::Mark(label) => (b"\n  ", Some(encode_label(label))),
+            Instruction::Call(label) => (b"\n \t", Some(encode_label(label))),
+            Instruction::Jump(label) => (b"\n \n", Some(encode_label(label))),
+            Instruction::JumpZero(label) => (b"\n\t ", Some(encode_label(label))),
+            Instruction::JumpNegative(label) => (b"\n\t\t", Some(encode_label(label))),
+            Instruction::Return => (b"\n\t\n", None),
+            Instruction::Exit => (b"\n\n\n", None),
+            Instruction::OutCharacter => (b"\t\n  ", None),
+            Instruction::OutInteger => (b"\t\n \t", None),
+            Instruction::ReadCharacter => (b"\t\n\t ", None),
+            Instruction::ReadInteger => (b"\t\n\t\t", None),
+            Instruction::JumpDynamic => (b"\n\n ", None),
+            Instruction::Syscall(num) => (b"\n\n\t", Some(encode_number(num))),
+        };
+        out.extend_from_slice(command);
+        if let Some(param) = param {
+            out.extend(param);
+        }
+    }
+
+    out
+}
+
+/// Encodes a number as its sign followed by the binary digits of its magnitude. Zero is encoded
+/// with a single digit, since not every interpreter accepts an empty number.
+fn encode_number(num: &Number) -> Vec<u8> {
+    let mut out = vec![if num.value < 0 { TAB } else { SPACE }];
+    out.extend(
+        format!("{:b}", num.value.unsigned_abs())
+            .bytes()
+            .map(|bit| if bit == b'0' { SPACE } else { TAB }),
+    );
+    out.push(LINE_FEED);
+
+    out
+}
+
+fn encode_label(label: &Label) -> Vec<u8> {
+    let mut out = label.value.as_bytes().to_vec();
+    out.push(LINE_FEED);
+
+    out
+}
+
+/// Converts whitespace source to its STL representation, writing `S` for a space, `T` for a tab
+/// and `L` for a line feed. Comments are dropped.
+pub fn to_stl(source: &[u8]) -> Vec<u8> {
+    source
+        .iter()
+        .filter_map(|token| match *token {
+            SPACE => Some(b'S'),
+            TAB => Some(b'T'),
+            LINE_FEED => Some(b'L'),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Renders whitespace source with visible symbols, `␠` for a space, `␉` for a tab and `␊` for a
+/// line feed. Comments are dropped.
+pub fn to_symbols(source: &[u8]) -> String {
+    source
+        .iter()
+        .filter_map(|token| match *token {
+            SPACE => Some('␠'),
+            TAB => Some('␉'),
+            LINE_FEED => Some('␊'),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Converts the STL representation back to whitespace source. Every byte other than `S`, `T` and
+/// `L` is a comment and dropped.
+pub fn from_stl(source: &[u8]) -> Vec<u8> {
+    source
+        .iter()
+        .filter_map(|token| match *token {
+            b'S' => Some(SPACE),
+            b'T' => Some(TAB),
+            b'L' => Some(LINE_FEED),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Writes the instructions as assembly, one mnemonic per line, see `WsCommandKind::mnemonic`.
+/// Labels are written as `L` followed by their bits, e.g. `L0101`.
+pub fn disassemble(instructions: &[Instruction]) -> String {
+    write_assembly(instructions, false)
+}
+
+/// Writes the instructions as assembly like `disassemble`, but writes labels as `.` followed by
+/// their number, e.g. `.10` for `L1010`, see `Label::number`. Labels with leading zeros would
+/// lose them as a number, they are written as bits.
+pub fn disassemble_numeric(instructions: &[Instruction]) -> String {
+    write_assembly(instructions, true)
+}
+
+fn write_assembly(instructions: &[Instruction], numeric_labels: bool) -> String {
+    let mut out = String::new();
+    for instr in instructions {
+        out.push_str(lint::command_kind(instr).mnemonic());
+        match instr {
+            Instruction::PushStack(num)
+            | Instruction::CopyNthStack(num)
+            | Instruction::SlideNStack(num)
+            | Instruction::Syscall(num) => out.push_str(&format!(" {}", num.value)),
+            Instruction::Mark(label)
+            | Instruction::Call(label)
+            | Instruction::Jump(label)
+            | Instruction::JumpZero(label)
+            | Instruction::JumpNegative(label) => match label.number() {
+                Some(number) if numeric_labels && format!("{:b}", number) == label.bits() => {
+                    out.push_str(&format!(" .{}", number))
+                }
+                _ => out.push_str(&format!(" L{}", label.bits())),
+            },
+            _ => {}
+        }
+        out.push('\n');
+    }
+
+    out
+}
+
+/// Parses assembly as written by `disassemble` or `disassemble_numeric`. Empty lines and
+/// everything after a `;` are ignored.
+pub fn assemble(source: &str) -> Result<Vec<Instruction>, ParseError> {
+    let mut instructions = vec![];
+    for (line_index, line) in source.lines().enumerate() {
+        let line_number = line_index + 1;
+        let line = line.split(';').next().unwrap_or_default();
+        let mut words = line.split_whitespace();
+        let mnemonic = match words.next() {
+            Some(mnemonic) => mnemonic,
+            None => continue,
+        };
+        let operand = words.next();
+        if let Some(extra) = words.next() {
+            return ParseErrorKind::AssemblyError(
+                line_number,
+                format!("unexpected operand {}", extra),
+            )
+            .throw();
+        }
+        let kind = match WsCommandKind::from_mnemonic(mnemonic) {
+            Some(kind) => kind,
+            None => {
+                return ParseErrorKind::AssemblyError(
+                    line_number,
+                    format!("unknown mnemonic {}", mnemonic),
+                )
+                .throw()
+            }
+        };
+        if kind.param_kind().is_none() && operand.is_some() {
+            return ParseErrorKind::AssemblyError(
+                line_number,
+                format!("{} takes no operand", mnemonic),
+            )
+            .throw();
+        }
+        let number = || match operand.map(str::parse) {
+            Some(Ok(value)) => Ok(Number { value }),
+            _ => {
+                ParseErrorKind::AssemblyError(line_number, format!("{} expects a number", mnemonic))
+                    .throw()
+            }
+        };
+        let bits = operand.and_then(|operand| match operand.strip_prefix('.') {
+            Some(number) => number
+                .parse::<u64>()
+                .ok()
+                .map(|number| format!("{:b}", number)),
+            None => operand.strip_prefix('L').map(str::to_string),
+        });
+        let label = || match &bits {
+            Some(bits) if bits.bytes().all(|bit| bit == b'0' || bit == b'1') => Ok(Label {
+                value: bits
+                    .chars()
+                    .map(|bit| if bit == '0' { ' ' } else { '\t' })
+                    .collect::<String>()
+                    .into(),
+                index: 0,
+            }),
+            _ => ParseErrorKind::AssemblyError(
+                line_number,
+                format!("{} expects a label like L0101 or .5", mnemonic),
+            )
+            .throw(),
+        };
+        let instr = match kind {
+            WsCommandKind::PushStack => Instruction::PushStack(number()?),
+            WsCommandKind::DuplicateStack => Instruction::DuplicateStack,
+            WsCommandKind::CopyNthStack => Instruction::CopyNthStack(number()?),
+            WsCommandKind::SwapStack => Instruction::SwapStack,
+            WsCommandKind::DiscardStack => Instruction::DiscardStack,
+            WsCommandKind::SlideNStack => Instruction::SlideNStack(number()?),
+            WsCommandKind::Add => Instruction::Add,
+            WsCommandKind::Subtract => Instruction::Subtract,
+            WsCommandKind::Multiply => Instruction::Multiply,
+            WsCommandKind::IntegerDivision => Instruction::IntegerDivision,
+            WsCommandKind::Modulo => Instruction::Modulo,
+            WsCommandKind::StoreHeap => Instruction::StoreHeap,
+            WsCommandKind::RetrieveHeap => Instruction::RetrieveHeap,
+            WsCommandKind::Mark => Instruction::Mark(label()?),
+            WsCommandKind::Call => Instruction::Call(label()?),
+            WsCommandKind::Jump => Instruction::Jump(label()?),
+            WsCommandKind::JumpZero => Instruction::JumpZero(label()?),
+            WsCommandKind::JumpNegative => Instruction::JumpNegative(label()?),
+            WsCommandKind::Return => Instruction::Return,
+            WsCommandKind::Exit => Instruction::Exit,
+            WsCommandKind::OutCharacter => Instruction::OutCharacter,
+            WsCommandKind::OutInteger => Instruction::OutInteger,
+            WsCommandKind::ReadCharacter => Instruction::ReadCharacter,
+            WsCommandKind::ReadInteger => Instruction::ReadInteger,
+            WsCommandKind::JumpDynamic => Instruction::JumpDynamic,
+            WsCommandKind::Syscall => Instruction::Syscall(number()?),
+        };
+        instructions.push(instr);
+    }
+
+    Ok(instructions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{assemble, disassemble, disassemble_numeric, encode, from_stl, to_stl};
+    use crate::parser::SourceType;
+    use crate::Program;
+    use std::fs;
+
+    #[test]
+    fn encode_round_trip() {
+        let source = fs::read("resources/ws/fibonacci.ws").unwrap();
+        let program = Program::from_bytes(&source, SourceType::Whitespace).unwrap();
+        let encoded = encode(program.instructions());
+
+        assert_eq!(
+            Program::from_bytes(&encoded, SourceType::Whitespace)
+                .unwrap()
+                .instructions(),
+            program.instructions()
+        );
+        assert_eq!(from_stl(&to_stl(&encoded)), encoded);
+    }
+
+    #[test]
+    fn assemble_round_trip() {
+        let source = fs::read("resources/ws/interpret_flow.ws").unwrap();
+        let program = Program::from_bytes(&source, SourceType::Whitespace).unwrap();
+        let assembly = disassemble(program.instructions());
+
+        assert_eq!(assemble(&assembly).unwrap(), program.instructions());
+        assert!(assemble("push").is_err());
+        assert!(assemble("push 1 2").is_err());
+        assert!(assemble("jmp 0101").is_err());
+        assert!(assemble("add 1").is_err());
+        assert!(assemble("nop").is_err());
+    }
+
+    #[test]
+    fn numeric_labels() {
+        let instructions = assemble("label L1010\njz .10\njmp L0101\nend").unwrap();
+
+        assert_eq!(
+            disassemble_numeric(&instructions),
+            "label .10\njz .10\njmp L0101\nend\n"
+        );
+        assert_eq!(
+            assemble(&disassemble_numeric(&instructions)).unwrap(),
+            instructions
+        );
+        assert!(assemble("jmp .x").is_err());
+    }
+}
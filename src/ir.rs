@@ -1,17 +1,42 @@
-use std::rc::Rc;
+use std::sync::Arc;
 
 #[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "cache", derive(serde::Serialize, serde::Deserialize))]
 pub struct Number {
-    pub value: i32,
+    pub value: i64,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Hash)]
+#[cfg_attr(feature = "cache", derive(serde::Serialize, serde::Deserialize))]
 pub struct Label {
-    pub value: Rc<str>,
+    pub value: Arc<str>,
     pub index: usize,
 }
 
+impl Label {
+    /// Returns the label as a bit string, reading a space as `0` and a tab as `1`, e.g. `1010`
+    pub fn bits(&self) -> String {
+        self.value
+            .chars()
+            .map(|c| if c == ' ' { '0' } else { '1' })
+            .collect()
+    }
+
+    /// Returns the label interpreted as a binary number, `None` if it does not fit into a `u64`.
+    /// Leading zeros are significant for labels but not for numbers, so different labels can
+    /// share the same number.
+    pub fn number(&self) -> Option<u64> {
+        let bits = self.bits();
+        if bits.is_empty() {
+            return Some(0);
+        }
+
+        u64::from_str_radix(&bits, 2).ok()
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "cache", derive(serde::Serialize, serde::Deserialize))]
 pub enum Instruction {
     PushStack(Number),
     DuplicateStack,
@@ -31,6 +56,8 @@ pub enum Instruction {
     Jump(Label),
     JumpZero(Label),
     JumpNegative(Label),
+    JumpDynamic,
+    Syscall(Number),
     Return,
     Exit,
     OutCharacter,
@@ -38,3 +65,26 @@ pub enum Instruction {
     ReadCharacter,
     ReadInteger,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Label;
+
+    #[test]
+    fn label_number() {
+        let label = Label {
+            value: " \t \t \t ".into(),
+            index: 0,
+        };
+
+        assert_eq!(label.bits(), "0101010");
+        assert_eq!(label.number(), Some(42));
+
+        let label = Label {
+            value: "\t".repeat(65).into(),
+            index: 0,
+        };
+
+        assert_eq!(label.number(), None);
+    }
+}
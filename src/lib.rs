@@ -1,9 +1,27 @@
+pub mod asm;
 pub mod ir;
+pub mod lint;
+mod optimize;
+pub mod output;
 pub mod parser;
+pub mod program;
+pub mod replay;
+pub mod trace;
+#[cfg(all(feature = "tui", not(target_arch = "wasm32")))]
+pub mod tui;
 pub mod vm;
 pub mod ws;
 
 pub use ir::Instruction;
-pub use parser::{Instr, ParseError, Parser, SourceType};
-pub use vm::{Vm, VmConfig, VmError};
-pub use ws::{WsInstruction, WsParser};
+pub use lint::{Diagnostic, Severity, Suggestion, SuggestionKind};
+pub use output::{OutputError, OutputSink};
+pub use parser::{Instr, ParseError, Parser, SourceType, Token};
+pub use program::{Program, ProgramSize};
+pub use replay::Replay;
+pub use trace::{compare_traces, sparkline, TraceDivergence, TraceEvent};
+pub use vm::{
+    execute, Buffering, ChainedInput, CharInput, CompatProfile, CostModel, DivisionRounding,
+    ExecutionResult, HeapDelta, HeapDumpFilter, InputQueue, IntInputMode, OutputEncoding,
+    OutputErrorPolicy, RunSliceOutcome, Vm, VmConfig, VmError, WordSize,
+};
+pub use ws::{WsCommandKind, WsInstruction, WsParser};
@@ -0,0 +1,528 @@
+use crate::vm::{VmError, VmErrorKind};
+use crate::{Instruction, WsCommandKind};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::sync::Arc;
+
+/// How serious an issue found by the static analysis is
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Severity {
+    /// The program fails or misbehaves when running into the issue
+    Error,
+    /// The program runs fine, but the issue may be a mistake or a portability problem
+    Warning,
+}
+
+/// A single issue found while statically analysing a program
+#[derive(Debug)]
+pub struct Diagnostic {
+    /// Index of the offending instruction, `None` if the issue concerns the whole program
+    pub instruction_index: Option<usize>,
+    pub severity: Severity,
+    pub error: VmError,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.severity == Severity::Warning {
+            write!(f, "warning: ")?;
+        }
+        match self.instruction_index {
+            Some(index) => write!(f, "instruction {}: {}", index, self.error.msg),
+            None => write!(f, "{}", self.error.msg),
+        }
+    }
+}
+
+/// A local pattern in the instruction stream that can be written shorter
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SuggestionKind {
+    /// `push n; push n` can be `push n; dup`
+    DuplicatePush,
+    /// `push 0; add`, `push 0; sub`, `push 1; mul` and `push 1; div` have no effect
+    NoOpArithmetic,
+    /// `swap; swap` has no effect
+    DoubleSwap,
+    /// `dup; drop` has no effect
+    DuplicateDiscard,
+}
+
+impl Display for SuggestionKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let msg = match self {
+            SuggestionKind::DuplicatePush => "pushing the same number twice, use dup instead",
+            SuggestionKind::NoOpArithmetic => "arithmetic with the identity element, remove both",
+            SuggestionKind::DoubleSwap => "swapping twice, remove both",
+            SuggestionKind::DuplicateDiscard => "duplicating and discarding, remove both",
+        };
+        write!(f, "{}", msg)
+    }
+}
+
+/// A size optimization found in a program
+#[derive(Debug, PartialEq)]
+pub struct Suggestion {
+    /// Index of the first instruction of the pattern
+    pub instruction_index: usize,
+    pub kind: SuggestionKind,
+}
+
+/// Maps every marked label to the index of its `Mark` instruction. If a label is marked more than
+/// once, the last mark wins.
+pub(crate) fn label_map(instructions: &[Instruction]) -> HashMap<Arc<str>, usize> {
+    let mut labels = HashMap::new();
+    for (i, instr) in instructions.iter().enumerate() {
+        if let Instruction::Mark(label) = instr {
+            labels.insert(label.value.clone(), i);
+        }
+    }
+
+    labels
+}
+
+/// Whether the instruction marks a label or continues anywhere but at the next instruction.
+/// `Exit` does not count, it just stops.
+pub(crate) fn changes_flow(instruction: &Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::Mark(_)
+            | Instruction::Call(_)
+            | Instruction::Jump(_)
+            | Instruction::JumpZero(_)
+            | Instruction::JumpNegative(_)
+            | Instruction::JumpDynamic
+            | Instruction::Return
+    )
+}
+
+/// Points every label at the index of its `Mark` instruction and returns the label map, see
+/// `label_map`. Labels that are never marked keep their index.
+pub(crate) fn resolve_labels(instructions: &mut [Instruction]) -> HashMap<Arc<str>, usize> {
+    let labels = label_map(instructions);
+
+    for instr in instructions.iter_mut() {
+        match instr {
+            Instruction::Mark(label)
+            | Instruction::Call(label)
+            | Instruction::Jump(label)
+            | Instruction::JumpZero(label)
+            | Instruction::JumpNegative(label) => {
+                if let Some(index) = labels.get(&label.value) {
+                    label.index = *index;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    labels
+}
+
+/// Runs all static checks over the instructions, collecting every issue found
+pub(crate) fn lint(instructions: &[Instruction]) -> Vec<Diagnostic> {
+    let mut diagnostics = label_diagnostics(instructions);
+    diagnostics.extend(stack_diagnostics(instructions));
+    if !exit_reachable(instructions) {
+        diagnostics.push(Diagnostic {
+            instruction_index: None,
+            severity: Severity::Error,
+            error: VmErrorKind::UnreachableExit.error(),
+        });
+    }
+
+    diagnostics
+}
+
+/// Warns about carriage returns in the source. They are comments in whitespace, but files edited
+/// on Windows get them before every line feed and some stricter interpreters reject them.
+pub(crate) fn encoding_diagnostics(source: &[u8]) -> Vec<Diagnostic> {
+    carriage_return_diagnostics(carriage_returns(source))
+}
+
+/// Returns the positions of the carriage returns in the source
+pub(crate) fn carriage_returns(source: &[u8]) -> Vec<usize> {
+    source
+        .iter()
+        .enumerate()
+        .filter(|(_, byte)| **byte == b'\r')
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Warns about the carriage returns at the positions, if there are any
+pub(crate) fn carriage_return_diagnostics(positions: Vec<usize>) -> Vec<Diagnostic> {
+    if positions.is_empty() {
+        return vec![];
+    }
+
+    vec![Diagnostic {
+        instruction_index: None,
+        severity: Severity::Warning,
+        error: VmErrorKind::CarriageReturns(positions).error(),
+    }]
+}
+
+/// Checks for labels that are marked more than once or never marked at all
+pub(crate) fn label_diagnostics(instructions: &[Instruction]) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    let mut marked = HashSet::new();
+    for (i, instr) in instructions.iter().enumerate() {
+        if let Instruction::Mark(label) = instr {
+            if !marked.insert(label.value.clone()) {
+                diagnostics.push(Diagnostic {
+                    instruction_index: Some(i),
+                    severity: Severity::Error,
+                    error: VmErrorKind::DuplicateLabel(instr.clone()).error(),
+                });
+            }
+        }
+    }
+
+    for (i, instr) in instructions.iter().enumerate() {
+        match instr {
+            Instruction::Call(label)
+            | Instruction::Jump(label)
+            | Instruction::JumpZero(label)
+            | Instruction::JumpNegative(label)
+                if !marked.contains(&label.value) =>
+            {
+                diagnostics.push(Diagnostic {
+                    instruction_index: Some(i),
+                    severity: Severity::Error,
+                    error: VmErrorKind::UndefinedLabel(instr.clone()).error(),
+                });
+            }
+            _ => {}
+        }
+    }
+
+    diagnostics
+}
+
+/// Returns the command encoding the instruction
+pub(crate) fn command_kind(instruction: &Instruction) -> WsCommandKind {
+    match instruction {
+        Instruction::PushStack(_) => WsCommandKind::PushStack,
+        Instruction::DuplicateStack => WsCommandKind::DuplicateStack,
+        Instruction::CopyNthStack(_) => WsCommandKind::CopyNthStack,
+        Instruction::SwapStack => WsCommandKind::SwapStack,
+        Instruction::DiscardStack => WsCommandKind::DiscardStack,
+        Instruction::SlideNStack(_) => WsCommandKind::SlideNStack,
+        Instruction::Add => WsCommandKind::Add,
+        Instruction::Subtract => WsCommandKind::Subtract,
+        Instruction::Multiply => WsCommandKind::Multiply,
+        Instruction::IntegerDivision => WsCommandKind::IntegerDivision,
+        Instruction::Modulo => WsCommandKind::Modulo,
+        Instruction::StoreHeap => WsCommandKind::StoreHeap,
+        Instruction::RetrieveHeap => WsCommandKind::RetrieveHeap,
+        Instruction::Mark(_) => WsCommandKind::Mark,
+        Instruction::Call(_) => WsCommandKind::Call,
+        Instruction::Jump(_) => WsCommandKind::Jump,
+        Instruction::JumpZero(_) => WsCommandKind::JumpZero,
+        Instruction::JumpNegative(_) => WsCommandKind::JumpNegative,
+        Instruction::Return => WsCommandKind::Return,
+        Instruction::Exit => WsCommandKind::Exit,
+        Instruction::OutCharacter => WsCommandKind::OutCharacter,
+        Instruction::OutInteger => WsCommandKind::OutInteger,
+        Instruction::ReadCharacter => WsCommandKind::ReadCharacter,
+        Instruction::ReadInteger => WsCommandKind::ReadInteger,
+        Instruction::JumpDynamic => WsCommandKind::JumpDynamic,
+        Instruction::Syscall(_) => WsCommandKind::Syscall,
+    }
+}
+
+/// Simulates the stack depth along straight-line code from the start of the program and reports
+/// every instruction that would underflow the stack. The depth is only known until the first
+/// `Mark`, since labels can be jumped to from anywhere, and is forgotten after every `Call`, since
+/// the subroutine may leave any number of items, just like a `Syscall`. Code after an
+/// unconditional jump is only reached through a label, so nothing is reported that depends on the
+/// depth at a join point.
+pub(crate) fn stack_diagnostics(instructions: &[Instruction]) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    let mut depth = Some(0);
+    for (i, instr) in instructions.iter().enumerate() {
+        match instr {
+            Instruction::Mark(_)
+            | Instruction::Call(_)
+            | Instruction::Jump(_)
+            | Instruction::JumpDynamic
+            | Instruction::Syscall(_)
+            | Instruction::Return
+            | Instruction::Exit => depth = None,
+            _ => {
+                if let Some(current) = depth {
+                    let (consumed, produced) = command_kind(instr).stack_effect();
+                    if current < consumed {
+                        diagnostics.push(Diagnostic {
+                            instruction_index: Some(i),
+                            severity: Severity::Error,
+                            error: VmErrorKind::StackUnderflow(instr.clone()).error(),
+                        });
+                        // the depth after an underflow is unknown, so later instructions are not
+                        // reported as a consequence of the same bug
+                        depth = None;
+                    } else {
+                        depth = Some(current - consumed + produced);
+                    }
+                }
+            }
+        }
+    }
+
+    diagnostics
+}
+
+/// Matches the pair of instructions starting at the index against the peephole patterns
+pub(crate) fn peephole(instructions: &[Instruction], index: usize) -> Option<SuggestionKind> {
+    match (instructions.get(index)?, instructions.get(index + 1)?) {
+        (Instruction::PushStack(first), Instruction::PushStack(second)) if first == second => {
+            Some(SuggestionKind::DuplicatePush)
+        }
+        (Instruction::PushStack(num), Instruction::Add | Instruction::Subtract)
+            if num.value == 0 =>
+        {
+            Some(SuggestionKind::NoOpArithmetic)
+        }
+        (Instruction::PushStack(num), Instruction::Multiply | Instruction::IntegerDivision)
+            if num.value == 1 =>
+        {
+            Some(SuggestionKind::NoOpArithmetic)
+        }
+        (Instruction::SwapStack, Instruction::SwapStack) => Some(SuggestionKind::DoubleSwap),
+        (Instruction::DuplicateStack, Instruction::DiscardStack) => {
+            Some(SuggestionKind::DuplicateDiscard)
+        }
+        _ => None,
+    }
+}
+
+/// Scans the instructions for local patterns that can be written shorter
+pub(crate) fn suggest_optimizations(instructions: &[Instruction]) -> Vec<Suggestion> {
+    (0..instructions.len())
+        .filter_map(|index| {
+            peephole(instructions, index).map(|kind| Suggestion {
+                instruction_index: index,
+                kind,
+            })
+        })
+        .collect()
+}
+
+/// Walks all control flow paths from the first instruction and checks whether any of them reaches
+/// an `Exit`. A `Return` is assumed to continue after one of the calls, which are already followed
+/// by walking past every `Call`. A `JumpDynamic` can continue anywhere, so an `Exit` anywhere in
+/// the program counts as reachable from it.
+fn exit_reachable(instructions: &[Instruction]) -> bool {
+    let labels = label_map(instructions);
+    let mut visited = vec![false; instructions.len()];
+    let mut pending = vec![0];
+    while let Some(index) = pending.pop() {
+        if index >= instructions.len() || visited[index] {
+            continue;
+        }
+        visited[index] = true;
+        match &instructions[index] {
+            Instruction::Exit => return true,
+            Instruction::JumpDynamic => {
+                return instructions
+                    .iter()
+                    .any(|instr| matches!(instr, Instruction::Exit))
+            }
+            Instruction::Return => {}
+            Instruction::Jump(label) => {
+                if let Some(target) = labels.get(&label.value) {
+                    pending.push(*target);
+                }
+            }
+            Instruction::Call(label)
+            | Instruction::JumpZero(label)
+            | Instruction::JumpNegative(label) => {
+                if let Some(target) = labels.get(&label.value) {
+                    pending.push(*target);
+                }
+                pending.push(index + 1);
+            }
+            _ => pending.push(index + 1),
+        }
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        encoding_diagnostics, stack_diagnostics, suggest_optimizations, Severity, Suggestion,
+        SuggestionKind,
+    };
+    use crate::ir::Label;
+    use crate::ir::Number;
+    use crate::parser::SourceType;
+    use crate::vm::{VmConfig, VmErrorKind};
+    use crate::{Instruction, Vm, VmError};
+
+    fn push(value: i64) -> Instruction {
+        Instruction::PushStack(Number { value })
+    }
+
+    fn suggestions(instructions: &[Instruction]) -> Vec<(usize, SuggestionKind)> {
+        suggest_optimizations(instructions)
+            .into_iter()
+            .map(
+                |Suggestion {
+                     instruction_index,
+                     kind,
+                 }| (instruction_index, kind),
+            )
+            .collect()
+    }
+
+    #[test]
+    fn lint_undefined_labels() -> Result<(), VmError> {
+        let config = VmConfig::default_no_heap_suppressed(
+            "resources/ws/lint_undefined_labels.ws",
+            SourceType::Whitespace,
+        );
+        let diagnostics = Vm::lint(config)?;
+
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].instruction_index, Some(1));
+        assert_eq!(diagnostics[1].instruction_index, Some(2));
+        assert!(diagnostics
+            .iter()
+            .all(|diagnostic| matches!(diagnostic.error.kind, VmErrorKind::UndefinedLabel(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn suggest_duplicate_push() {
+        let instructions = [push(3), push(3), push(4), Instruction::Exit];
+
+        assert_eq!(
+            suggestions(&instructions),
+            vec![(0, SuggestionKind::DuplicatePush)]
+        );
+    }
+
+    #[test]
+    fn suggest_no_op_arithmetic() {
+        let instructions = [
+            push(0),
+            Instruction::Add,
+            push(1),
+            Instruction::IntegerDivision,
+            push(1),
+            Instruction::Add,
+            Instruction::Exit,
+        ];
+
+        assert_eq!(
+            suggestions(&instructions),
+            vec![
+                (0, SuggestionKind::NoOpArithmetic),
+                (2, SuggestionKind::NoOpArithmetic)
+            ]
+        );
+    }
+
+    #[test]
+    fn suggest_double_swap() {
+        let instructions = [
+            Instruction::SwapStack,
+            Instruction::SwapStack,
+            Instruction::SwapStack,
+            Instruction::Exit,
+        ];
+
+        assert_eq!(
+            suggestions(&instructions),
+            vec![
+                (0, SuggestionKind::DoubleSwap),
+                (1, SuggestionKind::DoubleSwap)
+            ]
+        );
+    }
+
+    #[test]
+    fn suggest_duplicate_discard() {
+        let instructions = [
+            Instruction::DuplicateStack,
+            Instruction::DiscardStack,
+            Instruction::DiscardStack,
+            Instruction::Exit,
+        ];
+
+        assert_eq!(
+            suggestions(&instructions),
+            vec![(0, SuggestionKind::DuplicateDiscard)]
+        );
+    }
+
+    #[test]
+    fn lint_carriage_returns() -> Result<(), VmError> {
+        let config =
+            VmConfig::default_no_heap_suppressed("resources/ws/crlf.ws", SourceType::Whitespace);
+        let diagnostics = Vm::lint(config)?;
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+        match &diagnostics[0].error.kind {
+            VmErrorKind::CarriageReturns(positions) => {
+                assert_eq!(positions.len(), 22);
+                assert_eq!(positions[..3], [4, 6, 18]);
+            }
+            kind => panic!("unexpected diagnostic {:?}", kind),
+        }
+        assert!(diagnostics[0].to_string().starts_with("warning: "));
+        assert!(encoding_diagnostics(b"  \t\n").is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn lint_stack_underflow() {
+        let instructions = [
+            push(1),
+            Instruction::OutInteger,
+            Instruction::DiscardStack,
+            Instruction::Add,
+            Instruction::Exit,
+        ];
+        let diagnostics = stack_diagnostics(&instructions);
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].instruction_index, Some(2));
+        assert!(matches!(
+            diagnostics[0].error.kind,
+            VmErrorKind::StackUnderflow(Instruction::DiscardStack)
+        ));
+
+        let instructions = [
+            push(1),
+            push(2),
+            Instruction::Add,
+            Instruction::DuplicateStack,
+            Instruction::OutInteger,
+            Instruction::DiscardStack,
+            Instruction::Exit,
+        ];
+
+        assert!(stack_diagnostics(&instructions).is_empty());
+
+        // the depth at a label depends on where it is reached from
+        let label = Label {
+            value: " ".into(),
+            index: 0,
+        };
+        let instructions = [
+            push(1),
+            Instruction::Mark(label.clone()),
+            Instruction::DiscardStack,
+            push(0),
+            Instruction::Jump(label),
+        ];
+
+        assert!(stack_diagnostics(&instructions).is_empty());
+    }
+}
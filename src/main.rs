@@ -1,6 +1,18 @@
 use clap::{App, Arg, ArgMatches};
-use spacey::{parser::SourceType, Vm, VmConfig, VmError};
-use std::{str::FromStr, time::Instant};
+use spacey::{
+    asm, compare_traces, parser::SourceType, sparkline, vm::DEFAULT_HEAP_SIZE, CompatProfile,
+    HeapDumpFilter, Program, Replay, Severity, TraceEvent, Vm, VmConfig, VmError,
+};
+use std::{
+    env,
+    fmt::Display,
+    fs::{self, File},
+    io::{stdin, stdout, BufRead, Cursor, Read, Write},
+    process,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
 const ARG_FILE: &str = "file";
 const ARG_HEAP_SIZE: &str = "heap-size";
@@ -8,20 +20,76 @@ const ARG_RAW: &str = "raw";
 const ARG_DEBUG: &str = "debug";
 const ARG_DEBUG_HEAP: &str = "debug-file";
 const ARG_QUIET: &str = "quiet";
+const ARG_NO_BANNER: &str = "no-banner";
 const ARG_SOURCE_TYPE: &str = "source-type";
+const ARG_INPUT: &str = "input";
+const ARG_LIST_LABELS: &str = "list-labels";
+const ARG_STRICT: &str = "strict";
+const ARG_GZIP: &str = "gzip";
+const ARG_OPTIMIZE: &str = "optimize";
+const ARG_SEED: &str = "seed";
+const ARG_STATS_JSON: &str = "stats-json";
+const ARG_CHECK: &str = "check";
+const ARG_SIZE: &str = "size";
+const ARG_COUNT_ONLY: &str = "count-only";
+const ARG_HEAP_FILTER: &str = "heap-filter";
+const ARG_HEAP_RANGE: &str = "heap-range";
+const ARG_HEAP_COLLAPSE: &str = "heap-collapse";
+const ARG_COMPAT: &str = "compat";
+const ARG_EXTENSIONS: &str = "extensions";
+const ARG_TRACE_STACK_DEPTH: &str = "trace-stack-depth";
+const ARG_DUMP_STATE_ON_ERROR: &str = "dump-state-on-error";
+const CMD_DEBUG: &str = "debug";
+const CMD_CONVERT: &str = "convert";
+const CMD_DIFF: &str = "diff";
+const CMD_RECORD: &str = "record";
+const CMD_REPLAY: &str = "replay";
+#[cfg(feature = "tui")]
+const CMD_TUI: &str = "tui";
+const ARG_FIRST: &str = "first";
+const ARG_SECOND: &str = "second";
+const ARG_FROM: &str = "from";
+const ARG_TO: &str = "to";
+const ARG_IN: &str = "in";
+const ARG_OUT: &str = "out";
+const FORMATS: [&str; 3] = ["whitespace", "stl", "asm"];
+const ENV_HEAP_SIZE: &str = "SPACEY_HEAP_SIZE";
+const CONFIG_FILE: &str = "spacey.toml";
+
+/// Returns the version with the build information printed by `--version`
+fn long_version() -> String {
+    let features = env!("SPACEY_FEATURES");
+
+    format!(
+        "{}\ncommit: {}\nprofile: {}\nfeatures: {}",
+        env!("CARGO_PKG_VERSION"),
+        env!("SPACEY_GIT_COMMIT"),
+        env!("SPACEY_BUILD_PROFILE"),
+        if features.is_empty() {
+            "none"
+        } else {
+            features
+        }
+    )
+}
 
 fn args() -> ArgMatches {
-    App::new("spacey")
+    let long_version = long_version();
+    let app = App::new("spacey")
         .about("a lightweight whitespace interpreter")
-        .version("1.2.0")
+        .version(env!("CARGO_PKG_VERSION"))
+        .long_version(long_version.as_str())
         .author("Cedric Schwyter <cedricschwyter@bluewin.ch>")
+        .subcommand_negates_reqs(true)
         .arg(
             Arg::new(ARG_FILE)
                 .short('f')
                 .long(ARG_FILE)
                 .takes_value(true)
                 .required(true)
-                .help("source file to interpret"),
+                .help(
+                    "source file to interpret, - for stdin or an http(s) URL with the net feature",
+                ),
         )
         .arg(
             Arg::new(ARG_SOURCE_TYPE)
@@ -37,7 +105,10 @@ fn args() -> ArgMatches {
                 .long(ARG_HEAP_SIZE)
                 .takes_value(true)
                 .required(false)
-                .help("the size of the heap address space (each heap address stores one i32)"),
+                .help(
+                    "the size of the heap address space (each heap address stores one i32), \
+                    defaults to $SPACEY_HEAP_SIZE, then heap_size in ./spacey.toml",
+                ),
         )
         .arg(
             Arg::new(ARG_RAW)
@@ -63,6 +134,28 @@ fn args() -> ArgMatches {
                 .required(false)
                 .help("prints a heap dump after each executed instruction"),
         )
+        .arg(
+            Arg::new(ARG_HEAP_FILTER)
+                .long(ARG_HEAP_FILTER)
+                .takes_value(true)
+                .required(false)
+                .possible_values(["non-zero", "written", "all"])
+                .help("which heap cells the heap dump includes, defaults to non-zero"),
+        )
+        .arg(
+            Arg::new(ARG_HEAP_RANGE)
+                .long(ARG_HEAP_RANGE)
+                .takes_value(true)
+                .required(false)
+                .help("restricts the heap dump to the addresses <start>..<end>"),
+        )
+        .arg(
+            Arg::new(ARG_HEAP_COLLAPSE)
+                .long(ARG_HEAP_COLLAPSE)
+                .takes_value(true)
+                .required(false)
+                .help("collapses runs of at least this many zero cells in the heap dump, 0 never"),
+        )
         .arg(
             Arg::new(ARG_QUIET)
                 .short('q')
@@ -71,28 +164,666 @@ fn args() -> ArgMatches {
                 .takes_value(false)
                 .help("suppresses all output other than what the whitespace program is producing"),
         )
-        .get_matches()
+        .arg(
+            Arg::new(ARG_NO_BANNER)
+                .long(ARG_NO_BANNER)
+                .required(false)
+                .takes_value(false)
+                .help("suppresses the initialization and timing messages, but not warnings"),
+        )
+        .arg(
+            Arg::new(ARG_LIST_LABELS)
+                .long(ARG_LIST_LABELS)
+                .required(false)
+                .takes_value(false)
+                .help("prints every label with the index it resolves to and exits"),
+        )
+        .args(run_args())
+        .arg(
+            Arg::new(ARG_OPTIMIZE)
+                .short('O')
+                .long(ARG_OPTIMIZE)
+                .required(false)
+                .takes_value(false)
+                .help("removes instructions without effect before running the program"),
+        )
+        .arg(
+            Arg::new(ARG_CHECK)
+                .long(ARG_CHECK)
+                .required(false)
+                .takes_value(false)
+                .help("checks labels and termination without running, exits non-zero on errors"),
+        )
+        .arg(
+            Arg::new(ARG_SIZE)
+                .long(ARG_SIZE)
+                .required(false)
+                .takes_value(false)
+                .help("prints the size of the program without running, as JSON with --stats-json"),
+        )
+        .arg(
+            Arg::new(ARG_COUNT_ONLY)
+                .long(ARG_COUNT_ONLY)
+                .required(false)
+                .takes_value(false)
+                .help(
+                    "prints how often each command appears in the program without running, as \
+                    JSON with --stats-json",
+                ),
+        )
+        .arg(
+            Arg::new(ARG_STATS_JSON)
+                .long(ARG_STATS_JSON)
+                .takes_value(true)
+                .min_values(0)
+                .require_equals(true)
+                .required(false)
+                .help("prints timings and the instruction count as JSON, to stdout or =<file>"),
+        )
+        .arg(
+            Arg::new(ARG_TRACE_STACK_DEPTH)
+                .long(ARG_TRACE_STACK_DEPTH)
+                .required(false)
+                .takes_value(false)
+                .help("prints a sparkline of the stack depth over time and its peak to stderr"),
+        )
+        .arg(
+            Arg::new(ARG_DUMP_STATE_ON_ERROR)
+                .long(ARG_DUMP_STATE_ON_ERROR)
+                .required(false)
+                .takes_value(false)
+                .help(
+                    "prints the stack, call stack, non-zero heap and faulting instruction to \
+                    stderr when the program fails",
+                ),
+        )
+        .arg(
+            Arg::new(ARG_GZIP)
+                .short('z')
+                .long(ARG_GZIP)
+                .required(false)
+                .takes_value(false)
+                .help("decompresses the gzip-compressed source file (implied by a .gz extension)"),
+        )
+        .subcommand(
+            App::new(CMD_DEBUG)
+                .about("steps through a program interactively")
+                .arg(
+                    Arg::new(ARG_FILE)
+                        .required(true)
+                        .help("source file to debug"),
+                )
+                .arg(
+                    Arg::new(ARG_SOURCE_TYPE)
+                        .short('t')
+                        .long(ARG_SOURCE_TYPE)
+                        .takes_value(true)
+                        .default_value("whitespace")
+                        .help("type of source file"),
+                )
+                .arg(
+                    Arg::new(ARG_HEAP_SIZE)
+                        .short('s')
+                        .long(ARG_HEAP_SIZE)
+                        .takes_value(true)
+                        .help("the size of the heap address space, defaults like for running"),
+                )
+                .arg(
+                    Arg::new(ARG_INPUT)
+                        .short('i')
+                        .long(ARG_INPUT)
+                        .takes_value(true)
+                        .help("file the program reads its input from"),
+                ),
+        )
+        .subcommand(
+            App::new(CMD_CONVERT)
+                .about("converts a program between whitespace, STL and assembly")
+                .arg(
+                    Arg::new(ARG_FROM)
+                        .long(ARG_FROM)
+                        .takes_value(true)
+                        .required(true)
+                        .possible_values(FORMATS)
+                        .help("format of the input file"),
+                )
+                .arg(
+                    Arg::new(ARG_TO)
+                        .long(ARG_TO)
+                        .takes_value(true)
+                        .required(true)
+                        .possible_values(FORMATS)
+                        .help("format of the output file"),
+                )
+                .arg(
+                    Arg::new(ARG_IN)
+                        .long(ARG_IN)
+                        .takes_value(true)
+                        .required(true)
+                        .help("file to convert"),
+                )
+                .arg(
+                    Arg::new(ARG_OUT)
+                        .long(ARG_OUT)
+                        .takes_value(true)
+                        .help("file to write the converted program to, defaults to stdout"),
+                )
+                .arg(
+                    Arg::new(ARG_EXTENSIONS)
+                        .long(ARG_EXTENSIONS)
+                        .takes_value(false)
+                        .help("parses instructions that are not part of the language"),
+                ),
+        )
+        .subcommand(
+            App::new(CMD_DIFF)
+                .about("runs two programs and reports where their execution traces diverge")
+                .arg(
+                    Arg::new(ARG_FIRST)
+                        .required(true)
+                        .help("source file of the first program"),
+                )
+                .arg(
+                    Arg::new(ARG_SECOND)
+                        .required(true)
+                        .help("source file of the second program"),
+                )
+                .arg(
+                    Arg::new(ARG_OPTIMIZE)
+                        .short('O')
+                        .long(ARG_OPTIMIZE)
+                        .takes_value(false)
+                        .help("optimizes the second program before running it"),
+                )
+                .arg(
+                    Arg::new(ARG_HEAP_SIZE)
+                        .short('s')
+                        .long(ARG_HEAP_SIZE)
+                        .takes_value(true)
+                        .help("the size of the heap address space, defaults like for running"),
+                )
+                .arg(
+                    Arg::new(ARG_INPUT)
+                        .short('i')
+                        .long(ARG_INPUT)
+                        .takes_value(true)
+                        .help("file both programs read their input from"),
+                ),
+        )
+        .subcommand(
+            App::new(CMD_RECORD)
+                .about("runs a whitespace program and records the run to a replay file")
+                .arg(Arg::new(ARG_FILE).required(true).help("source file to run"))
+                .arg(
+                    Arg::new(ARG_OUT)
+                        .short('o')
+                        .long(ARG_OUT)
+                        .takes_value(true)
+                        .required(true)
+                        .help("replay file to write, e.g. bug.spacey"),
+                )
+                .arg(
+                    Arg::new(ARG_HEAP_SIZE)
+                        .short('s')
+                        .long(ARG_HEAP_SIZE)
+                        .takes_value(true)
+                        .help("the size of the heap address space, defaults like for running"),
+                )
+                .arg(
+                    Arg::new(ARG_INPUT)
+                        .short('i')
+                        .long(ARG_INPUT)
+                        .takes_value(true)
+                        .help("file the program reads its input from, defaults to stdin"),
+                )
+                .args(run_args()),
+        )
+        .subcommand(
+            App::new(CMD_REPLAY)
+                .about("reproduces a run recorded with record")
+                .arg(Arg::new(ARG_FILE).required(true).help("replay file to run")),
+        );
+    #[cfg(feature = "tui")]
+    let app = app.subcommand(
+        App::new(CMD_TUI)
+            .about("steps through a program in a terminal user interface")
+            .arg(
+                Arg::new(ARG_FILE)
+                    .required(true)
+                    .help("source file to step through"),
+            )
+            .arg(
+                Arg::new(ARG_HEAP_SIZE)
+                    .short('s')
+                    .long(ARG_HEAP_SIZE)
+                    .takes_value(true)
+                    .help("the size of the heap address space, defaults like for running"),
+            )
+            .arg(
+                Arg::new(ARG_INPUT)
+                    .short('i')
+                    .long(ARG_INPUT)
+                    .takes_value(true)
+                    .help("file the program reads its input from, empty by default"),
+            ),
+    );
+
+    app.get_matches()
+}
+
+/// Resolves the heap size from the command line, the `SPACEY_HEAP_SIZE` environment variable or
+/// `heap_size` in `spacey.toml` in the working directory, in that order
+fn heap_size(args: &ArgMatches) -> usize {
+    if let Some(size) = parse_arg(args, ARG_HEAP_SIZE) {
+        return size;
+    }
+    if let Ok(size) = env::var(ENV_HEAP_SIZE) {
+        return size
+            .parse()
+            .unwrap_or_else(|_| exit_with(&format!("{} must be a number", ENV_HEAP_SIZE)));
+    }
+    if let Ok(content) = fs::read_to_string(CONFIG_FILE) {
+        let table: toml::Table = content
+            .parse()
+            .unwrap_or_else(|err| exit_with(&format!("failed to parse {}: {}", CONFIG_FILE, err)));
+        if let Some(size) = table.get("heap_size") {
+            return size
+                .as_integer()
+                .and_then(|size| usize::try_from(size).ok())
+                .unwrap_or_else(|| {
+                    exit_with(&format!("heap_size in {} must be a number", CONFIG_FILE))
+                });
+        }
+    }
+
+    DEFAULT_HEAP_SIZE
+}
+
+/// Decides which of the messages around the program output are printed
+struct Log {
+    /// Whether the initialization and timing messages are printed
+    banner: bool,
+    /// Whether warnings about the program are printed
+    warnings: bool,
+}
+
+impl Log {
+    /// Prints an initialization or timing message to stdout
+    fn banner(&self, msg: impl Display) {
+        if self.banner {
+            println!("{}", msg);
+        }
+    }
+
+    /// Prints a warning to stderr
+    fn warn(&self, msg: impl Display) {
+        if self.warnings {
+            eprintln!("{}", msg);
+        }
+    }
+}
+
+/// Prints the message to stderr and exits with a failure
+fn exit_with(msg: &str) -> ! {
+    eprintln!("{}", msg);
+    process::exit(1);
+}
+
+/// Returns the arguments deciding how the program runs, shared by running and recording
+fn run_args<'help>() -> [Arg<'help>; 4] {
+    [
+        Arg::new(ARG_STRICT)
+            .long(ARG_STRICT)
+            .required(false)
+            .takes_value(false)
+            .help("fails on arithmetic overflow and on undefined or duplicate labels"),
+        Arg::new(ARG_COMPAT)
+            .long(ARG_COMPAT)
+            .takes_value(true)
+            .required(false)
+            .possible_values(["spacey", "wspace"])
+            .help("matches the behavior of another interpreter, defaults to spacey"),
+        Arg::new(ARG_EXTENSIONS)
+            .long(ARG_EXTENSIONS)
+            .required(false)
+            .takes_value(false)
+            .help("runs programs using instructions that are not part of the language"),
+        Arg::new(ARG_SEED)
+            .long(ARG_SEED)
+            .takes_value(true)
+            .required(false)
+            .help("reads reproducible pseudo-random numbers from the given seed as input"),
+    ]
+}
+
+/// Applies the arguments of `run_args` to the config
+fn apply_run_args(config: &mut VmConfig, args: &ArgMatches) {
+    if let Some(profile) = args.value_of(ARG_COMPAT) {
+        config.set_compat_profile(CompatProfile::from_str(profile).unwrap());
+    }
+    config.set_strict(args.is_present(ARG_STRICT));
+    config.set_extensions(args.is_present(ARG_EXTENSIONS));
+    if let Some(seed) = parse_arg(args, ARG_SEED) {
+        config.set_input_seed(seed);
+    }
+}
+
+/// Parses the value of the argument if it is given, exits with a message if it is malformed
+fn parse_arg<T: FromStr>(args: &ArgMatches, name: &str) -> Option<T> {
+    args.value_of(name).map(|value| {
+        value
+            .parse()
+            .unwrap_or_else(|_| exit_with(&format!("invalid value {} for --{}", value, name)))
+    })
+}
+
+/// Parses the program in the input format and writes it in the output format. Comments are not
+/// preserved.
+fn convert(args: &ArgMatches) -> Result<(), VmError> {
+    let source = fs::read(args.value_of(ARG_IN).unwrap())?;
+    let parse = |source: &[u8]| {
+        if args.is_present(ARG_EXTENSIONS) {
+            return Program::from_bytes_with_extensions(source, SourceType::Whitespace);
+        }
+
+        Program::from_bytes(source, SourceType::Whitespace)
+    };
+    let instructions = match args.value_of(ARG_FROM).unwrap() {
+        "whitespace" => parse(&source)?.instructions().to_vec(),
+        "stl" => parse(&asm::from_stl(&source))?.instructions().to_vec(),
+        _ => asm::assemble(&String::from_utf8_lossy(&source))?,
+    };
+    let converted = match args.value_of(ARG_TO).unwrap() {
+        "whitespace" => asm::encode(&instructions),
+        "stl" => asm::to_stl(&asm::encode(&instructions)),
+        _ => asm::disassemble(&instructions).into_bytes(),
+    };
+    match args.value_of(ARG_OUT) {
+        Some(out) => fs::write(out, converted)?,
+        None => stdout().write_all(&converted)?,
+    }
+
+    Ok(())
+}
+
+/// Runs both programs with the same input and prints the first divergence of their traces and
+/// whether their outputs differ. Exits with a failure if anything differs.
+fn diff(args: &ArgMatches) -> Result<(), VmError> {
+    let input = match args.value_of(ARG_INPUT) {
+        Some(input) => fs::read(input)?,
+        None => vec![],
+    };
+    let run = |file_name, optimize| -> Result<(Vec<TraceEvent>, Vec<u8>), VmError> {
+        let mut config = VmConfig::new(
+            file_name,
+            SourceType::Whitespace,
+            heap_size(args),
+            false,
+            false,
+            false,
+            false,
+        );
+        config.set_optimize(optimize);
+        let mut vm = Vm::new(config)?;
+        vm.set_input(Box::new(Cursor::new(input.clone())));
+        vm.capture_output();
+        let trace = vm.run_traced()?;
+
+        Ok((trace, vm.take_output()))
+    };
+    let (first, first_output) = run(args.value_of(ARG_FIRST).unwrap(), false)?;
+    let (second, second_output) = run(
+        args.value_of(ARG_SECOND).unwrap(),
+        args.is_present(ARG_OPTIMIZE),
+    )?;
+    let describe = |event: Option<TraceEvent>| match event {
+        Some(event) => event.to_string(),
+        None => "end of trace".to_string(),
+    };
+    let divergence = compare_traces(&first, &second);
+    match &divergence {
+        Some(divergence) => println!(
+            "traces diverge at step {}: {} vs {}",
+            divergence.step,
+            describe(divergence.first),
+            describe(divergence.second)
+        ),
+        None => println!("traces are identical ({} steps)", first.len()),
+    }
+    if first_output != second_output {
+        println!("outputs differ");
+    }
+    if divergence.is_some() || first_output != second_output {
+        process::exit(1);
+    }
+
+    Ok(())
+}
+
+/// Passes the input through to the program while keeping a copy of everything read
+struct RecordingInput {
+    input: Box<dyn Read + Send>,
+    recorded: Arc<Mutex<Vec<u8>>>,
+}
+
+impl Read for RecordingInput {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.input.read(buf)?;
+        self.recorded
+            .lock()
+            .unwrap()
+            .extend_from_slice(&buf[..read]);
+
+        Ok(read)
+    }
+}
+
+/// Runs the program and writes its source, configuration and the input it read to a replay file.
+/// The replay file is written even if the run fails, so failing runs can be reproduced.
+fn record(args: &ArgMatches) -> Result<(), VmError> {
+    let file_name = args.value_of(ARG_FILE).unwrap();
+    let mut config = VmConfig::new(
+        file_name,
+        SourceType::Whitespace,
+        heap_size(args),
+        false,
+        false,
+        false,
+        false,
+    );
+    apply_run_args(&mut config, args);
+    let mut replay = Replay::new(fs::read(file_name)?, &config);
+    let recorded = Arc::new(Mutex::new(vec![]));
+    let mut vm = replay.vm()?;
+    // generated input is generated again when replaying, so there is nothing to record
+    if !replay.generates_input() {
+        let input: Box<dyn Read + Send> = match args.value_of(ARG_INPUT) {
+            Some(input) => Box::new(File::open(input)?),
+            None => Box::new(stdin()),
+        };
+        vm.set_input(Box::new(RecordingInput {
+            input,
+            recorded: recorded.clone(),
+        }));
+    }
+    let result = vm.run();
+    replay.input = std::mem::take(&mut *recorded.lock().unwrap());
+    fs::write(args.value_of(ARG_OUT).unwrap(), replay.encode())?;
+
+    result
+}
+
+/// Reproduces a run recorded with `record`, reading the recorded input instead of stdin
+fn replay(args: &ArgMatches) -> Result<(), VmError> {
+    let replay = Replay::decode(&fs::read(args.value_of(ARG_FILE).unwrap())?)?;
+
+    replay.vm()?.run()
+}
+
+/// Steps through the program in a terminal user interface. The terminal cannot be used for input
+/// while the interface is shown, so the input is read from a file or empty.
+#[cfg(feature = "tui")]
+fn tui(args: &ArgMatches) -> Result<(), VmError> {
+    let config = VmConfig::new(
+        args.value_of(ARG_FILE).unwrap(),
+        SourceType::Whitespace,
+        heap_size(args),
+        false,
+        false,
+        false,
+        false,
+    );
+    let mut vm = Vm::new(config)?;
+    let input = match args.value_of(ARG_INPUT) {
+        Some(input) => fs::read(input)?,
+        None => vec![],
+    };
+    vm.set_input(Box::new(Cursor::new(input)));
+    let mut tui = spacey::tui::Tui::new(vm);
+    let mut terminal = ratatui::init();
+    let result = tui.run(&mut terminal);
+    ratatui::restore();
+    result?;
+
+    Ok(())
+}
+
+fn debug(args: &ArgMatches) -> Result<(), VmError> {
+    let file_name = args.value_of(ARG_FILE).unwrap();
+    let source_type = args.value_of(ARG_SOURCE_TYPE).unwrap();
+    let heap_size = heap_size(args);
+    let config = VmConfig::new(
+        file_name,
+        SourceType::from_str(source_type).unwrap(),
+        heap_size,
+        false,
+        false,
+        false,
+        false,
+    );
+    let mut vm = Vm::new(config)?;
+    if let Some(input) = args.value_of(ARG_INPUT) {
+        match File::open(input) {
+            Ok(file) => vm.set_input(Box::new(file)),
+            Err(err) => {
+                eprintln!("failed to open input file {}: {}", input, err);
+                process::exit(1);
+            }
+        }
+    }
+
+    println!("commands: step, continue, stack, heap, break <index>, reset, quit");
+    print_position(&vm);
+    let stdin = stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("(spacey) ");
+        stdout().flush().unwrap();
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            _ => break,
+        };
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next()) {
+            (Some("step" | "s"), _) => match vm.step() {
+                Ok(_) => print_position(&vm),
+                Err(err) => println!("error: {}", err),
+            },
+            (Some("continue" | "c"), _) => match vm.resume() {
+                Ok(Some(index)) => {
+                    println!("hit breakpoint at {}", index);
+                    print_position(&vm);
+                }
+                Ok(None) => println!("program finished"),
+                Err(err) => println!("error: {}", err),
+            },
+            (Some("stack"), _) => println!("{:?}", vm.stack()),
+            (Some("heap"), _) => {
+                for (addr, val) in vm.heap().iter().enumerate() {
+                    if *val != 0 {
+                        println!("{}: {}", addr, val);
+                    }
+                }
+            }
+            (Some("break" | "b"), Some(index)) => match index.parse() {
+                Ok(index) => {
+                    vm.add_breakpoint(index);
+                    println!("breakpoint set at {}", index);
+                }
+                Err(_) => println!("invalid instruction index: {}", index),
+            },
+            (Some("reset"), _) => {
+                vm.reset();
+                print_position(&vm);
+            }
+            (Some("quit" | "q"), _) => break,
+            (None, _) => {}
+            _ => println!("unknown command: {}", line),
+        }
+    }
+
+    Ok(())
+}
+
+fn print_labels(vm: &Vm) {
+    let mut labels = vm
+        .labels()
+        .iter()
+        .map(|(label, index)| (*index, label))
+        .collect::<Vec<_>>();
+    labels.sort();
+    for (index, label) in labels {
+        let name = label
+            .chars()
+            .map(|c| if c == ' ' { 'S' } else { 'T' })
+            .collect::<String>();
+        match vm.instruction_at(index + 1) {
+            Some(next) => println!("{}: {} -> {:?}", index, name, next),
+            None => println!("{}: {} -> end of program", index, name),
+        }
+    }
+}
+
+fn print_position(vm: &Vm) {
+    match (vm.next_instruction(), vm.current_instruction()) {
+        (Some(index), Some(instr)) => println!("{}: {:?}", index, instr),
+        _ => println!("program finished"),
+    }
 }
 
 fn main() -> Result<(), VmError> {
     let args = args();
+    match args.subcommand() {
+        Some((CMD_DEBUG, args)) => return debug(args),
+        Some((CMD_CONVERT, args)) => return convert(args),
+        Some((CMD_DIFF, args)) => return diff(args),
+        Some((CMD_RECORD, args)) => return record(args),
+        Some((CMD_REPLAY, args)) => return replay(args),
+        #[cfg(feature = "tui")]
+        Some((CMD_TUI, args)) => return tui(args),
+        _ => {}
+    }
     let file_name = args.value_of(ARG_FILE).unwrap();
-    let heap_size = match args.value_of(ARG_HEAP_SIZE) {
-        Some(size) => size.parse().unwrap(),
-        None => 524288,
-    };
+    let heap_size = heap_size(&args);
     let raw = args.is_present(ARG_RAW);
     let debug = args.is_present(ARG_DEBUG);
     let debug_heap = args.is_present(ARG_DEBUG_HEAP);
-    let quiet = args.is_present(ARG_QUIET);
+    let list_labels = args.is_present(ARG_LIST_LABELS);
+    let log = Log {
+        banner: !(args.is_present(ARG_QUIET)
+            || args.is_present(ARG_NO_BANNER)
+            || list_labels
+            || args.is_present(ARG_CHECK)
+            || args.is_present(ARG_SIZE)
+            || args.is_present(ARG_COUNT_ONLY)),
+        warnings: !args.is_present(ARG_QUIET),
+    };
     let source_type = args.value_of(ARG_SOURCE_TYPE).unwrap();
-    if !quiet {
-        println!(
-        "initializing, loading and parsing the provided source, creating the virtual machine..."
+    log.banner(
+        "initializing, loading and parsing the provided source, creating the virtual machine...",
     );
-    }
     let start = Instant::now();
-    let config = VmConfig::new(
+    let mut config = VmConfig::new(
         file_name,
         SourceType::from_str(source_type).unwrap(),
         heap_size,
@@ -101,32 +832,154 @@ fn main() -> Result<(), VmError> {
         debug_heap,
         false,
     );
-    let mut vm = Vm::new(config)?;
+    apply_run_args(&mut config, &args);
+    config.set_gzip(args.is_present(ARG_GZIP));
+    config.set_optimize(args.is_present(ARG_OPTIMIZE));
+    if let Some(filter) = args.value_of(ARG_HEAP_FILTER) {
+        config.set_heap_dump_filter(HeapDumpFilter::from_str(filter).unwrap());
+    }
+    if let Some(range) = args.value_of(ARG_HEAP_RANGE) {
+        let bounds = range
+            .split_once("..")
+            .and_then(|(start, end)| Some((start.parse().ok()?, end.parse().ok()?)));
+        match bounds {
+            Some((start, end)) => config.set_heap_dump_range(start, end),
+            None => {
+                eprintln!("invalid heap range {}, expected <start>..<end>", range);
+                process::exit(1);
+            }
+        }
+    }
+    if let Some(threshold) = parse_arg(&args, ARG_HEAP_COLLAPSE) {
+        config.set_heap_dump_collapse(threshold);
+    }
+    // the initial depth is recorded too, the hook only reports changes
+    let depths = Arc::new(Mutex::new(vec![0]));
+    if args.is_present(ARG_TRACE_STACK_DEPTH) {
+        let recorded = depths.clone();
+        config.set_on_stack_change(move |depth| recorded.lock().unwrap().push(depth));
+    }
+    if args.is_present(ARG_CHECK) {
+        let diagnostics = Vm::lint(config)?;
+        for diagnostic in &diagnostics {
+            eprintln!("{}: {}", file_name, diagnostic);
+        }
+        let failed = diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.severity == Severity::Error);
+        process::exit(if failed { 1 } else { 0 });
+    }
+    if args.is_present(ARG_SIZE) {
+        let size = Vm::program_size(config)?;
+        if args.is_present(ARG_STATS_JSON) {
+            write_stats(
+                &args,
+                format!(
+                    "{{ \"bytes\": {}, \"significant_bytes\": {}, \"instructions\": {} }}",
+                    size.bytes, size.significant_bytes, size.instructions
+                ),
+            );
+        } else {
+            println!("bytes             {:>8}", size.bytes);
+            println!("significant bytes {:>8}", size.significant_bytes);
+            println!("instructions      {:>8}", size.instructions);
+        }
+        return Ok(());
+    }
+    if args.is_present(ARG_COUNT_ONLY) {
+        let histogram = Vm::instruction_histogram(config)?;
+        if args.is_present(ARG_STATS_JSON) {
+            let counts: Vec<String> = histogram
+                .iter()
+                .map(|(kind, count)| format!("\"{}\": {}", kind.mnemonic(), count))
+                .collect();
+            write_stats(&args, format!("{{ {} }}", counts.join(", ")));
+        } else {
+            for (kind, count) in histogram {
+                println!("{:<8} {:>8}", kind.mnemonic(), count);
+            }
+        }
+        return Ok(());
+    }
+    // these need the whole program, everything else can stream it
+    let mut vm = if list_labels || raw || debug {
+        Vm::new(config)?
+    } else {
+        Vm::new_streaming(config)?
+    };
     let end = Instant::now();
-    if !quiet {
-        println!(
-            "initialized in {} ms ({} ns)",
-            end.duration_since(start).as_millis(),
-            end.duration_since(start).as_nanos()
-        );
+    let init = end.duration_since(start);
+    log.banner(format!(
+        "initialized in {} ms ({} ns)",
+        init.as_millis(),
+        init.as_nanos()
+    ));
+    for warning in vm.warnings() {
+        log.warn(format!("{}: {}", file_name, warning));
     }
 
+    if list_labels {
+        print_labels(&vm);
+        return Ok(());
+    }
+
+    let mut run = Duration::ZERO;
     if !raw {
-        if !quiet {
-            println!("starting to execute whitespace routine...\n\n");
-        }
+        log.banner("starting to execute whitespace routine...\n\n");
         let start = Instant::now();
-        vm.run()?;
-        let end = Instant::now();
-        if !quiet {
-            println!("\n\nexecuted {} instructions", vm.instruction_count);
-            println!(
-                "\n\nroutine took {} ms ({} ns)",
-                end.duration_since(start).as_millis(),
-                end.duration_since(start).as_nanos()
-            );
+        if let Err(err) = vm.run() {
+            if args.is_present(ARG_DUMP_STATE_ON_ERROR) {
+                eprint!("{}", vm.dump_state());
+            }
+            return Err(err);
+        }
+        run = start.elapsed();
+        log.banner(format!(
+            "\n\nexecuted {} instructions",
+            vm.instruction_count
+        ));
+        log.banner(format!(
+            "\n\nroutine took {} ms ({} ns)",
+            run.as_millis(),
+            run.as_nanos()
+        ));
+    }
+
+    if args.is_present(ARG_TRACE_STACK_DEPTH) {
+        let depths = depths.lock().unwrap();
+        eprintln!(
+            "stack depth: {} (peak {})",
+            sparkline(&depths, 60),
+            depths.iter().max().unwrap_or(&0)
+        );
+    }
+
+    if args.is_present(ARG_STATS_JSON) {
+        let stats = format!(
+            "{{ \"init_ms\": {}, \"run_ms\": {}, \"instructions\": {} }}",
+            init.as_secs_f64() * 1000.0,
+            run.as_secs_f64() * 1000.0,
+            vm.instruction_count
+        );
+        if args.value_of(ARG_STATS_JSON).is_none() {
+            // separates the stats from the program output
+            println!();
         }
+        write_stats(&args, stats);
     }
 
     Ok(())
 }
+
+/// Writes the JSON stats to the file given to `--stats-json`, to stdout if there is none
+fn write_stats(args: &ArgMatches, stats: String) {
+    match args.value_of(ARG_STATS_JSON) {
+        Some(file) => {
+            if let Err(err) = fs::write(file, stats + "\n") {
+                eprintln!("failed to write stats file {}: {}", file, err);
+                process::exit(1);
+            }
+        }
+        None => println!("{}", stats),
+    }
+}
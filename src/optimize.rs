@@ -0,0 +1,59 @@
+use crate::lint::{self, SuggestionKind};
+use crate::Instruction;
+
+/// Removes pairs of instructions that have no effect on the program, e.g. `push 0; add` or
+/// `swap; swap`. Removing a pair can make its neighbours a removable pair, which is removed as
+/// well. `Mark` instructions are never removed, so labels keep pointing at the same code once
+/// they are resolved.
+///
+/// The only observable difference is that a removed pair can no longer fail on a stack that
+/// holds too few values.
+pub(crate) fn optimize(instructions: Vec<Instruction>) -> Vec<Instruction> {
+    let mut optimized: Vec<Instruction> = Vec::with_capacity(instructions.len());
+    for instr in instructions {
+        optimized.push(instr);
+        if optimized.len() < 2 {
+            continue;
+        }
+        let index = optimized.len() - 2;
+        match lint::peephole(&optimized, index) {
+            Some(
+                SuggestionKind::NoOpArithmetic
+                | SuggestionKind::DoubleSwap
+                | SuggestionKind::DuplicateDiscard,
+            ) => optimized.truncate(index),
+            Some(SuggestionKind::DuplicatePush) | None => {}
+        }
+    }
+
+    optimized
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::SourceType;
+    use crate::vm::{Vm, VmConfig, VmError};
+
+    fn run(optimize: bool) -> Result<(Vec<u8>, usize), VmError> {
+        let mut config =
+            VmConfig::default_heap("resources/ws/redundant.ws", SourceType::Whitespace);
+        config.set_optimize(optimize);
+        let mut interpreter = Vm::new(config)?;
+        interpreter.capture_output();
+        interpreter.run()?;
+
+        Ok((interpreter.take_output(), interpreter.instruction_count))
+    }
+
+    #[test]
+    fn optimize_redundant() -> Result<(), VmError> {
+        let (output, count) = run(false)?;
+        let (optimized_output, optimized_count) = run(true)?;
+
+        assert_eq!(output, b"321");
+        assert_eq!(optimized_output, output);
+        assert!(optimized_count < count);
+
+        Ok(())
+    }
+}
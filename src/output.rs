@@ -0,0 +1,279 @@
+use crate::vm::{Buffering, OutputEncoding};
+use std::fmt::Display;
+use std::io::{stdout, Write};
+
+/// The number of bytes a block-buffered sink collects before writing them
+const BLOCK_SIZE: usize = 8192;
+
+/// Destination of the program output
+enum Target {
+    Stdout,
+    Captured(Vec<u8>),
+    Writer(Box<dyn Write + Send>),
+}
+
+/// Why writing to an `OutputSink` failed
+#[derive(Debug)]
+pub enum OutputError {
+    /// The write would pass the byte limit, nothing was written
+    LimitExceeded(u64),
+    /// The underlying writer failed
+    Io(std::io::Error),
+}
+
+impl Display for OutputError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OutputError::LimitExceeded(limit) => {
+                write!(f, "output would exceed the limit of {} bytes", limit)
+            }
+            OutputError::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for OutputError {}
+
+/// Where and how the program output is written: the destination, when it is flushed, how
+/// characters are encoded and how many bytes may be written in total. Configure it once and pass
+/// it to `Vm::set_output_sink`. Buffered output is flushed when the sink is dropped.
+pub struct OutputSink {
+    target: Target,
+    buffer: Vec<u8>,
+    buffering: Buffering,
+    encoding: OutputEncoding,
+    limit: Option<u64>,
+    written: u64,
+}
+
+impl OutputSink {
+    fn new(target: Target) -> OutputSink {
+        OutputSink {
+            target,
+            buffer: vec![],
+            buffering: Buffering::PerChar,
+            encoding: OutputEncoding::Utf8,
+            limit: None,
+            written: 0,
+        }
+    }
+
+    /// Creates a sink writing to stdout
+    pub fn stdout() -> OutputSink {
+        OutputSink::new(Target::Stdout)
+    }
+
+    /// Creates a sink collecting the output in memory, see `take_captured`
+    pub fn captured() -> OutputSink {
+        OutputSink::new(Target::Captured(vec![]))
+    }
+
+    /// Creates a sink writing to the given writer
+    ///
+    /// - `writer` the writer to write output to
+    pub fn writer(writer: Box<dyn Write + Send>) -> OutputSink {
+        OutputSink::new(Target::Writer(writer))
+    }
+
+    /// Sets when the output is flushed, defaults to `Buffering::PerChar`
+    ///
+    /// - `buffering` when to flush the output
+    pub fn set_buffering(&mut self, buffering: Buffering) {
+        self.buffering = buffering;
+    }
+
+    /// Sets how characters are serialized, defaults to `OutputEncoding::Utf8`
+    ///
+    /// - `encoding` the encoding of characters
+    pub fn set_encoding(&mut self, encoding: OutputEncoding) {
+        self.encoding = encoding;
+    }
+
+    /// Limits the number of bytes written in total, no limit by default
+    ///
+    /// - `limit` the maximum number of bytes, `None` for no limit
+    pub fn set_limit(&mut self, limit: Option<u64>) {
+        self.limit = limit;
+    }
+
+    /// Returns the number of bytes written so far, including buffered ones
+    pub fn bytes_written(&self) -> u64 {
+        self.written
+    }
+
+    /// Starts counting the bytes written towards the limit over
+    pub fn reset_limit(&mut self) {
+        self.written = 0;
+    }
+
+    /// Writes a character in the configured encoding
+    ///
+    /// - `character` the character to write
+    pub fn write_char(&mut self, character: char) -> Result<(), OutputError> {
+        let bytes: Vec<u8> = match self.encoding {
+            OutputEncoding::Utf8 => character.to_string().into_bytes(),
+            OutputEncoding::Utf16Le => character
+                .encode_utf16(&mut [0; 2])
+                .iter()
+                .flat_map(|unit| unit.to_le_bytes())
+                .collect(),
+            OutputEncoding::Raw => (character as u32).to_le_bytes().to_vec(),
+        };
+
+        self.write_bytes(&bytes)
+    }
+
+    /// Writes the bytes, unless they would pass the limit, and flushes them as configured. Only
+    /// bytes that were written successfully count towards the limit.
+    ///
+    /// - `bytes` the bytes to write
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), OutputError> {
+        let written = self.written + bytes.len() as u64;
+        if let Some(limit) = self.limit {
+            if written > limit {
+                return Err(OutputError::LimitExceeded(limit));
+            }
+        }
+        let flush = match self.buffering {
+            Buffering::PerChar => true,
+            Buffering::Line => bytes.contains(&b'\n'),
+            Buffering::Block => {
+                self.buffer.extend_from_slice(bytes);
+                if self.buffer.len() >= BLOCK_SIZE {
+                    let buffer = std::mem::take(&mut self.buffer);
+                    self.write_target(&buffer).map_err(OutputError::Io)?;
+                }
+                self.written = written;
+
+                return Ok(());
+            }
+        };
+        self.write_target(bytes).map_err(OutputError::Io)?;
+        self.written = written;
+        if flush {
+            self.flush().map_err(OutputError::Io)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_target(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        match &mut self.target {
+            Target::Stdout => stdout().write_all(bytes),
+            Target::Captured(captured) => {
+                captured.extend_from_slice(bytes);
+
+                Ok(())
+            }
+            Target::Writer(writer) => writer.write_all(bytes),
+        }
+    }
+
+    /// Writes the buffered output and flushes the destination
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        if !self.buffer.is_empty() {
+            let buffer = std::mem::take(&mut self.buffer);
+            self.write_target(&buffer)?;
+        }
+        match &mut self.target {
+            Target::Stdout => stdout().flush(),
+            Target::Captured(_) => Ok(()),
+            Target::Writer(writer) => writer.flush(),
+        }
+    }
+
+    /// Returns the output captured so far, including buffered output, and clears the capture
+    /// buffer. Empty if the sink does not capture its output.
+    pub fn take_captured(&mut self) -> Vec<u8> {
+        if let Target::Captured(captured) = &mut self.target {
+            captured.append(&mut self.buffer);
+
+            return std::mem::take(captured);
+        }
+
+        vec![]
+    }
+
+    /// Flushes the output and writes everything written from now on to the other destination,
+    /// keeping the configuration and the byte count
+    pub(crate) fn redirect(&mut self, mut other: OutputSink) {
+        // an error is reported by the destination writing the output from now on
+        let _ = self.flush();
+        std::mem::swap(&mut self.target, &mut other.target);
+    }
+}
+
+impl Drop for OutputSink {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{OutputError, OutputSink};
+    use crate::vm::{Buffering, OutputEncoding};
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    /// A writer recording everything written to it, shared with the test
+    #[derive(Clone, Default)]
+    struct SharedWriter(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn block_buffering_with_limit() {
+        let writer = SharedWriter::default();
+        let mut sink = OutputSink::writer(Box::new(writer.clone()));
+        sink.set_buffering(Buffering::Block);
+        sink.set_encoding(OutputEncoding::Utf16Le);
+        sink.set_limit(Some(5));
+
+        assert!(sink.write_char('h').is_ok());
+        assert!(sink.write_char('i').is_ok());
+        assert!(matches!(
+            sink.write_char('!'),
+            Err(OutputError::LimitExceeded(5))
+        ));
+        assert!(writer.0.lock().unwrap().is_empty());
+        assert_eq!(sink.bytes_written(), 4);
+
+        drop(sink);
+
+        assert_eq!(*writer.0.lock().unwrap(), b"h\0i\0");
+    }
+
+    /// A writer failing every write
+    struct FailingWriter;
+
+    impl Write for FailingWriter {
+        fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+            Err(std::io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn failed_writes_do_not_count() {
+        let mut sink = OutputSink::writer(Box::new(FailingWriter));
+        sink.set_limit(Some(1));
+
+        assert!(matches!(sink.write_char('a'), Err(OutputError::Io(_))));
+        assert!(matches!(sink.write_char('b'), Err(OutputError::Io(_))));
+        assert_eq!(sink.bytes_written(), 0);
+    }
+}
@@ -3,11 +3,13 @@ use std::any::Any;
 use std::{
     error::Error,
     fmt::{Debug, Display},
+    ops::Range,
     str::FromStr,
 };
 
 use wasm_bindgen::{prelude::wasm_bindgen, JsValue};
 
+use crate::ws::{is_token, LINE_FEED, SPACE, TAB};
 use crate::Instruction;
 
 #[wasm_bindgen]
@@ -37,6 +39,10 @@ pub(crate) enum ParseErrorKind {
     FileOpenError(Box<dyn Error>),
     #[allow(unused)]
     MemoryMapError(Box<dyn Error>),
+    #[allow(unused)]
+    DecompressionError(Box<dyn Error>),
+    ReadError(Box<dyn Error>),
+    AssemblyError(usize, String),
 }
 
 impl ParseErrorKind {
@@ -60,6 +66,15 @@ impl ParseErrorKind {
             ParseErrorKind::MemoryMapError(err) => {
                 format!("failed to memory map file, details: {}", err)
             }
+            ParseErrorKind::DecompressionError(err) => {
+                format!("failed to decompress file, details: {}", err)
+            }
+            ParseErrorKind::ReadError(err) => {
+                format!("failed to read source, details: {}", err)
+            }
+            ParseErrorKind::AssemblyError(line, err) => {
+                format!("invalid assembly on line {}: {}", line, err)
+            }
         };
         Err(ParseError { msg, kind: self })
     }
@@ -97,11 +112,79 @@ pub trait Instr: Debug {
     #[cfg(test)]
     fn as_any(&self) -> &dyn Any;
 
+    /// Returns the range of source bytes that encode the instruction
+    fn span(&self) -> Range<usize>;
+
     fn translate(&self) -> Result<Instruction, ParseError>;
 }
 
+/// A single lexical token of whitespace source
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Token {
+    Space,
+    Tab,
+    Newline,
+}
+
 pub trait Parser {
     fn instruction(&mut self) -> Option<Result<Box<dyn Instr>, ParseError>>;
+
+    /// Returns the whole source being parsed
+    fn source(&self) -> &[u8];
+
+    /// Enables parsing the dialect extensions, e.g. `JumpDynamic`, which are rejected like any
+    /// other unexpected token otherwise. Languages without extensions ignore it.
+    fn set_extensions(&mut self, _extensions: bool) {}
+
+    /// Returns the tokens of the whole source with the range of source bytes of each, e.g. for
+    /// highlighting the source. Comments are skipped. The tokens are independent of parsing
+    /// instructions.
+    fn tokens(&self) -> Box<dyn Iterator<Item = (Token, Range<usize>)> + '_> {
+        Box::new(
+            self.source()
+                .iter()
+                .enumerate()
+                .filter_map(|(index, byte)| {
+                    let token = match *byte {
+                        SPACE => Token::Space,
+                        TAB => Token::Tab,
+                        LINE_FEED => Token::Newline,
+                        _ => return None,
+                    };
+
+                    Some((token, index..index + 1))
+                }),
+        )
+    }
+
+    /// Returns the comments of the source with the range of source bytes of each. A comment is a
+    /// contiguous run of bytes that are not tokens.
+    fn extract_comments(&self) -> Vec<(Range<usize>, String)> {
+        let source = self.source();
+        let mut comments = vec![];
+        let mut start = None;
+        for (index, byte) in source.iter().enumerate() {
+            match (start, is_token(*byte)) {
+                (None, false) => start = Some(index),
+                (Some(begin), true) => {
+                    comments.push(begin..index);
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(begin) = start {
+            comments.push(begin..source.len());
+        }
+
+        comments
+            .into_iter()
+            .map(|span| {
+                let text = String::from_utf8_lossy(&source[span.clone()]).into_owned();
+                (span, text)
+            })
+            .collect()
+    }
 }
 
 impl Iterator for &mut Box<dyn Parser> {
@@ -0,0 +1,793 @@
+use crate::asm;
+use crate::ir::Label;
+use crate::lint;
+use crate::parser::{Parser, SourceType};
+use crate::vm::{VmError, VmErrorKind};
+use crate::ws;
+use crate::{Instruction, WsCommandKind, WsParser};
+use std::collections::{BTreeSet, HashMap};
+use std::io::Read;
+#[cfg(feature = "cache")]
+use std::io::{Error, ErrorKind, Write};
+use std::ops::Range;
+#[cfg(feature = "cache")]
+use std::{fs::File, path::Path};
+
+/// Magic bytes at the start of every binary program
+#[cfg(feature = "cache")]
+const BINARY_MAGIC: &[u8; 4] = b"SPCY";
+
+/// Version of the binary program format, bumped whenever the encoding of programs changes
+#[cfg(feature = "cache")]
+const BINARY_VERSION: u32 = 4;
+
+/// A parsed program, ready to be loaded into a `Vm`
+#[derive(Debug, PartialEq, Clone, Default)]
+#[cfg_attr(feature = "cache", derive(serde::Serialize, serde::Deserialize))]
+pub struct Program {
+    pub(crate) instructions: Vec<Instruction>,
+    pub(crate) source: Vec<u8>,
+    spans: Vec<Range<usize>>,
+}
+
+/// The size of a program, e.g. to compare versions of a golfed program
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ProgramSize {
+    /// Size of the source in bytes, including comments
+    pub bytes: usize,
+    /// Number of spaces, tabs and line feeds in the source, the bytes that are not comments
+    pub significant_bytes: usize,
+    /// Number of instructions
+    pub instructions: usize,
+}
+
+impl Program {
+    /// Parses a program from the bytes of its source. The dialect extensions are rejected, see
+    /// `from_bytes_with_extensions`.
+    ///
+    /// - `source` the source of the program
+    /// - `source_type` the type of the source
+    pub fn from_bytes(source: &[u8], source_type: SourceType) -> Result<Program, VmError> {
+        Program::parse_bytes(source, source_type, false)
+    }
+
+    /// Parses a program using the dialect extensions, e.g. `JumpDynamic`, from the bytes of its
+    /// source. It only runs with `VmConfig::set_extensions`.
+    ///
+    /// - `source` the source of the program
+    /// - `source_type` the type of the source
+    pub fn from_bytes_with_extensions(
+        source: &[u8],
+        source_type: SourceType,
+    ) -> Result<Program, VmError> {
+        Program::parse_bytes(source, source_type, true)
+    }
+
+    fn parse_bytes(
+        source: &[u8],
+        source_type: SourceType,
+        extensions: bool,
+    ) -> Result<Program, VmError> {
+        let mut parser = match source_type {
+            SourceType::Whitespace => match WsParser::from_bytes(source) {
+                Ok(content) => content,
+                Err(err) => return VmErrorKind::ParseError(Box::new(err)).throw(),
+            },
+            SourceType::Malbolge | SourceType::Brainfuck => {
+                return VmErrorKind::UnsupportedSourceType.throw()
+            }
+        };
+        parser.set_extensions(extensions);
+
+        Program::from_parser(parser, false)
+    }
+
+    /// Parses a program from a reader, e.g. a network stream or stdin. The source is buffered
+    /// entirely before parsing.
+    ///
+    /// - `reader` the reader to read the source from
+    /// - `source_type` the type of the source
+    pub fn from_reader(reader: impl Read, source_type: SourceType) -> Result<Program, VmError> {
+        let parser = match source_type {
+            SourceType::Whitespace => match WsParser::from_reader(reader) {
+                Ok(content) => content,
+                Err(err) => return VmErrorKind::ParseError(Box::new(err)).throw(),
+            },
+            SourceType::Malbolge | SourceType::Brainfuck => {
+                return VmErrorKind::UnsupportedSourceType.throw()
+            }
+        };
+
+        Program::from_parser(parser, false)
+    }
+
+    /// Collects and translates all instructions of the parser
+    ///
+    /// - `parser` the parser to read the instructions from
+    /// - `raw` print the IR of every parsed instruction to stdout
+    pub(crate) fn from_parser(mut parser: Box<dyn Parser>, raw: bool) -> Result<Program, VmError> {
+        let mut instructions = vec![];
+        let mut spans = vec![];
+        while let Some(next) = Program::next_instruction(&mut parser, raw) {
+            let (instr, span) = next?;
+            instructions.push(instr);
+            spans.push(span);
+        }
+
+        Ok(Program {
+            instructions,
+            source: parser.source().to_vec(),
+            spans,
+        })
+    }
+
+    /// Parses and translates the next instruction of the parser, returning it with its span.
+    /// `None` at the end of the source.
+    ///
+    /// - `parser` the parser to read the instruction from
+    /// - `raw` print the IR of the parsed instruction to stdout
+    pub(crate) fn next_instruction(
+        parser: &mut Box<dyn Parser>,
+        raw: bool,
+    ) -> Option<Result<(Instruction, Range<usize>), VmError>> {
+        let instr = match parser.instruction()? {
+            Ok(content) => content,
+            Err(err) => return Some(VmErrorKind::ParseError(Box::new(err)).throw()),
+        };
+        if raw {
+            dbg!(&instr);
+        }
+        let span = instr.span();
+
+        Some(match instr.translate() {
+            Ok(instr) => Ok((instr, span)),
+            Err(err) => VmErrorKind::TranslateError(Box::new(err)).throw(),
+        })
+    }
+
+    /// Returns all instructions of the program
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    /// Returns the exact source bytes that encode the instruction at the given index, without
+    /// surrounding comments. `None` past the end of the program.
+    ///
+    /// - `index` the index of the instruction
+    pub fn source_slice(&self, index: usize) -> Option<&[u8]> {
+        self.spans.get(index).map(|span| &self.source[span.clone()])
+    }
+
+    /// Lists every instruction as its source rendered with visible symbols, see `asm::to_symbols`,
+    /// followed by its assembly as a comment, e.g. `␠␠␠␉␊  ; push 1`. One line per instruction.
+    pub fn annotated_listing(&self) -> String {
+        asm::disassemble(&self.instructions)
+            .lines()
+            .zip(&self.spans)
+            .map(|(line, span)| {
+                format!(
+                    "{}  ; {}\n",
+                    asm::to_symbols(&self.source[span.clone()]),
+                    line
+                )
+            })
+            .collect()
+    }
+
+    /// Builds the control flow graph of the program and writes it in the DOT language of Graphviz,
+    /// e.g. to render it with `dot -Tsvg`. Every node is a basic block, named `b` followed by the
+    /// index of its first instruction. Blocks start at labels and after every instruction
+    /// changing the flow. Jumps and calls are labelled edges, falling through to the next block
+    /// is an unlabelled edge. `Return`, `Exit` and the targets of `JumpDynamic` have no edges.
+    pub fn to_dot(&self) -> String {
+        let labels = lint::label_map(&self.instructions);
+        let mut leaders = BTreeSet::from([0]);
+        for (index, instr) in self.instructions.iter().enumerate() {
+            match instr {
+                Instruction::Mark(_) => {
+                    leaders.insert(index);
+                }
+                Instruction::Call(_)
+                | Instruction::Jump(_)
+                | Instruction::JumpZero(_)
+                | Instruction::JumpNegative(_)
+                | Instruction::JumpDynamic
+                | Instruction::Return
+                | Instruction::Exit => {
+                    leaders.insert(index + 1);
+                }
+                _ => {}
+            }
+        }
+        leaders.retain(|index| *index < self.instructions.len());
+
+        let listing = asm::disassemble(&self.instructions);
+        let lines = listing.lines().collect::<Vec<_>>();
+        let mut out =
+            String::from("digraph program {\n    node [shape=box, fontname=\"monospace\"];\n");
+        let mut edges = vec![];
+        let starts = leaders.iter().copied().collect::<Vec<_>>();
+        for (block, &start) in starts.iter().enumerate() {
+            let end = starts
+                .get(block + 1)
+                .copied()
+                .unwrap_or(self.instructions.len());
+            let label = (start..end)
+                .map(|index| format!("{}: {}\\l", index, lines[index]))
+                .collect::<String>();
+            out.push_str(&format!("    b{} [label=\"{}\"];\n", start, label));
+
+            let last = &self.instructions[end - 1];
+            let target = Program::label(last)
+                .filter(|_| !matches!(last, Instruction::Mark(_)))
+                .and_then(|label| labels.get(&label.value));
+            if let Some(target) = target {
+                edges.push((start, *target, Some(lint::command_kind(last).mnemonic())));
+            }
+            let falls_through = !matches!(
+                last,
+                Instruction::Jump(_)
+                    | Instruction::JumpDynamic
+                    | Instruction::Return
+                    | Instruction::Exit
+            );
+            if falls_through && end < self.instructions.len() {
+                edges.push((start, end, None));
+            }
+        }
+        for (from, to, label) in edges {
+            match label {
+                Some(label) => out.push_str(&format!(
+                    "    b{} -> b{} [label=\"{}\"];\n",
+                    from, to, label
+                )),
+                None => out.push_str(&format!("    b{} -> b{};\n", from, to)),
+            }
+        }
+        out.push_str("}\n");
+
+        out
+    }
+
+    /// Returns the size of the source and the number of instructions
+    pub fn size(&self) -> ProgramSize {
+        ProgramSize {
+            bytes: self.source.len(),
+            significant_bytes: self
+                .source
+                .iter()
+                .filter(|byte| ws::is_token(**byte))
+                .count(),
+            instructions: self.instructions.len(),
+        }
+    }
+
+    /// Returns how often each command appears in the program, not how often it is executed, in
+    /// the order of the instruction set. Commands the program does not use are left out.
+    pub fn instruction_histogram(&self) -> Vec<(WsCommandKind, usize)> {
+        let mut counts = HashMap::new();
+        for instr in &self.instructions {
+            *counts.entry(lint::command_kind(instr)).or_insert(0) += 1;
+        }
+
+        WsCommandKind::all()
+            .iter()
+            .filter_map(|kind| counts.get(kind).map(|count| (*kind, *count)))
+            .collect()
+    }
+
+    /// Returns the largest heap address the program writes to, to size the heap, as far as it can
+    /// be told without running the program. The values on the stack are tracked along
+    /// straight-line code, so addresses pushed, duplicated or computed from constants count as
+    /// constant, e.g. `push 7; push 1; retrieve; store` or `push 3; push 4; add; readc`. Nothing
+    /// is known about the stack after a label or a call. `None` if the program writes to an
+    /// address computed at runtime, or does not write to the heap at all.
+    pub fn max_constant_heap_address(&self) -> Option<usize> {
+        // the known values on top of the stack, `None` for values computed at runtime
+        let mut stack: Vec<Option<i64>> = vec![];
+        let pop = |stack: &mut Vec<Option<i64>>| stack.pop().flatten();
+        let mut max = None;
+        for instr in &self.instructions {
+            match instr {
+                Instruction::PushStack(num) => stack.push(Some(num.value)),
+                Instruction::DuplicateStack => stack.push(stack.last().copied().flatten()),
+                Instruction::CopyNthStack(num) => {
+                    let copied = usize::try_from(num.value)
+                        .ok()
+                        .and_then(|n| stack.len().checked_sub(n + 1))
+                        .and_then(|index| stack[index]);
+                    stack.push(copied);
+                }
+                Instruction::SwapStack => {
+                    let (top, below) = (pop(&mut stack), pop(&mut stack));
+                    stack.extend([top, below]);
+                }
+                Instruction::DiscardStack
+                | Instruction::JumpZero(_)
+                | Instruction::JumpNegative(_)
+                | Instruction::OutCharacter
+                | Instruction::OutInteger => {
+                    stack.pop();
+                }
+                Instruction::SlideNStack(num) => {
+                    let top = pop(&mut stack);
+                    let n = usize::try_from(num.value).unwrap_or(0);
+                    stack.truncate(stack.len().saturating_sub(n));
+                    stack.push(top);
+                }
+                Instruction::Add
+                | Instruction::Subtract
+                | Instruction::Multiply
+                | Instruction::IntegerDivision
+                | Instruction::Modulo => {
+                    let (right, left) = (pop(&mut stack), pop(&mut stack));
+                    let result = left.zip(right).and_then(|(left, right)| match instr {
+                        Instruction::Add => left.checked_add(right),
+                        Instruction::Subtract => left.checked_sub(right),
+                        Instruction::Multiply => left.checked_mul(right),
+                        // the rounding of negative operands depends on the compat profile
+                        Instruction::IntegerDivision if left >= 0 && right > 0 => {
+                            Some(left / right)
+                        }
+                        Instruction::Modulo if left >= 0 && right > 0 => Some(left % right),
+                        _ => None,
+                    });
+                    stack.push(result);
+                }
+                Instruction::RetrieveHeap => {
+                    stack.pop();
+                    stack.push(None);
+                }
+                Instruction::StoreHeap => {
+                    stack.pop();
+                    let addr = usize::try_from(pop(&mut stack)?).ok()?;
+                    max = max.max(Some(addr));
+                }
+                Instruction::ReadCharacter | Instruction::ReadInteger => {
+                    let addr = usize::try_from(pop(&mut stack)?).ok()?;
+                    max = max.max(Some(addr));
+                }
+                // labels can be jumped to and subroutines and host functions can leave anything
+                Instruction::Mark(_)
+                | Instruction::Call(_)
+                | Instruction::Jump(_)
+                | Instruction::JumpDynamic
+                | Instruction::Syscall(_)
+                | Instruction::Return
+                | Instruction::Exit => stack.clear(),
+            }
+        }
+
+        max
+    }
+
+    /// Whether the program stores to or retrieves from the heap. Reading input always stores to
+    /// the heap.
+    pub fn uses_heap(&self) -> bool {
+        self.reads_input()
+            || self
+                .instructions
+                .iter()
+                .any(|instr| matches!(instr, Instruction::StoreHeap | Instruction::RetrieveHeap))
+    }
+
+    /// Whether the program reads characters or integers from the input
+    pub fn reads_input(&self) -> bool {
+        self.instructions
+            .iter()
+            .any(|instr| matches!(instr, Instruction::ReadCharacter | Instruction::ReadInteger))
+    }
+
+    /// Whether the program writes characters or integers to the output
+    pub fn writes_output(&self) -> bool {
+        self.instructions
+            .iter()
+            .any(|instr| matches!(instr, Instruction::OutCharacter | Instruction::OutInteger))
+    }
+
+    /// Whether the program calls subroutines or jumps. `Mark` and `Exit` alone do not count.
+    pub fn uses_flow_control(&self) -> bool {
+        self.instructions.iter().any(|instr| {
+            matches!(
+                instr,
+                Instruction::Call(_)
+                    | Instruction::Jump(_)
+                    | Instruction::JumpZero(_)
+                    | Instruction::JumpNegative(_)
+                    | Instruction::Return
+            )
+        })
+    }
+
+    /// Replaces every instruction with the one returned by the function, e.g. to instrument the
+    /// program before running it, and resolves the labels of the new instructions. The source
+    /// and the source slices of the instructions are left as is.
+    ///
+    /// - `f` the function returning the replacement of an instruction
+    pub fn map_instructions(&mut self, f: impl FnMut(Instruction) -> Instruction) {
+        self.instructions = std::mem::take(&mut self.instructions)
+            .into_iter()
+            .map(f)
+            .collect();
+        lint::resolve_labels(&mut self.instructions);
+    }
+
+    /// Renames labels, both where they are marked and where they are referenced. Labels missing
+    /// from the mapping keep their name. Names are raw space and tab sequences. The source of the
+    /// program is left as is, `asm::encode` writes the renamed program.
+    ///
+    /// Fails without renaming anything if a new name is not made of spaces and tabs or if two
+    /// different labels would end up with the same name.
+    ///
+    /// - `map` the new name of every label to rename, by its current name
+    pub fn rename_labels(&mut self, map: &HashMap<String, String>) -> Result<(), VmError> {
+        if let Some(name) = map
+            .values()
+            .find(|name| !name.chars().all(|c| c == ' ' || c == '\t'))
+        {
+            return VmErrorKind::InvalidLabelName(name.clone()).throw();
+        }
+        let mut renamed: HashMap<&str, &str> = HashMap::new();
+        for label in self.instructions.iter().filter_map(Program::label) {
+            let old = &*label.value;
+            let new = map.get(old).map_or(old, String::as_str);
+            if let Some(previous) = renamed.insert(new, old) {
+                if previous != old {
+                    return VmErrorKind::LabelCollision(new.to_string()).throw();
+                }
+            }
+        }
+
+        for instr in &mut self.instructions {
+            if let Some(label) = Program::label_mut(instr) {
+                if let Some(new) = map.get(&*label.value) {
+                    label.value = new.as_str().into();
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn label(instr: &Instruction) -> Option<&Label> {
+        match instr {
+            Instruction::Mark(label)
+            | Instruction::Call(label)
+            | Instruction::Jump(label)
+            | Instruction::JumpZero(label)
+            | Instruction::JumpNegative(label) => Some(label),
+            _ => None,
+        }
+    }
+
+    fn label_mut(instr: &mut Instruction) -> Option<&mut Label> {
+        match instr {
+            Instruction::Mark(label)
+            | Instruction::Call(label)
+            | Instruction::Jump(label)
+            | Instruction::JumpZero(label)
+            | Instruction::JumpNegative(label) => Some(label),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "cache")]
+impl Program {
+    /// Saves the program to a compact binary file, which can be loaded with `load_binary` without
+    /// parsing the source again
+    ///
+    /// - `path` the path of the binary file
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), VmError> {
+        let encoded =
+            bincode::serialize(self).map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+        let mut file = File::create(path)?;
+        file.write_all(BINARY_MAGIC)?;
+        file.write_all(&BINARY_VERSION.to_le_bytes())?;
+        file.write_all(&encoded)?;
+
+        Ok(())
+    }
+
+    /// Loads a program saved with `save`. Files written by a different version of the format are
+    /// rejected.
+    ///
+    /// - `path` the path of the binary file
+    pub fn load_binary(path: impl AsRef<Path>) -> Result<Program, VmError> {
+        let mut content = vec![];
+        File::open(path)?.read_to_end(&mut content)?;
+        let header_len = BINARY_MAGIC.len() + 4;
+        if content.len() < header_len || &content[..BINARY_MAGIC.len()] != BINARY_MAGIC {
+            return Err(Error::new(ErrorKind::InvalidData, "not a binary spacey program").into());
+        }
+        let mut version = [0; 4];
+        version.copy_from_slice(&content[BINARY_MAGIC.len()..header_len]);
+        let version = u32::from_le_bytes(version);
+        if version != BINARY_VERSION {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "binary program has format version {}, expected {}",
+                    version, BINARY_VERSION
+                ),
+            )
+            .into());
+        }
+        let program = bincode::deserialize(&content[header_len..])
+            .map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+
+        Ok(program)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Program, ProgramSize};
+    use crate::ir::Number;
+    use crate::parser::SourceType;
+    use crate::vm::{Vm, VmConfig, VmError, VmErrorKind};
+    use crate::{asm, Instruction, WsCommandKind, WsParser};
+    use std::collections::HashMap;
+    use std::fs;
+    use std::io::Cursor;
+
+    #[test]
+    fn program_from_reader() -> Result<(), VmError> {
+        let source = fs::read("resources/ws/hello_world.ws").unwrap();
+        let program = Program::from_reader(Cursor::new(source.clone()), SourceType::Whitespace)?;
+
+        assert_eq!(
+            program,
+            Program::from_bytes(&source, SourceType::Whitespace)?
+        );
+        assert!(matches!(
+            Program::from_reader(Cursor::new(source.clone()), SourceType::Brainfuck)
+                .unwrap_err()
+                .kind,
+            VmErrorKind::UnsupportedSourceType
+        ));
+
+        let config = VmConfig::default_heap("resources/ws/hello_world.ws", SourceType::Whitespace);
+        let mut interpreter = Vm::from_program(config, program)?;
+        interpreter.capture_output();
+        interpreter.run()?;
+
+        assert_eq!(interpreter.take_output(), b"Hello, world!");
+
+        Ok(())
+    }
+
+    #[test]
+    fn program_source_slice() -> Result<(), VmError> {
+        let mut source = b"push64:".to_vec();
+        source.extend(fs::read("resources/ws/parse_stack.ws").unwrap());
+        let program = Program::from_bytes(&source, SourceType::Whitespace)?;
+
+        assert_eq!(
+            program.instructions()[0],
+            Instruction::PushStack(Number { value: 64 })
+        );
+        assert_eq!(program.source_slice(0), Some(&b"   \t      \n"[..]));
+        assert_eq!(program.source_slice(program.instructions().len()), None);
+
+        let mut parser = WsParser::from_bytes(program.source_slice(0).unwrap()).unwrap();
+        let decoded = (&mut parser).next().unwrap().unwrap().translate().unwrap();
+
+        assert_eq!(decoded, program.instructions()[0]);
+        assert!((&mut parser).next().is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn rename_labels() -> Result<(), VmError> {
+        let source = fs::read("resources/ws/count.ws").unwrap();
+        let program = Program::from_bytes(&source, SourceType::Whitespace)?;
+        let run = |program| -> Result<Vec<u8>, VmError> {
+            let config = VmConfig::default_no_heap("resources/ws/count.ws", SourceType::Whitespace);
+            let mut interpreter = Vm::from_program(config, program)?;
+            interpreter.capture_output();
+            interpreter.run()?;
+
+            Ok(interpreter.take_output())
+        };
+        let mut renamed = program.clone();
+        renamed.rename_labels(&HashMap::from([
+            (" \t    \t\t".to_string(), "\t".to_string()),
+            (" \t   \t \t".to_string(), " \t    \t\t".to_string()),
+        ]))?;
+
+        assert_eq!(
+            asm::disassemble(&renamed.instructions()[11..14]),
+            "jz L01000011\njmp L1\nlabel L01000011\n"
+        );
+        assert_eq!(run(renamed.clone())?, run(program.clone())?);
+
+        let encoded = asm::encode(renamed.instructions());
+        assert_eq!(
+            Program::from_bytes(&encoded, SourceType::Whitespace)?.instructions(),
+            renamed.instructions()
+        );
+
+        let mut collision = program.clone();
+        let map = HashMap::from([(" \t    \t\t".to_string(), " \t   \t \t".to_string())]);
+        assert!(collision.rename_labels(&map).is_err());
+        assert_eq!(collision, program);
+        let map = HashMap::from([(" \t    \t\t".to_string(), "L1".to_string())]);
+        assert!(collision.rename_labels(&map).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn annotated_listing() -> Result<(), VmError> {
+        let program = Program::from_bytes(b"   \t\ncomment\n\n\n", SourceType::Whitespace)?;
+
+        assert_eq!(program.annotated_listing(), "␠␠␠␉␊  ; push 1\n␊␊␊  ; end\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn to_dot() -> Result<(), VmError> {
+        let source = fs::read("resources/ws/recursion_no_exit.ws").unwrap();
+        let program = Program::from_bytes(&source, SourceType::Whitespace)?;
+        let dot = program.to_dot();
+        let (edges, nodes): (Vec<_>, Vec<_>) = dot
+            .lines()
+            .filter(|line| line.starts_with("    b"))
+            .partition(|line| line.contains(" -> "));
+
+        assert!(dot.starts_with("digraph program {\n"));
+        assert_eq!(nodes.len(), 5);
+        assert_eq!(edges.len(), 5);
+        assert!(dot.contains("    b1 [label=\"1: label L0\\l2: dup\\l3: jz L1\\l\"];\n"));
+        assert!(dot.contains("    b1 -> b8 [label=\"jz\"];\n"));
+        assert!(dot.contains("    b1 -> b4;\n"));
+        assert!(dot.contains("    b4 -> b1 [label=\"call\"];\n"));
+        assert!(!dot.contains("b7 ->"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn map_instructions() -> Result<(), VmError> {
+        let run = |program| -> Result<Vec<u8>, VmError> {
+            let config = VmConfig::default_no_heap("", SourceType::Whitespace);
+            let mut interpreter = Vm::from_program(config, program)?;
+            interpreter.capture_output();
+            interpreter.run()?;
+
+            Ok(interpreter.take_output())
+        };
+        let mut program =
+            Program::from_bytes(b"   \t     \t\n\t\n \t\n\n\n", SourceType::Whitespace)?;
+
+        assert_eq!(run(program.clone())?, b"65");
+
+        program.map_instructions(|instr| match instr {
+            Instruction::OutInteger => Instruction::OutCharacter,
+            instr => instr,
+        });
+
+        assert_eq!(run(program)?, b"A");
+
+        Ok(())
+    }
+
+    #[test]
+    fn instruction_histogram() -> Result<(), VmError> {
+        let source = fs::read("resources/ws/interpret_stack.ws").unwrap();
+        let program = Program::from_bytes(&source, SourceType::Whitespace)?;
+
+        assert_eq!(
+            program.instruction_histogram(),
+            [
+                (WsCommandKind::PushStack, 2),
+                (WsCommandKind::DuplicateStack, 3),
+                (WsCommandKind::SwapStack, 1),
+                (WsCommandKind::DiscardStack, 1),
+                (WsCommandKind::SlideNStack, 1),
+                (WsCommandKind::Exit, 1),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn max_constant_heap_address() -> Result<(), VmError> {
+        let address = |file| -> Result<Option<usize>, VmError> {
+            let source = fs::read(file).unwrap();
+
+            Ok(Program::from_bytes(&source, SourceType::Whitespace)?.max_constant_heap_address())
+        };
+
+        assert_eq!(address("resources/ws/heap_gap.ws")?, Some(40));
+        assert_eq!(address("resources/ws/read_twice.ws")?, Some(0));
+        assert_eq!(address("resources/ws/cat.ws")?, Some(1));
+        // the stored value is computed, the address is still a constant
+        assert_eq!(address("resources/ws/increment_cell.ws")?, Some(0));
+        assert_eq!(address("resources/ws/echo.ws")?, Some(0));
+        assert_eq!(address("resources/ws/sieve.ws")?, None);
+        assert_eq!(address("resources/ws/hello_world.ws")?, None);
+
+        for (source, expected) in [
+            ("push 3\npush 4\nadd\nreadc\nend", Some(7)),
+            (
+                "push 9\npush 1\nswap\ncopy 1\nretrieve\nstore\nend",
+                Some(9),
+            ),
+            ("push 1\npush 2\nsub\nreadn\nend", None),
+            ("label L0\npush 5\nreadc\nend", Some(5)),
+            ("push 5\nlabel L0\nreadc\nend", None),
+        ] {
+            let instructions = asm::assemble(source)
+                .map_err(|err| VmErrorKind::ParseError(Box::new(err)).error())?;
+            let program = Program::from_bytes(&asm::encode(&instructions), SourceType::Whitespace)?;
+
+            assert_eq!(program.max_constant_heap_address(), expected, "{}", source);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn program_size() -> Result<(), VmError> {
+        let source = fs::read("resources/ws/fibonacci.ws").unwrap();
+        let program = Program::from_bytes(&source, SourceType::Whitespace)?;
+
+        assert_eq!(
+            program.size(),
+            ProgramSize {
+                bytes: 381,
+                significant_bytes: 292,
+                instructions: 53,
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn program_capabilities() -> Result<(), VmError> {
+        let source = fs::read("resources/ws/interpret_stack.ws").unwrap();
+        let program = Program::from_bytes(&source, SourceType::Whitespace)?;
+
+        assert!(!program.uses_heap());
+        assert!(!program.reads_input());
+        assert!(!program.writes_output());
+        assert!(!program.uses_flow_control());
+
+        let source = fs::read("resources/ws/echo.ws").unwrap();
+        let program = Program::from_bytes(&source, SourceType::Whitespace)?;
+
+        assert!(program.uses_heap());
+        assert!(program.reads_input());
+        assert!(program.writes_output());
+        assert!(program.uses_flow_control());
+
+        Ok(())
+    }
+
+    #[cfg(feature = "cache")]
+    #[test]
+    fn program_binary_round_trip() -> Result<(), VmError> {
+        let source = fs::read("resources/ws/fibonacci.ws").unwrap();
+        let program = Program::from_bytes(&source, SourceType::Whitespace)?;
+        let path = std::env::temp_dir().join("spacey_program_binary_round_trip.bin");
+        program.save(&path)?;
+
+        assert_eq!(Program::load_binary(&path)?, program);
+
+        let mut content = fs::read(&path).unwrap();
+        content[4] += 1;
+        fs::write(&path, content).unwrap();
+
+        assert!(Program::load_binary(&path).is_err());
+
+        fs::remove_file(&path).unwrap();
+
+        Ok(())
+    }
+}
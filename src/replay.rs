@@ -0,0 +1,360 @@
+use crate::parser::SourceType;
+use crate::program::Program;
+use crate::vm::{DivisionRounding, IntInputMode, Vm, VmConfig, VmError, WordSize};
+use std::io::{Cursor, Error, ErrorKind};
+
+const REPLAY_MAGIC: &[u8] = b"spacey-replay\n";
+/// Version of the replay format, bumped whenever the layout changes
+const REPLAY_VERSION: u32 = 2;
+const FLAG_EXTENSIONS: u8 = 1;
+const FLAG_CHECKED_ARITHMETIC: u8 = 2;
+const FLAG_CHECK_LABELS: u8 = 4;
+const FLAG_CHECK_BALANCED_CALLS: u8 = 8;
+const FLAG_IMPLICIT_EXIT: u8 = 16;
+const FLAG_WORD_SIZE_I64: u8 = 32;
+const FLAG_DIVISION_FLOOR: u8 = 64;
+const FLAG_INT_INPUT_TRUNCATE_FLOAT: u8 = 128;
+
+/// A recorded run, containing everything needed to reproduce it: the program source, the
+/// configuration deciding how the program runs and the input the program read.
+///
+/// A `.spacey` file starts with the magic `spacey-replay\n` and the format version as a 32-bit
+/// little-endian integer, followed by the heap size as a 64-bit little-endian integer, a flag byte
+/// (from bit 0: extensions, checked arithmetic, label checks, balanced call checks, implicit
+/// exit, 64-bit words, floor division and truncated decimal input), the optional `eof_value` as a
+/// 32-bit and the optional input seed as a 64-bit little-endian integer, the optional generated
+/// input, the source and the input. An optional value is prefixed by a byte, 1 if it is present
+/// and 0 otherwise, and the generated input, the source and the input are each prefixed by their
+/// length as a 64-bit little-endian integer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Replay {
+    /// The whitespace source of the program
+    pub source: Vec<u8>,
+    /// The size of the heap address space
+    pub heap_size: usize,
+    /// Whether the extension instructions were enabled
+    pub extensions: bool,
+    /// The width of the cells on the stack and the heap
+    pub word_size: WordSize,
+    /// How `IntegerDivision` and `Modulo` rounded
+    pub division_rounding: DivisionRounding,
+    /// Whether arithmetic overflow was an error
+    pub checked_arithmetic: bool,
+    /// Whether undefined and duplicate labels were an error
+    pub check_labels: bool,
+    /// Whether every `Call` had to return before the program exited
+    pub check_balanced_calls: bool,
+    /// How `ReadInteger` treated tokens that are not integers
+    pub int_input: IntInputMode,
+    /// The value stored when reading past the end of the input
+    pub eof_value: Option<i32>,
+    /// Whether running past the last instruction ended the program
+    pub implicit_exit: bool,
+    /// The seed of the pseudo-random input
+    pub input_seed: Option<u64>,
+    /// The line read over and over as input
+    pub generated_input: Option<String>,
+    /// The input the program read, empty if the input was generated
+    pub input: Vec<u8>,
+}
+
+impl Replay {
+    /// Creates a replay of the program running with the options of the given configuration,
+    /// without any input yet
+    ///
+    /// - `source` the whitespace source of the program
+    /// - `config` the configuration the program runs with
+    pub fn new(source: Vec<u8>, config: &VmConfig) -> Replay {
+        Replay {
+            source,
+            heap_size: config.heap_size,
+            extensions: config.extensions,
+            word_size: config.word_size,
+            division_rounding: config.division_rounding,
+            checked_arithmetic: config.checked_arithmetic,
+            check_labels: config.check_labels,
+            check_balanced_calls: config.check_balanced_calls,
+            int_input: config.int_input,
+            eof_value: config.eof_value,
+            implicit_exit: config.implicit_exit,
+            input_seed: config.input_seed,
+            generated_input: config.generated_input.clone(),
+            input: vec![],
+        }
+    }
+
+    /// Whether the program reads generated input instead of the recorded input
+    pub fn generates_input(&self) -> bool {
+        self.input_seed.is_some() || self.generated_input.is_some()
+    }
+
+    /// Serializes the replay to the `.spacey` format
+    pub fn encode(&self) -> Vec<u8> {
+        let mut encoded = REPLAY_MAGIC.to_vec();
+        encoded.extend_from_slice(&REPLAY_VERSION.to_le_bytes());
+        encoded.extend_from_slice(&(self.heap_size as u64).to_le_bytes());
+        let flags = [
+            (self.extensions, FLAG_EXTENSIONS),
+            (self.checked_arithmetic, FLAG_CHECKED_ARITHMETIC),
+            (self.check_labels, FLAG_CHECK_LABELS),
+            (self.check_balanced_calls, FLAG_CHECK_BALANCED_CALLS),
+            (self.implicit_exit, FLAG_IMPLICIT_EXIT),
+            (self.word_size == WordSize::I64, FLAG_WORD_SIZE_I64),
+            (
+                self.division_rounding == DivisionRounding::Floor,
+                FLAG_DIVISION_FLOOR,
+            ),
+            (
+                self.int_input == IntInputMode::TruncateFloat,
+                FLAG_INT_INPUT_TRUNCATE_FLOAT,
+            ),
+        ];
+        encoded.push(
+            flags
+                .iter()
+                .filter(|(set, _)| *set)
+                .fold(0, |flags, (_, flag)| flags | flag),
+        );
+        push_optional(
+            &mut encoded,
+            self.eof_value.map(|value| value.to_le_bytes().to_vec()),
+        );
+        push_optional(
+            &mut encoded,
+            self.input_seed.map(|seed| seed.to_le_bytes().to_vec()),
+        );
+        push_optional(
+            &mut encoded,
+            self.generated_input
+                .as_ref()
+                .map(|line| section(line.as_bytes())),
+        );
+        for bytes in [&self.source, &self.input] {
+            encoded.extend_from_slice(&section(bytes));
+        }
+
+        encoded
+    }
+
+    /// Reads a replay in the `.spacey` format. Replays written by a different version of the
+    /// format are rejected.
+    ///
+    /// - `bytes` the content of the replay file
+    pub fn decode(bytes: &[u8]) -> Result<Replay, VmError> {
+        let invalid = || Error::new(ErrorKind::InvalidData, "not a spacey replay");
+        let rest = bytes.strip_prefix(REPLAY_MAGIC).ok_or_else(invalid)?;
+        let (version, rest) = split_array::<4>(rest).ok_or_else(invalid)?;
+        let version = u32::from_le_bytes(version);
+        if version != REPLAY_VERSION {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "replay has format version {}, expected {}",
+                    version, REPLAY_VERSION
+                ),
+            )
+            .into());
+        }
+        let (heap_size, rest) = split_array::<8>(rest).ok_or_else(invalid)?;
+        let heap_size = usize::try_from(u64::from_le_bytes(heap_size)).map_err(|_| invalid())?;
+        let (&flags, rest) = rest.split_first().ok_or_else(invalid)?;
+        let (eof_value, rest) = split_optional(rest, split_array::<4>).ok_or_else(invalid)?;
+        let (input_seed, rest) = split_optional(rest, split_array::<8>).ok_or_else(invalid)?;
+        let (generated_input, rest) = split_optional(rest, split_section).ok_or_else(invalid)?;
+        let generated_input = match generated_input {
+            Some(line) => Some(String::from_utf8(line.to_vec()).map_err(|_| invalid())?),
+            None => None,
+        };
+        let (source, rest) = split_section(rest).ok_or_else(invalid)?;
+        let (input, rest) = split_section(rest).ok_or_else(invalid)?;
+        if !rest.is_empty() {
+            return Err(invalid().into());
+        }
+
+        Ok(Replay {
+            source: source.to_vec(),
+            heap_size,
+            extensions: flags & FLAG_EXTENSIONS != 0,
+            word_size: match flags & FLAG_WORD_SIZE_I64 != 0 {
+                true => WordSize::I64,
+                false => WordSize::I32,
+            },
+            division_rounding: match flags & FLAG_DIVISION_FLOOR != 0 {
+                true => DivisionRounding::Floor,
+                false => DivisionRounding::Truncate,
+            },
+            checked_arithmetic: flags & FLAG_CHECKED_ARITHMETIC != 0,
+            check_labels: flags & FLAG_CHECK_LABELS != 0,
+            check_balanced_calls: flags & FLAG_CHECK_BALANCED_CALLS != 0,
+            int_input: match flags & FLAG_INT_INPUT_TRUNCATE_FLOAT != 0 {
+                true => IntInputMode::TruncateFloat,
+                false => IntInputMode::Strict,
+            },
+            eof_value: eof_value.map(i32::from_le_bytes),
+            implicit_exit: flags & FLAG_IMPLICIT_EXIT != 0,
+            input_seed: input_seed.map(u64::from_le_bytes),
+            generated_input,
+            input: input.to_vec(),
+        })
+    }
+
+    /// Creates a virtual machine set up like the recorded run, reading the recorded input unless
+    /// the input is generated
+    pub fn vm(&self) -> Result<Vm, VmError> {
+        // the program is parsed from memory, so the config never refers to a source file
+        let mut config = VmConfig::new(
+            "",
+            SourceType::Whitespace,
+            self.heap_size,
+            false,
+            false,
+            false,
+            false,
+        );
+        config.set_extensions(self.extensions);
+        config.set_word_size(self.word_size);
+        config.set_division_rounding(self.division_rounding);
+        config.set_checked_arithmetic(self.checked_arithmetic);
+        config.set_check_labels(self.check_labels);
+        config.set_check_balanced_calls(self.check_balanced_calls);
+        config.set_int_input(self.int_input);
+        if let Some(eof_value) = self.eof_value {
+            config.set_eof_value(eof_value);
+        }
+        config.set_implicit_exit(self.implicit_exit);
+        if let Some(seed) = self.input_seed {
+            config.set_input_seed(seed);
+        }
+        if let Some(line) = &self.generated_input {
+            config.set_generated_input(line);
+        }
+        let program = if self.extensions {
+            Program::from_bytes_with_extensions(&self.source, SourceType::Whitespace)?
+        } else {
+            Program::from_bytes(&self.source, SourceType::Whitespace)?
+        };
+        let mut vm = Vm::from_program(config, program)?;
+        if !self.generates_input() {
+            vm.set_input(Box::new(Cursor::new(self.input.clone())));
+        }
+
+        Ok(vm)
+    }
+}
+
+/// Prefixes the bytes with their length
+fn section(bytes: &[u8]) -> Vec<u8> {
+    let mut section = (bytes.len() as u64).to_le_bytes().to_vec();
+    section.extend_from_slice(bytes);
+
+    section
+}
+
+/// Appends a byte telling whether the value is present, followed by the value if it is
+fn push_optional(encoded: &mut Vec<u8>, value: Option<Vec<u8>>) {
+    match value {
+        Some(value) => {
+            encoded.push(1);
+            encoded.extend_from_slice(&value);
+        }
+        None => encoded.push(0),
+    }
+}
+
+fn split_array<const N: usize>(bytes: &[u8]) -> Option<([u8; N], &[u8])> {
+    if bytes.len() < N {
+        return None;
+    }
+    let (array, rest) = bytes.split_at(N);
+
+    Some((array.try_into().ok()?, rest))
+}
+
+/// Splits off a section prefixed by its length
+fn split_section(bytes: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (len, rest) = split_array::<8>(bytes)?;
+    let len = usize::try_from(u64::from_le_bytes(len)).ok()?;
+    if rest.len() < len {
+        return None;
+    }
+
+    Some(rest.split_at(len))
+}
+
+/// Splits off a value prefixed by whether it is present, read with `split` if it is
+fn split_optional<'a, T>(
+    bytes: &'a [u8],
+    split: impl Fn(&'a [u8]) -> Option<(T, &'a [u8])>,
+) -> Option<(Option<T>, &'a [u8])> {
+    match bytes.split_first()? {
+        (0, rest) => Some((None, rest)),
+        (1, rest) => split(rest).map(|(value, rest)| (Some(value), rest)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Replay;
+    use crate::parser::SourceType;
+    use crate::vm::{CompatProfile, VmConfig, VmError, WordSize};
+
+    #[test]
+    fn encode_decode() -> Result<(), VmError> {
+        let mut config = VmConfig::new("", SourceType::Whitespace, 64, false, false, false, false);
+        config.set_extensions(true);
+        let mut replay = Replay::new(std::fs::read("resources/ws/read_twice.ws")?, &config);
+        replay.input = b"12\n34\n".to_vec();
+        let encoded = replay.encode();
+
+        assert_eq!(Replay::decode(&encoded)?, replay);
+        assert!(Replay::decode(&encoded[..encoded.len() - 1]).is_err());
+        assert!(Replay::decode(b"spacey-replay\n\x01\0\0\0").is_err());
+
+        let mut vm = replay.vm()?;
+        vm.capture_output();
+        vm.run()?;
+
+        assert_eq!(vm.take_output(), b"1234");
+
+        Ok(())
+    }
+
+    #[test]
+    fn replay_config() -> Result<(), VmError> {
+        let mut config = VmConfig::new("", SourceType::Whitespace, 64, false, false, false, false);
+        config.set_compat_profile(CompatProfile::WspaceHaskell);
+        config.set_eof_value(-1);
+        config.set_implicit_exit(true);
+        config.set_input_seed(7);
+        let replay = Replay::new(std::fs::read("resources/ws/read_twice.ws")?, &config);
+        let decoded = Replay::decode(&replay.encode())?;
+
+        assert_eq!(decoded, replay);
+        assert_eq!(decoded.word_size, WordSize::I64);
+        assert_eq!(decoded.eof_value, Some(-1));
+        assert!(decoded.implicit_exit);
+        assert_eq!(decoded.input_seed, Some(7));
+
+        // the input is generated from the seed again instead of read from the recording
+        let run = |replay: &Replay| -> Result<Vec<u8>, VmError> {
+            let mut vm = replay.vm()?;
+            vm.capture_output();
+            vm.run()?;
+
+            Ok(vm.take_output())
+        };
+
+        assert_eq!(run(&decoded)?, run(&replay)?);
+        assert!(!run(&decoded)?.is_empty());
+
+        config.set_generated_input("5");
+        let replay = Replay::new(std::fs::read("resources/ws/read_twice.ws")?, &config);
+        let decoded = Replay::decode(&replay.encode())?;
+
+        assert_eq!(decoded.generated_input.as_deref(), Some("5"));
+        assert_eq!(run(&decoded)?, b"55");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,149 @@
+use std::fmt::Display;
+
+/// A single executed instruction of a program run, see `Vm::run_traced`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct TraceEvent {
+    /// Index of the executed instruction
+    pub instruction_index: usize,
+    /// Depth of the stack after executing the instruction
+    pub stack_depth: usize,
+}
+
+impl Display for TraceEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "instruction {} with stack depth {}",
+            self.instruction_index, self.stack_depth
+        )
+    }
+}
+
+/// The first step at which two traces differ
+#[derive(Debug, PartialEq)]
+pub struct TraceDivergence {
+    /// Number of steps both traces share before diverging
+    pub step: usize,
+    /// The event of the first trace at the step, `None` if the first trace ended before
+    pub first: Option<TraceEvent>,
+    /// The event of the second trace at the step, `None` if the second trace ended before
+    pub second: Option<TraceEvent>,
+}
+
+/// Compares two traces step by step and returns the first divergence, `None` if they are
+/// identical
+///
+/// - `first` the first trace
+/// - `second` the second trace
+pub fn compare_traces(first: &[TraceEvent], second: &[TraceEvent]) -> Option<TraceDivergence> {
+    let step = first
+        .iter()
+        .zip(second)
+        .position(|(a, b)| a != b)
+        .unwrap_or_else(|| first.len().min(second.len()));
+    if step == first.len() && step == second.len() {
+        return None;
+    }
+
+    Some(TraceDivergence {
+        step,
+        first: first.get(step).copied(),
+        second: second.get(step).copied(),
+    })
+}
+
+/// The characters of a sparkline, from the lowest to the highest value
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Draws the stack depths as a sparkline of at most `width` characters, scaled to the peak
+/// depth. Longer series are sampled by drawing the peak of each of `width` consecutive chunks.
+///
+/// - `depths` the stack depths over time
+/// - `width` the maximum number of characters
+pub fn sparkline(depths: &[usize], width: usize) -> String {
+    if depths.is_empty() || width == 0 {
+        return String::new();
+    }
+    let peak = depths.iter().copied().max().unwrap_or_default();
+    let chunk = depths.len().div_ceil(width);
+
+    depths
+        .chunks(chunk)
+        .map(|chunk| {
+            let depth = chunk.iter().copied().max().unwrap_or_default();
+            match peak {
+                0 => SPARKS[0],
+                _ => SPARKS[depth * (SPARKS.len() - 1) / peak],
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compare_traces, sparkline, TraceDivergence, TraceEvent};
+    use crate::parser::SourceType;
+    use crate::vm::{Vm, VmConfig, VmError};
+
+    fn run(optimize: bool) -> Result<(Vec<u8>, Vec<TraceEvent>), VmError> {
+        let mut config =
+            VmConfig::default_heap("resources/ws/redundant.ws", SourceType::Whitespace);
+        config.set_optimize(optimize);
+        let mut interpreter = Vm::new(config)?;
+        interpreter.capture_output();
+        let trace = interpreter.run_traced()?;
+
+        Ok((interpreter.take_output(), trace))
+    }
+
+    #[test]
+    fn compare_optimized_trace() -> Result<(), VmError> {
+        let (output, trace) = run(false)?;
+        let (optimized_output, optimized_trace) = run(true)?;
+
+        assert_eq!(optimized_output, output);
+        assert_eq!(compare_traces(&trace, &trace), None);
+        assert_eq!(
+            compare_traces(&trace, &optimized_trace),
+            Some(TraceDivergence {
+                step: 7,
+                first: Some(TraceEvent {
+                    instruction_index: 7,
+                    stack_depth: 2,
+                }),
+                second: Some(TraceEvent {
+                    instruction_index: 7,
+                    stack_depth: 1,
+                }),
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn compare_truncated_trace() {
+        let event = |instruction_index| TraceEvent {
+            instruction_index,
+            stack_depth: 0,
+        };
+        let trace = [event(0), event(1)];
+
+        assert_eq!(
+            compare_traces(&trace, &trace[..1]),
+            Some(TraceDivergence {
+                step: 1,
+                first: Some(event(1)),
+                second: None,
+            })
+        );
+    }
+
+    #[test]
+    fn sparkline_scales_to_peak() {
+        assert_eq!(sparkline(&[0, 1, 2, 4, 2, 0], 80), "▁▂▄█▄▁");
+        assert_eq!(sparkline(&[0, 7, 0, 0, 1, 1], 3), "█▁▂");
+        assert_eq!(sparkline(&[0, 0], 80), "▁▁");
+        assert_eq!(sparkline(&[], 80), "");
+    }
+}
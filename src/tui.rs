@@ -0,0 +1,145 @@
+use crate::asm;
+use crate::vm::{Vm, VmError};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::widgets::{Block, List, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+
+/// A terminal user interface stepping through a program, showing the instructions with the
+/// current one highlighted, the stack, the non-zero heap and the output
+pub struct Tui {
+    vm: Vm,
+    listing: Vec<String>,
+    output: Vec<u8>,
+    status: String,
+}
+
+impl Tui {
+    /// Creates the interface for the interpreter. The output of the program is captured and shown
+    /// in the interface instead of being written to stdout.
+    ///
+    /// - `vm` the interpreter to step through
+    pub fn new(mut vm: Vm) -> Tui {
+        vm.capture_output();
+        let listing = asm::disassemble(vm.instructions())
+            .lines()
+            .enumerate()
+            .map(|(index, line)| format!("{:>5} {}", index, line))
+            .collect();
+
+        Tui {
+            vm,
+            listing,
+            output: vec![],
+            status: "space steps, q quits".to_string(),
+        }
+    }
+
+    /// Returns the interpreter being stepped through
+    pub fn vm(&self) -> &Vm {
+        &self.vm
+    }
+
+    /// Executes the next instruction and updates the status line, like `Vm::step`
+    pub fn step(&mut self) -> Result<bool, VmError> {
+        let result = self.vm.step();
+        self.output.extend(self.vm.take_output());
+        self.status = match &result {
+            Ok(true) => "space steps, q quits".to_string(),
+            Ok(false) => "program finished, q quits".to_string(),
+            Err(err) => format!("error: {}", err.msg),
+        };
+
+        result
+    }
+
+    /// Draws the interface into the frame
+    pub fn draw(&self, frame: &mut Frame) {
+        let [main, status] =
+            Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+        let [listing, state] =
+            Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)])
+                .areas(main);
+        let [stack, heap, output] = Layout::vertical([
+            Constraint::Percentage(40),
+            Constraint::Percentage(40),
+            Constraint::Percentage(20),
+        ])
+        .areas(state);
+
+        let mut listing_state = ListState::default().with_selected(self.vm.next_instruction());
+        frame.render_stateful_widget(
+            List::new(self.listing.iter().map(String::as_str))
+                .block(Block::bordered().title("instructions"))
+                .highlight_style(Style::default().add_modifier(Modifier::REVERSED)),
+            listing,
+            &mut listing_state,
+        );
+        frame.render_widget(
+            List::new(self.vm.stack().iter().rev().map(|val| val.to_string()))
+                .block(Block::bordered().title("stack (top first)")),
+            stack,
+        );
+        frame.render_widget(
+            List::new(
+                self.vm
+                    .heap()
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, val)| **val != 0)
+                    .map(|(addr, val)| format!("{}: {}", addr, val)),
+            )
+            .block(Block::bordered().title("heap (non-zero)")),
+            heap,
+        );
+        frame.render_widget(
+            Paragraph::new(String::from_utf8_lossy(&self.output).into_owned())
+                .block(Block::bordered().title("output")),
+            output,
+        );
+        frame.render_widget(Paragraph::new(self.status.as_str()), status);
+    }
+
+    /// Shows the interface in the terminal until `q` or escape is pressed. Every other key
+    /// executes the next instruction.
+    ///
+    /// - `terminal` the terminal to draw into
+    pub fn run(&mut self, terminal: &mut DefaultTerminal) -> std::io::Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                    _ => {
+                        // errors are shown in the status line, the program stays where it failed
+                        let _ = self.step();
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Tui;
+    use crate::parser::SourceType;
+    use crate::vm::{Vm, VmConfig, VmError};
+
+    #[test]
+    fn tui_initializes() -> Result<(), VmError> {
+        let config = VmConfig::default_heap("resources/ws/hello_world.ws", SourceType::Whitespace);
+        let mut tui = Tui::new(Vm::new(config)?);
+
+        assert_eq!(tui.listing.len(), tui.vm().instructions().len());
+        assert!(tui.listing[0].ends_with("push 72"));
+        assert!(tui.step()?);
+        assert_eq!(tui.vm().stack(), [72]);
+
+        Ok(())
+    }
+}
@@ -1,43 +1,576 @@
+use crate::asm;
+use crate::lint::{self, Diagnostic, Suggestion};
+use crate::optimize;
+use crate::output::{OutputError, OutputSink};
 use crate::parser::{ParseError, Parser, SourceType};
-use crate::{Instruction, WsParser};
-#[cfg(not(target_arch = "wasm32"))]
+use crate::program::{Program, ProgramSize};
+use crate::trace::TraceEvent;
+use crate::{Instruction, WsCommandKind, WsParser};
+#[cfg(all(not(target_arch = "wasm32"), feature = "terminal"))]
 use getch::Getch;
 use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::collections::HashMap;
+use std::collections::VecDeque;
 use std::fmt::Display;
-use std::io::{stdin, stdout, Write};
+#[cfg(feature = "async")]
+use std::future::Future;
+use std::io::{stdin, stdout, Cursor, ErrorKind, Read, Write};
+use std::num::ParseIntError;
+#[cfg(feature = "async")]
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+#[cfg(feature = "async")]
+use std::task::{Context, Poll, Waker};
 use wasm_bindgen::prelude::wasm_bindgen;
 use wasm_bindgen::JsValue;
 
-#[allow(unused)]
-const DEFAULT_HEAP_SIZE: usize = 524288;
+/// The number of instructions `Vm::run_async` executes before yielding to the runtime
+#[cfg(feature = "async")]
+const ASYNC_SLICE: u64 = 4096;
+
+/// The size of the heap address space used when no size is given
+pub const DEFAULT_HEAP_SIZE: usize = 524288;
+
+/// The number of executed instruction indices reported by a `NoTermination` error when no length
+/// is given
+pub const DEFAULT_HISTORY_LEN: usize = 8;
+
+/// The length from which runs of zero cells are collapsed in the heap dump when no threshold is
+/// given
+pub const DEFAULT_HEAP_DUMP_COLLAPSE: usize = 16;
+
+/// The number of consecutive windows the stack has to grow in before `on_stack_growth` is called
+const STACK_GROWTH_WINDOWS: usize = 4;
+
+/// A host function called by the `Syscall` extension with the stack, see
+/// `VmConfig::register_syscall`
+type HostFunction = Box<dyn FnMut(&mut Vec<i64>) + Send>;
+
+/// Converts a value popped off the stack or given as parameter to an index below `len`, `None`
+/// if it is negative or not below `len`. Converts without `as`, so values exceeding the index
+/// type, e.g. a 16 bit `usize`, are rejected instead of truncated.
+fn checked_index<T: TryFrom<i64> + PartialOrd>(value: i64, len: T) -> Option<T> {
+    T::try_from(value).ok().filter(|index| *index < len)
+}
+
+/// Called with the name of a label when execution enters its region, see
+/// `VmConfig::set_on_enter_label`
+type LabelCallback = Box<dyn FnMut(&str) + Send>;
 
-/// The root component for the virtual machine
+/// The root component for the virtual machine. The interpreter is `Send`, so it can be moved to a
+/// worker thread, which is why injected readers, writers and callbacks have to be `Send` as well.
 #[wasm_bindgen]
 pub struct Vm {
     config: VmConfig,
-    stack: Vec<i32>,
+    stack: Vec<i64>,
     call_stack: Vec<usize>,
-    heap: Vec<i32>,
+    heap: Vec<i64>,
+    heap_size: usize,
+    written: BTreeSet<usize>,
+    heap_journal: Option<Vec<(usize, i64, bool)>>,
     instruction_pointer: usize,
     instructions: Vec<Instruction>,
+    labels: HashMap<String, usize>,
+    breakpoints: BTreeSet<usize>,
     done: bool,
+    last_executed: Option<usize>,
+    history: VecDeque<usize>,
+    carriage_returns: Vec<usize>,
+    input: Option<Box<dyn Read + Send>>,
+    char_input: Box<dyn CharInput>,
+    pending_token: Vec<u8>,
+    pending_character: Vec<u8>,
+    output: OutputSink,
+    gas: Option<u64>,
+    coverage: Vec<bool>,
+    regions: Vec<Option<Arc<str>>>,
+    region: Option<Arc<str>>,
+    /// Whether the program has no flow and is run while parsing it, see `Vm::new_streaming`
+    streaming: bool,
+    /// The index of the first loaded instruction in the whole program, only non-zero while
+    /// streaming, where a single instruction is loaded at a time
+    streamed: usize,
     pub instruction_count: usize,
 }
 
+/// Input that repeats the same line forever, so reading never blocks
+struct GeneratedInput {
+    line: Vec<u8>,
+    position: usize,
+}
+
+impl GeneratedInput {
+    fn new(line: &str) -> GeneratedInput {
+        GeneratedInput {
+            line: format!("{}\n", line).into_bytes(),
+            position: 0,
+        }
+    }
+}
+
+impl Read for GeneratedInput {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        for byte in buf.iter_mut() {
+            *byte = self.line[self.position];
+            self.position = (self.position + 1) % self.line.len();
+        }
+
+        Ok(buf.len())
+    }
+}
+
+/// Input of pseudo-random numbers between 0 and 9999, one per line, reproducible from a seed
+struct SeededInput {
+    state: u64,
+    line: Vec<u8>,
+    position: usize,
+}
+
+impl SeededInput {
+    fn new(seed: u64) -> SeededInput {
+        SeededInput {
+            state: seed,
+            line: vec![],
+            position: 0,
+        }
+    }
+
+    /// Advances the state with the splitmix64 generator
+    fn next_number(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+impl Read for SeededInput {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        for byte in buf.iter_mut() {
+            if self.position == self.line.len() {
+                self.line = format!("{}\n", self.next_number() % 10000).into_bytes();
+                self.position = 0;
+            }
+            *byte = self.line[self.position];
+            self.position += 1;
+        }
+
+        Ok(buf.len())
+    }
+}
+
+/// Reads key presses from an interactive terminal, used when no input is injected with
+/// `Vm::set_input`. The default reads with `getch` if the `terminal` feature is enabled and from
+/// stdin otherwise, see `Vm::set_char_input` to read with another library, e.g. `crossterm`.
+pub trait CharInput: Send {
+    /// Reads the next byte typed, without waiting for a line feed, `None` on end of input
+    fn read_byte(&mut self) -> std::io::Result<Option<u8>>;
+
+    /// Shows a character read by `ReadCharacter`, since terminals in raw mode do not echo. Writes
+    /// the character to stdout by default.
+    ///
+    /// - `character` the character read
+    fn echo(&mut self, character: char) -> std::io::Result<()> {
+        let mut out = stdout();
+        write!(out, "{}", character)?;
+        out.flush()
+    }
+}
+
+/// The default `CharInput`, reading with `getch` if the `terminal` feature is enabled and from
+/// stdin otherwise
+struct TerminalInput;
+
+impl CharInput for TerminalInput {
+    #[cfg(all(not(target_arch = "wasm32"), feature = "terminal"))]
+    fn read_byte(&mut self) -> std::io::Result<Option<u8>> {
+        Getch::new().getch().map(Some)
+    }
+
+    /// Without the `terminal` feature, the terminal is read line by line through stdin
+    #[cfg(all(not(target_arch = "wasm32"), not(feature = "terminal")))]
+    fn read_byte(&mut self) -> std::io::Result<Option<u8>> {
+        Vm::read_input_byte(&mut stdin())
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn read_byte(&mut self) -> std::io::Result<Option<u8>> {
+        unimplemented!();
+    }
+}
+
+/// Input that is fed in chunks while the program runs. Reading from an empty queue fails with
+/// `ErrorKind::WouldBlock` until more input is pushed or the queue is closed, so the interpreter
+/// never blocks waiting for input. Clones share the same queue, also across threads. With the
+/// `async` feature, pushing input wakes `Vm::run_async` waiting for it.
+#[derive(Clone, Default)]
+pub struct InputQueue {
+    queue: Arc<Mutex<InputQueueState>>,
+}
+
+#[derive(Default)]
+struct InputQueueState {
+    bytes: VecDeque<u8>,
+    closed: bool,
+    #[cfg(feature = "async")]
+    waker: Option<Waker>,
+}
+
+impl InputQueue {
+    /// Creates a new, empty queue
+    pub fn new() -> InputQueue {
+        InputQueue::default()
+    }
+
+    fn state(&self) -> MutexGuard<'_, InputQueueState> {
+        // the state stays consistent even if a thread panicked while holding the lock
+        self.queue.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Appends input to the queue
+    ///
+    /// - `bytes` the input to append
+    pub fn push(&self, bytes: &[u8]) {
+        let mut state = self.state();
+        state.bytes.extend(bytes);
+        #[cfg(feature = "async")]
+        InputQueue::wake(&mut state);
+    }
+
+    /// Marks the end of the input. Once the queued input is read, reading reports the end of the
+    /// input instead of blocking.
+    pub fn close(&self) {
+        let mut state = self.state();
+        state.closed = true;
+        #[cfg(feature = "async")]
+        InputQueue::wake(&mut state);
+    }
+
+    #[cfg(feature = "async")]
+    fn wake(state: &mut InputQueueState) {
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// A future that is pending until the queue has input to read or is closed
+#[cfg(feature = "async")]
+struct InputReady<'a> {
+    queue: &'a InputQueue,
+}
+
+#[cfg(feature = "async")]
+impl Future for InputReady<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.queue.state();
+        if !state.bytes.is_empty() || state.closed {
+            return Poll::Ready(());
+        }
+        state.waker = Some(cx.waker().clone());
+
+        Poll::Pending
+    }
+}
+
+impl Read for InputQueue {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut state = self.state();
+        if state.bytes.is_empty() && !state.closed && !buf.is_empty() {
+            return Err(ErrorKind::WouldBlock.into());
+        }
+
+        state.bytes.read(buf)
+    }
+}
+
+/// Input read from several sources in turn: each source is read until it is exhausted, then the
+/// next one, e.g. a prefilled buffer followed by stdin
+#[derive(Default)]
+pub struct ChainedInput {
+    sources: VecDeque<Box<dyn Read + Send>>,
+}
+
+impl ChainedInput {
+    /// Creates a new input without any sources
+    pub fn new() -> ChainedInput {
+        ChainedInput::default()
+    }
+
+    /// Appends a source, read once all previous sources are exhausted
+    ///
+    /// - `source` the source to read from
+    pub fn then(mut self, source: Box<dyn Read + Send>) -> ChainedInput {
+        self.sources.push_back(source);
+        self
+    }
+}
+
+impl Read for ChainedInput {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while let Some(source) = self.sources.front_mut() {
+            let read = source.read(buf)?;
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+            self.sources.pop_front();
+        }
+
+        Ok(0)
+    }
+}
+
+/// A future that is pending once, so the runtime gets to run other tasks
+#[cfg(feature = "async")]
+struct YieldNow {
+    yielded: bool,
+}
+
+#[cfg(feature = "async")]
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.yielded {
+            return Poll::Ready(());
+        }
+        self.yielded = true;
+        cx.waker().wake_by_ref();
+
+        Poll::Pending
+    }
+}
+
+/// Why `Vm::run_slice` returned
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum RunSliceOutcome {
+    /// The program terminated
+    Finished,
+    /// The budget of instructions was used up before the program terminated
+    BudgetExhausted,
+    /// The input reported `ErrorKind::WouldBlock`. The read instruction is retried by the next
+    /// slice.
+    Blocked,
+}
+
+/// The gas charged for executing each kind of instruction, see `VmConfig::set_gas_budget`
+#[derive(Debug, Clone)]
+pub struct CostModel {
+    costs: HashMap<WsCommandKind, u64>,
+}
+
+impl CostModel {
+    /// Creates a cost model charging 1 for every instruction, so the gas budget limits the
+    /// number of executed instructions
+    pub fn new() -> CostModel {
+        CostModel {
+            costs: HashMap::new(),
+        }
+    }
+
+    /// Sets the gas charged for executing instructions of the given kind
+    ///
+    /// - `kind` the kind of instruction
+    /// - `cost` the gas charged per executed instruction
+    pub fn set_cost(&mut self, kind: WsCommandKind, cost: u64) {
+        self.costs.insert(kind, cost);
+    }
+
+    /// Returns the gas charged for executing instructions of the given kind
+    ///
+    /// - `kind` the kind of instruction
+    pub fn cost(&self, kind: WsCommandKind) -> u64 {
+        self.costs.get(&kind).copied().unwrap_or(1)
+    }
+}
+
+impl Default for CostModel {
+    fn default() -> Self {
+        CostModel::new()
+    }
+}
+
+/// A heap checkpoint taken by `Vm::checkpoint_heap`. Only the cells written after the checkpoint
+/// are recorded, which is much lighter than copying the heap.
+#[derive(Debug)]
+pub struct HeapDelta {
+    start: usize,
+}
+
+/// The output and final state of a program run with `execute`
+#[derive(Debug, PartialEq)]
+pub struct ExecutionResult {
+    pub output: Vec<u8>,
+    /// The final stack, bottom first
+    pub stack: Vec<i64>,
+    /// All heap cells holding a non-zero value, by address
+    pub heap: BTreeMap<usize, i64>,
+}
+
 /// Configuration options for the interpreter
 #[wasm_bindgen]
 pub struct VmConfig {
     source_type: SourceType,
     #[cfg(not(target_arch = "wasm32"))]
     file_name: String,
+    #[cfg(not(target_arch = "wasm32"))]
+    gzip: bool,
     #[cfg(target_arch = "wasm32")]
     source: String,
-    heap_size: usize,
+    pub(crate) heap_size: usize,
     raw: bool,
     debug: bool,
     debug_heap: bool,
     suppress_output: bool,
+    pub(crate) word_size: WordSize,
+    pub(crate) division_rounding: DivisionRounding,
+    on_output_error: OutputErrorPolicy,
+    pub(crate) check_balanced_calls: bool,
+    pub(crate) checked_arithmetic: bool,
+    pub(crate) check_labels: bool,
+    pub(crate) generated_input: Option<String>,
+    heap_dump_filter: HeapDumpFilter,
+    heap_dump_range: Option<(usize, usize)>,
+    heap_dump_collapse: usize,
+    optimize: bool,
+    output_encoding: OutputEncoding,
+    output_buffering: Buffering,
+    pub(crate) int_input: IntInputMode,
+    pub(crate) input_seed: Option<u64>,
+    pub(crate) eof_value: Option<i32>,
+    pub(crate) implicit_exit: bool,
+    pub(crate) extensions: bool,
+    history_len: usize,
+    int_width: Option<usize>,
+    gas_budget: Option<u64>,
+    max_output_bytes: Option<u64>,
+    cost_model: CostModel,
+    on_heap_write: Option<Box<dyn FnMut(usize, i64) + Send>>,
+    on_stack_change: Option<Box<dyn FnMut(usize) + Send>>,
+    on_enter_label: Option<LabelCallback>,
+    stack_growth_window: u64,
+    on_stack_growth: Option<Box<dyn FnMut(usize) + Send>>,
+    syscalls: HashMap<i64, HostFunction>,
+}
+
+/// What to do when writing the program output fails, e.g. on a broken pipe
+#[wasm_bindgen]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum OutputErrorPolicy {
+    /// Abort with an error, the default
+    Fail,
+    /// Stop the program as if it had exited
+    StopSilently,
+    /// Drop the output and keep running
+    Ignore,
+}
+
+/// How `OutCharacter` serializes a character
+#[wasm_bindgen]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum OutputEncoding {
+    /// UTF-8, the default
+    Utf8,
+    /// UTF-16 with little-endian code units
+    Utf16Le,
+    /// The code point as four little-endian bytes
+    Raw,
+}
+
+/// When the output is flushed
+#[wasm_bindgen]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Buffering {
+    /// After every character or integer written, the default for interactive programs
+    PerChar,
+    /// After every write containing a line feed
+    Line,
+    /// Only when the buffer is full, before reading input, at `Exit` and when the interpreter is
+    /// dropped
+    Block,
+}
+
+/// How `ReadInteger` treats a token that is not an integer
+#[wasm_bindgen]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum IntInputMode {
+    /// Only integers are accepted, anything else is an error, the default
+    Strict,
+    /// Decimal numbers like `3.14` are accepted as well and truncated towards zero, like some
+    /// interpreters do
+    TruncateFloat,
+}
+
+/// Which heap cells the heap dump of the `debug_heap` option includes
+#[wasm_bindgen]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum HeapDumpFilter {
+    /// Cells holding a non-zero value, the default
+    NonZero,
+    /// Cells written by the program, even if the value written was zero
+    Written,
+    /// Every cell
+    All,
+}
+
+impl FromStr for HeapDumpFilter {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "non-zero" => Ok(HeapDumpFilter::NonZero),
+            "written" => Ok(HeapDumpFilter::Written),
+            "all" => Ok(HeapDumpFilter::All),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The width of a single cell on the stack and the heap
+#[wasm_bindgen]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum WordSize {
+    /// Values wrap around like an `i32`, the default
+    I32,
+    /// Values wrap around like an `i64`
+    I64,
+}
+
+/// How `IntegerDivision` and `Modulo` round a quotient that is not a whole number
+#[wasm_bindgen]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum DivisionRounding {
+    /// Towards zero, the remainder has the sign of the dividend like `%` in C, the default
+    Truncate,
+    /// Towards negative infinity, the remainder has the sign of the divisor like `div` and `mod`
+    /// in Haskell
+    Floor,
+}
+
+/// A set of options matching the behavior of a well-known interpreter, so programs written for it
+/// run the same, see `VmConfig::set_compat_profile`
+#[wasm_bindgen]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum CompatProfile {
+    /// The defaults of this interpreter
+    Spacey,
+    /// `wspace`, the reference interpreter written in Haskell by the authors of the language
+    WspaceHaskell,
+}
+
+impl FromStr for CompatProfile {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "spacey" => Ok(CompatProfile::Spacey),
+            "wspace" => Ok(CompatProfile::WspaceHaskell),
+            _ => Err(()),
+        }
+    }
 }
 
 #[cfg(target_arch = "wasm32")]
@@ -47,7 +580,7 @@ impl VmConfig {
     ///
     /// - `source` the source as a String
     /// - `source_type` the type of the source
-    /// - `heap_size` the size of the heap address space (each address holds an i32)
+    /// - `heap_size` the size of the heap address space (each address holds one word)
     /// - `raw` print the raw instructions of the parsed source file to stdout
     /// - `debug` print debugging information to stdout when executing an instruction
     /// - `debug_heap` print heap dump to stdout when executing an instruction
@@ -69,6 +602,35 @@ impl VmConfig {
             debug,
             debug_heap,
             suppress_output,
+            word_size: WordSize::I32,
+            division_rounding: DivisionRounding::Truncate,
+            on_output_error: OutputErrorPolicy::Fail,
+            check_balanced_calls: false,
+            checked_arithmetic: false,
+            check_labels: false,
+            generated_input: None,
+            heap_dump_filter: HeapDumpFilter::NonZero,
+            heap_dump_range: None,
+            heap_dump_collapse: DEFAULT_HEAP_DUMP_COLLAPSE,
+            optimize: false,
+            output_encoding: OutputEncoding::Utf8,
+            output_buffering: Buffering::PerChar,
+            int_input: IntInputMode::Strict,
+            input_seed: None,
+            eof_value: None,
+            implicit_exit: false,
+            extensions: false,
+            history_len: DEFAULT_HISTORY_LEN,
+            int_width: None,
+            gas_budget: None,
+            max_output_bytes: None,
+            cost_model: CostModel::new(),
+            on_heap_write: None,
+            on_stack_change: None,
+            on_enter_label: None,
+            stack_growth_window: 0,
+            on_stack_growth: None,
+            syscalls: HashMap::new(),
         }
     }
 
@@ -77,15 +639,15 @@ impl VmConfig {
     /// - `source` the source as a String
     /// - `source_type` the type of the source
     pub fn default_heap(source: &str, source_type: SourceType) -> VmConfig {
-        VmConfig {
+        VmConfig::new(
+            source,
             source_type,
-            source: source.to_string(),
-            heap_size: DEFAULT_HEAP_SIZE,
-            raw: false,
-            debug: false,
-            debug_heap: false,
-            suppress_output: false,
-        }
+            DEFAULT_HEAP_SIZE,
+            false,
+            false,
+            false,
+            false,
+        )
     }
 
     /// Returns a default interpreter configuration with no heap
@@ -93,15 +655,7 @@ impl VmConfig {
     /// - `source` the source as a String
     /// - `source_type` the type of the source
     pub fn default_no_heap(source: &str, source_type: SourceType) -> VmConfig {
-        VmConfig {
-            source_type,
-            source: source.to_string(),
-            heap_size: 0,
-            raw: false,
-            debug: false,
-            debug_heap: false,
-            suppress_output: false,
-        }
+        VmConfig::new(source, source_type, 0, false, false, false, false)
     }
 
     /// Returns a default interpreter configuration with the default heap size, suppressing output
@@ -109,15 +663,15 @@ impl VmConfig {
     /// - `source` the source as a String
     /// - `source_type` the type of the source
     pub fn default_heap_suppressed(source: &str, source_type: SourceType) -> VmConfig {
-        VmConfig {
+        VmConfig::new(
+            source,
             source_type,
-            source: source.to_string(),
-            heap_size: DEFAULT_HEAP_SIZE,
-            raw: false,
-            debug: false,
-            debug_heap: false,
-            suppress_output: true,
-        }
+            DEFAULT_HEAP_SIZE,
+            false,
+            false,
+            false,
+            true,
+        )
     }
 
     /// Returns a default interpreter configuration with no heap, suppressing output
@@ -125,15 +679,7 @@ impl VmConfig {
     /// - `source` the source as a String
     /// - `source_type` the type of the source
     pub fn default_no_heap_suppressed(source: &str, source_type: SourceType) -> VmConfig {
-        VmConfig {
-            source_type,
-            source: source.to_string(),
-            heap_size: 0,
-            raw: false,
-            debug: false,
-            debug_heap: false,
-            suppress_output: true,
-        }
+        VmConfig::new(source, source_type, 0, false, false, false, true)
     }
 
     /// Returns a default debug interpreter configuration with the default heap size
@@ -141,15 +687,15 @@ impl VmConfig {
     /// - `source` the source as a String
     /// - `source_type` the type of the source
     pub fn debug_heap(source: &str, source_type: SourceType) -> VmConfig {
-        VmConfig {
+        VmConfig::new(
+            source,
             source_type,
-            source: source.to_string(),
-            heap_size: DEFAULT_HEAP_SIZE,
-            raw: false,
-            debug: true,
-            debug_heap: true,
-            suppress_output: false,
-        }
+            DEFAULT_HEAP_SIZE,
+            false,
+            true,
+            true,
+            false,
+        )
     }
 
     /// Returns a default debug interpreter configuration with no heap
@@ -157,15 +703,7 @@ impl VmConfig {
     /// - `source` the source as a String
     /// - `source_type` the type of the source
     pub fn debug_no_heap(source: &str, source_type: SourceType) -> VmConfig {
-        VmConfig {
-            source_type,
-            source: source.to_string(),
-            heap_size: 0,
-            raw: false,
-            debug: true,
-            debug_heap: false,
-            suppress_output: false,
-        }
+        VmConfig::new(source, source_type, 0, false, true, false, false)
     }
 
     /// Returns a default debug interpreter configuration to only compute the intermediate
@@ -174,15 +712,7 @@ impl VmConfig {
     /// - `source` the source as a String
     /// - `source_type` the type of the source
     pub fn raw(source: &str, source_type: SourceType) -> VmConfig {
-        VmConfig {
-            source_type,
-            source: source.to_string(),
-            heap_size: 0,
-            raw: true,
-            debug: false,
-            debug_heap: false,
-            suppress_output: false,
-        }
+        VmConfig::new(source, source_type, 0, true, false, false, false)
     }
 }
 
@@ -192,7 +722,7 @@ impl VmConfig {
     ///
     /// - `file_name` the path to the source file on disk
     /// - `source_type` the type of the source
-    /// - `heap_size` the size of the heap address space (each address holds an i32)
+    /// - `heap_size` the size of the heap address space (each address holds one word)
     /// - `raw` print the IR of the parsed source file to stdout
     /// - `debug` print debugging information to stdout when executing an instruction
     /// - `debug_heap` print heap dump to stdout when executing an instruction
@@ -208,11 +738,41 @@ impl VmConfig {
         VmConfig {
             source_type,
             file_name: file_name.to_string(),
+            gzip: false,
             heap_size,
             raw,
             debug,
             debug_heap,
             suppress_output,
+            word_size: WordSize::I32,
+            division_rounding: DivisionRounding::Truncate,
+            on_output_error: OutputErrorPolicy::Fail,
+            check_balanced_calls: false,
+            checked_arithmetic: false,
+            check_labels: false,
+            generated_input: None,
+            heap_dump_filter: HeapDumpFilter::NonZero,
+            heap_dump_range: None,
+            heap_dump_collapse: DEFAULT_HEAP_DUMP_COLLAPSE,
+            optimize: false,
+            output_encoding: OutputEncoding::Utf8,
+            output_buffering: Buffering::PerChar,
+            int_input: IntInputMode::Strict,
+            input_seed: None,
+            eof_value: None,
+            implicit_exit: false,
+            extensions: false,
+            history_len: DEFAULT_HISTORY_LEN,
+            int_width: None,
+            gas_budget: None,
+            max_output_bytes: None,
+            cost_model: CostModel::new(),
+            on_heap_write: None,
+            on_stack_change: None,
+            on_enter_label: None,
+            stack_growth_window: 0,
+            on_stack_growth: None,
+            syscalls: HashMap::new(),
         }
     }
 
@@ -221,15 +781,15 @@ impl VmConfig {
     /// - `file_name` the name of the source file on disk
     /// - `source_type` the type of the source
     pub fn default_heap(file_name: &str, source_type: SourceType) -> VmConfig {
-        VmConfig {
+        VmConfig::new(
+            file_name,
             source_type,
-            file_name: file_name.to_string(),
-            heap_size: DEFAULT_HEAP_SIZE,
-            raw: false,
-            debug: false,
-            debug_heap: false,
-            suppress_output: false,
-        }
+            DEFAULT_HEAP_SIZE,
+            false,
+            false,
+            false,
+            false,
+        )
     }
 
     /// Returns a default interpreter configuration with no heap
@@ -237,15 +797,7 @@ impl VmConfig {
     /// - `file_name` the name of the source file on disk
     /// - `source_type` the type of the source
     pub fn default_no_heap(file_name: &str, source_type: SourceType) -> VmConfig {
-        VmConfig {
-            source_type,
-            file_name: file_name.to_string(),
-            heap_size: 0,
-            raw: false,
-            debug: false,
-            debug_heap: false,
-            suppress_output: false,
-        }
+        VmConfig::new(file_name, source_type, 0, false, false, false, false)
     }
 
     /// Returns a default interpreter configuration with the default heap size, suppressing output
@@ -253,15 +805,15 @@ impl VmConfig {
     /// - `file_name` the name of the source file on disk
     /// - `source_type` the type of the source
     pub fn default_heap_suppressed(file_name: &str, source_type: SourceType) -> VmConfig {
-        VmConfig {
+        VmConfig::new(
+            file_name,
             source_type,
-            file_name: file_name.to_string(),
-            heap_size: DEFAULT_HEAP_SIZE,
-            raw: false,
-            debug: false,
-            debug_heap: false,
-            suppress_output: true,
-        }
+            DEFAULT_HEAP_SIZE,
+            false,
+            false,
+            false,
+            true,
+        )
     }
 
     /// Returns a default interpreter configuration with no heap, suppressing output
@@ -269,15 +821,7 @@ impl VmConfig {
     /// - `file_name` the name of the source file on disk
     /// - `source_type` the type of the source
     pub fn default_no_heap_suppressed(file_name: &str, source_type: SourceType) -> VmConfig {
-        VmConfig {
-            source_type,
-            file_name: file_name.to_string(),
-            heap_size: 0,
-            raw: false,
-            debug: false,
-            debug_heap: false,
-            suppress_output: true,
-        }
+        VmConfig::new(file_name, source_type, 0, false, false, false, true)
     }
 
     /// Returns a default debug interpreter configuration with the default heap size
@@ -285,15 +829,15 @@ impl VmConfig {
     /// - `file_name` the name of the source file on disk
     /// - `source_type` the type of the source
     pub fn debug_heap(file_name: &str, source_type: SourceType) -> VmConfig {
-        VmConfig {
+        VmConfig::new(
+            file_name,
             source_type,
-            file_name: file_name.to_string(),
-            heap_size: DEFAULT_HEAP_SIZE,
-            raw: false,
-            debug: true,
-            debug_heap: true,
-            suppress_output: false,
-        }
+            DEFAULT_HEAP_SIZE,
+            false,
+            true,
+            true,
+            false,
+        )
     }
 
     /// Returns a default debug interpreter configuration with no heap
@@ -301,15 +845,7 @@ impl VmConfig {
     /// - `file_name` the name of the source file on disk
     /// - `source_type` the type of the source
     pub fn debug_no_heap(file_name: &str, source_type: SourceType) -> VmConfig {
-        VmConfig {
-            source_type,
-            file_name: file_name.to_string(),
-            heap_size: 0,
-            raw: false,
-            debug: true,
-            debug_heap: false,
-            suppress_output: false,
-        }
+        VmConfig::new(file_name, source_type, 0, false, true, false, false)
     }
 
     /// Returns a default debug interpreter configuration to only compute the intermediate
@@ -318,769 +854,4118 @@ impl VmConfig {
     /// - `file_name` the name of the source file on disk
     /// - `source_type` the type of the source
     pub fn raw(file_name: &str, source_type: SourceType) -> VmConfig {
-        VmConfig {
-            source_type,
-            file_name: file_name.to_string(),
-            heap_size: 0,
-            raw: true,
-            debug: false,
-            debug_heap: false,
-            suppress_output: false,
-        }
+        VmConfig::new(file_name, source_type, 0, true, false, false, false)
     }
-}
 
-#[derive(Debug)]
-enum VmErrorKind {
-    TranslateError(ParseError),
-    ParseError(ParseError),
-    StackUnderflow(Instruction),
-    NumberOutOfBoundsError(Instruction, i32, i32, i32),
-    NoTermination(Instruction),
-    IOError(Instruction),
+    /// Sets whether the source file is gzip-compressed regardless of its extension. Files ending
+    /// in `.gz` are always decompressed.
+    ///
+    /// - `gzip` decompress the source file before parsing
+    pub fn set_gzip(&mut self, gzip: bool) {
+        self.gzip = gzip;
+    }
 }
 
-impl Display for VmErrorKind {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self)
+#[wasm_bindgen]
+impl VmConfig {
+    /// Sets the width of the cells on the stack and the heap, defaults to `WordSize::I32`
+    ///
+    /// - `word_size` the width of a single cell
+    pub fn set_word_size(&mut self, word_size: WordSize) {
+        self.word_size = word_size;
     }
-}
 
-impl VmErrorKind {
-    fn throw<T>(self) -> Result<T, VmError> {
-        let msg = match &self {
-            VmErrorKind::TranslateError(err) => format!("error during instruction translation: {}", err),
-            VmErrorKind::StackUnderflow(instr) => format!("stack is empty - failed executing: {:?}", instr),
-            VmErrorKind::NumberOutOfBoundsError(instr, num, low, high) => format!("number is out of bounds for: {:?}, expected in the closed interval bounded by {} and {}, but was {}", instr, low, high, num),
-            VmErrorKind::NoTermination(instr) => format!("no termination instruction after last executed instruction: {:?}", instr),
-            VmErrorKind::IOError(instr) => format!("stdin error when executing: {:?}", instr),
-            VmErrorKind::ParseError(err) => format!("parse error occurred: {}, {}", err.kind, err.msg)
+    /// Sets how `IntegerDivision` and `Modulo` round, defaults to `DivisionRounding::Truncate`
+    ///
+    /// - `division_rounding` the rounding of the quotient
+    pub fn set_division_rounding(&mut self, division_rounding: DivisionRounding) {
+        self.division_rounding = division_rounding;
+    }
+
+    /// Sets the options where whitespace interpreters disagree to match the given interpreter.
+    /// Options set afterwards override the profile. The profiles set
+    ///
+    /// | profile         | word size | division rounding | `eof_value` | `implicit_exit` |
+    /// |-----------------|-----------|-------------------|-------------|-----------------|
+    /// | `Spacey`        | `I32`     | `Truncate`        | none        | disabled        |
+    /// | `WspaceHaskell` | `I64`     | `Floor`           | none        | disabled        |
+    ///
+    /// `wspace` uses arbitrary precision integers, `I64` is the closest word size. Both profiles
+    /// count `CopyNthStack` from the top of the stack starting at 0, let `SlideNStack` keep the top
+    /// value and parse a number without bits as 0, so these are not configurable.
+    ///
+    /// - `profile` the interpreter to match
+    pub fn set_compat_profile(&mut self, profile: CompatProfile) {
+        let (word_size, division_rounding) = match profile {
+            CompatProfile::Spacey => (WordSize::I32, DivisionRounding::Truncate),
+            CompatProfile::WspaceHaskell => (WordSize::I64, DivisionRounding::Floor),
         };
-        Err(VmError { msg, kind: self })
+        self.word_size = word_size;
+        self.division_rounding = division_rounding;
+        self.eof_value = None;
+        self.implicit_exit = false;
     }
-}
 
-#[derive(Debug)]
-#[allow(dead_code)]
-pub struct VmError {
-    msg: String,
-    kind: VmErrorKind,
-}
+    /// Sets what happens when writing the program output fails, defaults to
+    /// `OutputErrorPolicy::Fail`
+    ///
+    /// - `policy` the policy to apply on output errors
+    pub fn set_on_output_error(&mut self, policy: OutputErrorPolicy) {
+        self.on_output_error = policy;
+    }
 
-impl Into<JsValue> for VmError {
-    fn into(self) -> JsValue {
-        JsValue::from(format!("spacey error occured: {}, {}", self.kind, self.msg))
+    /// Sets whether every `Call` must have returned when the program exits, disabled by default
+    ///
+    /// - `check_balanced_calls` fail with an error if the call stack is not empty on exit
+    pub fn set_check_balanced_calls(&mut self, check_balanced_calls: bool) {
+        self.check_balanced_calls = check_balanced_calls;
     }
-}
 
-impl Display for VmError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self)
+    /// Sets whether arithmetic results that do not fit into a word are an error instead of
+    /// wrapping around, disabled by default
+    ///
+    /// - `checked_arithmetic` fail with an error on arithmetic overflow
+    pub fn set_checked_arithmetic(&mut self, checked_arithmetic: bool) {
+        self.checked_arithmetic = checked_arithmetic;
     }
-}
 
-#[wasm_bindgen]
-impl Vm {
-    /// Creates a new interpreter with the given arguments
+    /// Sets whether undefined and duplicate labels are an error when loading the program instead
+    /// of being resolved leniently, disabled by default
     ///
-    /// - `config` The configuration of the interpreter
-    pub fn new(config: VmConfig) -> Result<Vm, VmError> {
-        #[cfg(not(target_arch = "wasm32"))]
-        let source = &config.file_name;
-        #[cfg(target_arch = "wasm32")]
-        let source = &config.source;
-        let mut parser: Box<dyn Parser> = match config.source_type {
-            SourceType::Whitespace => match WsParser::new(source) {
-                Ok(content) => content,
-                Err(err) => return VmErrorKind::ParseError(err).throw(),
-            },
-            SourceType::Malbolge => unimplemented!(),
-            SourceType::Brainfuck => unimplemented!(),
+    /// - `check_labels` fail with an error on undefined or duplicate labels
+    pub fn set_check_labels(&mut self, check_labels: bool) {
+        self.check_labels = check_labels;
+    }
+
+    /// Sets which heap cells the heap dump includes, defaults to `HeapDumpFilter::NonZero`
+    ///
+    /// - `filter` the cells to include in the heap dump
+    pub fn set_heap_dump_filter(&mut self, filter: HeapDumpFilter) {
+        self.heap_dump_filter = filter;
+    }
+
+    /// Restricts the heap dump to the addresses in the given range, defaults to the entire heap
+    ///
+    /// - `start` the first address to include
+    /// - `end` the address after the last one to include
+    pub fn set_heap_dump_range(&mut self, start: usize, end: usize) {
+        self.heap_dump_range = Some((start, end));
+    }
+
+    /// Sets from which length runs of consecutive zero cells are collapsed into a single line
+    /// naming the skipped addresses in the heap dump, like `*` in `xxd`, defaults to
+    /// `DEFAULT_HEAP_DUMP_COLLAPSE`. 0 never collapses.
+    ///
+    /// - `threshold` the minimum number of zero cells to collapse
+    pub fn set_heap_dump_collapse(&mut self, threshold: usize) {
+        self.heap_dump_collapse = threshold;
+    }
+
+    /// Sets how `OutCharacter` serializes a character, defaults to `OutputEncoding::Utf8`.
+    /// `OutInteger` always writes its digits as ASCII.
+    ///
+    /// - `encoding` the encoding of the characters written
+    pub fn set_output_encoding(&mut self, encoding: OutputEncoding) {
+        self.output_encoding = encoding;
+    }
+
+    /// Sets when the output is flushed, defaults to `Buffering::PerChar`. `Buffering::Block` is
+    /// much faster for programs writing a lot of output.
+    ///
+    /// - `buffering` when to flush the output
+    pub fn set_output_buffering(&mut self, buffering: Buffering) {
+        self.output_buffering = buffering;
+    }
+
+    /// Sets how `ReadInteger` treats tokens that are not integers, defaults to
+    /// `IntInputMode::Strict`. Truncated numbers that do not fit into a word are an error.
+    ///
+    /// - `int_input` whether decimal numbers are accepted and truncated
+    pub fn set_int_input(&mut self, int_input: IntInputMode) {
+        self.int_input = int_input;
+    }
+
+    /// Sets whether pairs of instructions without effect, e.g. `push 0; add`, are removed before
+    /// running the program, disabled by default. Removed instructions are not counted as
+    /// executed and shift the indices of the instructions after them.
+    ///
+    /// - `optimize` remove instructions without effect
+    pub fn set_optimize(&mut self, optimize: bool) {
+        self.optimize = optimize;
+    }
+
+    /// Makes the program read the given line over and over instead of reading from the terminal,
+    /// so input-driven programs can be run without blocking, e.g. in benchmarks. The input starts
+    /// over from the beginning of the line on every reset.
+    ///
+    /// - `line` the line to read, without the trailing line feed
+    pub fn set_generated_input(&mut self, line: &str) {
+        self.generated_input = Some(line.to_string());
+    }
+
+    /// Makes the program read pseudo-random numbers between 0 and 9999, one per line, instead of
+    /// reading from the terminal. The same seed always yields the same input, which starts over on
+    /// every reset. Generated input set with `set_generated_input` takes precedence.
+    ///
+    /// - `seed` the seed of the pseudo-random numbers
+    pub fn set_input_seed(&mut self, seed: u64) {
+        self.input_seed = Some(seed);
+    }
+
+    /// Makes `ReadCharacter` and `ReadInteger` store the given value instead of failing when the
+    /// input has ended, like `getchar()` returning `EOF` in C
+    ///
+    /// - `eof_value` the value to store at the end of the input
+    pub fn set_eof_value(&mut self, eof_value: i32) {
+        self.eof_value = Some(eof_value);
+    }
+
+    /// Left-pads the output of `OutInteger` with spaces to at least the given width, like `%5d`
+    /// in `printf`. Wider numbers are written in full.
+    ///
+    /// - `int_width` the minimum width of integer output
+    pub fn set_int_width(&mut self, int_width: usize) {
+        self.int_width = Some(int_width);
+    }
+
+    /// Sets the gas available to the program. Every executed instruction is charged the gas of
+    /// the cost model, 1 per instruction by default. An instruction that costs more than the
+    /// remaining gas fails with an error instead of being executed.
+    ///
+    /// - `gas_budget` the total gas the program may use
+    pub fn set_gas_budget(&mut self, gas_budget: u64) {
+        self.gas_budget = Some(gas_budget);
+    }
+
+    /// Limits the number of bytes the program may write. Output that would pass the limit is not
+    /// written, the program fails with an error instead. Unlimited by default.
+    ///
+    /// - `max_output_bytes` the maximum number of bytes written in total
+    pub fn set_max_output_bytes(&mut self, max_output_bytes: u64) {
+        self.max_output_bytes = Some(max_output_bytes);
+    }
+
+    /// Enables or disables ending the program by running past its last instruction, as some
+    /// lenient interpreters allow. By default a program has to execute an `Exit`.
+    ///
+    /// - `implicit_exit` whether running past the last instruction terminates cleanly
+    pub fn set_implicit_exit(&mut self, implicit_exit: bool) {
+        self.implicit_exit = implicit_exit;
+    }
+
+    /// Sets how many of the last executed instruction indices a `NoTermination` error reports,
+    /// defaults to `DEFAULT_HISTORY_LEN`. 0 stops recording them.
+    ///
+    /// - `history_len` the number of instruction indices to report
+    pub fn set_history_len(&mut self, history_len: usize) {
+        self.history_len = history_len;
+    }
+
+    /// Enables or disables the dialect extensions, instructions that are not part of the
+    /// whitespace language. Programs using them fail to load unless enabled. The extensions are
+    ///
+    /// - `JumpDynamic`, encoded as `[LF][LF][Space]`, pops the index of the next instruction to
+    ///   execute from the stack, e.g. to implement jump tables. Indices count every instruction
+    ///   of the parsed program starting at 0, so programs using it are never optimized.
+    /// - `Syscall`, encoded as `[LF][LF][Tab]` followed by a number, calls the host function
+    ///   registered under the number with `register_syscall`.
+    ///
+    /// - `extensions` whether to run programs using the dialect extensions
+    pub fn set_extensions(&mut self, extensions: bool) {
+        self.extensions = extensions;
+    }
+
+    /// Enables or disables the strictest interpretation of the program. Strict mode enables
+    /// `checked_arithmetic` and `check_labels`, removes the `eof_value`, so reading past the end
+    /// of the input is an error, and disables `implicit_exit`, so the program has to execute an
+    /// `Exit`. Options set afterwards override strict mode, like a compat profile. Division and
+    /// modulo by zero are always errors.
+    ///
+    /// - `strict` whether to interpret the program strictly
+    pub fn set_strict(&mut self, strict: bool) {
+        self.checked_arithmetic = strict;
+        self.check_labels = strict;
+        if strict {
+            self.eof_value = None;
+            self.implicit_exit = false;
+        }
+    }
+}
+
+impl VmConfig {
+    /// Sets the gas charged per kind of instruction, only used together with a gas budget
+    ///
+    /// - `cost_model` the gas charged per kind of instruction
+    pub fn set_cost_model(&mut self, cost_model: CostModel) {
+        self.cost_model = cost_model;
+    }
+
+    /// Calls the given function with the address and the new value on every write to the heap,
+    /// by `StoreHeap` as well as by reading input
+    ///
+    /// - `on_heap_write` the function to call on heap writes
+    pub fn set_on_heap_write(&mut self, on_heap_write: impl FnMut(usize, i64) + Send + 'static) {
+        self.on_heap_write = Some(Box::new(on_heap_write));
+    }
+
+    /// Calls the given function with the new stack depth after every instruction that changed it
+    ///
+    /// - `on_stack_change` the function to call on stack depth changes
+    pub fn set_on_stack_change(&mut self, on_stack_change: impl FnMut(usize) + Send + 'static) {
+        self.on_stack_change = Some(Box::new(on_stack_change));
+    }
+
+    /// Calls the given function with the label name whenever execution enters the region of
+    /// another label, e.g. to attribute instructions to subroutines in a profiler. A label's
+    /// region reaches from its `Mark` to the next one. Regions are entered by falling through the
+    /// `Mark` as well as by jumping, calling or returning into them, so every call to a subroutine
+    /// and every return into one reports it. Jumping within the region, e.g. in a loop, does not.
+    ///
+    /// - `on_enter_label` the function to call with the raw space and tab name of the label
+    pub fn set_on_enter_label(&mut self, on_enter_label: impl FnMut(&str) + Send + 'static) {
+        self.on_enter_label = Some(Box::new(on_enter_label));
+    }
+
+    /// Calls the given function with the stack depth when `run` sees the stack grow in several
+    /// consecutive windows of the given number of instructions, a hint that the program leaks
+    /// stack items. Unlike a limit, the program keeps running; the function is called again after
+    /// as many further windows of growth. Disabled by default.
+    ///
+    /// - `window` the number of instructions between two checks of the stack depth
+    /// - `on_stack_growth` the function to call with the stack depth on suspicious growth
+    pub fn set_on_stack_growth(
+        &mut self,
+        window: u64,
+        on_stack_growth: impl FnMut(usize) + Send + 'static,
+    ) {
+        self.stack_growth_window = window.max(1);
+        self.on_stack_growth = Some(Box::new(on_stack_growth));
+    }
+
+    /// Registers a host function called by the `Syscall` extension with the given number, e.g. to
+    /// give embedded programs access to the time or to random numbers. The function pops its
+    /// arguments from the stack and pushes its results, which are fit into the word size like the
+    /// results of arithmetic. Replaces a function registered before under the same number.
+    /// Programs using syscalls only run with `set_extensions`.
+    ///
+    /// - `number` the parameter of the `Syscall` instructions calling the function
+    /// - `syscall` the host function, called with the stack, top last
+    pub fn register_syscall(
+        &mut self,
+        number: i64,
+        syscall: impl FnMut(&mut Vec<i64>) + Send + 'static,
+    ) {
+        self.syscalls.insert(number, Box::new(syscall));
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum VmErrorKind {
+    TranslateError(Box<ParseError>),
+    ParseError(Box<ParseError>),
+    StackUnderflow(Instruction),
+    InsufficientStack(Instruction, usize, usize),
+    NumberOutOfBoundsError(Instruction, i64, i64, i64),
+    NoTermination(Option<Instruction>, usize, Vec<usize>),
+    IOError(Instruction),
+    UndefinedLabel(Instruction),
+    DuplicateLabel(Instruction),
+    UnreachableExit,
+    LabelNotFound(String),
+    NoReturn(String),
+    SeekOutOfBounds(usize, usize),
+    InitialHeapTooLarge(usize, usize),
+    UnsupportedSourceType,
+    UnknownSyscall(Instruction),
+    LabelCollision(String),
+    CarriageReturns(Vec<usize>),
+    InvalidLabelName(String),
+    UnbalancedCalls(usize),
+    DivisionByZero(Instruction),
+    ArithmeticOverflow(Instruction),
+    EndOfInput(Instruction),
+    HeapDisabled(Instruction),
+    OutOfGas(Instruction, u64),
+    OutputLimitExceeded(Instruction, u64),
+    ExtensionDisabled(Instruction),
+    Io(std::io::Error),
+    InvalidIntegerInput(ParseIntError),
+}
+
+impl Display for VmErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl VmErrorKind {
+    pub(crate) fn throw<T>(self) -> Result<T, VmError> {
+        Err(self.error())
+    }
+
+    pub(crate) fn error(self) -> VmError {
+        let msg = match &self {
+            VmErrorKind::TranslateError(err) => format!("error during instruction translation: {}", err),
+            VmErrorKind::StackUnderflow(instr) => format!("stack is empty - failed executing: {:?}", instr),
+            VmErrorKind::InsufficientStack(instr, needed, found) => format!("{:?} needs {} value(s) on the stack, found {}", instr, needed, found),
+            VmErrorKind::NumberOutOfBoundsError(instr, num, low, high) => format!("number is out of bounds for: {:?}, expected in the closed interval bounded by {} and {}, but was {}", instr, low, high, num),
+            VmErrorKind::NoTermination(None, ..) => "no termination instruction, the program ended without executing any instruction".to_string(),
+            VmErrorKind::NoTermination(Some(instr), depth, history) => format!("no termination instruction after last executed instruction: {:?}, with call depth {} after executing the instructions {:?}", instr, depth, history),
+            VmErrorKind::IOError(instr) => format!("stdin error when executing: {:?}", instr),
+            VmErrorKind::ParseError(err) => format!("parse error occurred: {}, {}", err.kind, err.msg),
+            VmErrorKind::UndefinedLabel(instr) => format!("label is never marked: {:?}", instr),
+            VmErrorKind::DuplicateLabel(instr) => format!("label is marked more than once: {:?}", instr),
+            VmErrorKind::DivisionByZero(instr) => format!("division by zero when executing: {:?}", instr),
+            VmErrorKind::ArithmeticOverflow(instr) => format!("result does not fit into a word when executing: {:?}", instr),
+            VmErrorKind::EndOfInput(instr) => format!("input ended when executing: {:?}", instr),
+            VmErrorKind::HeapDisabled(instr) => format!("the heap is disabled (heap size 0) - failed executing: {:?}", instr),
+            VmErrorKind::OutOfGas(instr, remaining) => format!("gas budget exhausted with {} gas remaining - failed executing: {:?}", remaining, instr),
+            VmErrorKind::OutputLimitExceeded(instr, limit) => format!("output would exceed the limit of {} bytes - failed executing: {:?}", limit, instr),
+            VmErrorKind::ExtensionDisabled(instr) => format!("the instruction is a dialect extension, which is disabled: {:?}", instr),
+            VmErrorKind::Io(err) => format!("input/output error: {}", err),
+            VmErrorKind::InvalidIntegerInput(err) => format!("input is not a valid integer: {}", err),
+            VmErrorKind::UnbalancedCalls(depth) => format!("program exited with {} call(s) that never returned", depth),
+            VmErrorKind::LabelNotFound(label) => format!("no label named {:?} in the program", label),
+            VmErrorKind::CarriageReturns(positions) => format!("source contains {} carriage return(s), e.g. from CRLF line endings, which some interpreters reject, first at position {}", positions.len(), positions[0]),
+            VmErrorKind::UnknownSyscall(instr) => format!("no host function is registered for: {:?}", instr),
+            VmErrorKind::NoReturn(label) => format!("the subroutine at label {:?} ended the program instead of returning", label),
+            VmErrorKind::SeekOutOfBounds(index, len) => format!("cannot seek to instruction {}, the program has {} instruction(s)", index, len),
+            VmErrorKind::InitialHeapTooLarge(len, heap_size) => format!("the initial heap has {} cell(s), the heap size is {}", len, heap_size),
+            VmErrorKind::UnsupportedSourceType => "only whitespace sources can be parsed".to_string(),
+            VmErrorKind::LabelCollision(label) => format!("more than one label would be named {:?}", label),
+            VmErrorKind::InvalidLabelName(label) => format!("label name {:?} is not made of spaces and tabs", label),
+            VmErrorKind::UnreachableExit => "no exit instruction is reachable from the start of the program".to_string(),
         };
-        let mut instructions = vec![];
-        for instr in &mut parser {
-            let instr = match instr {
-                Ok(content) => content,
-                Err(err) => return VmErrorKind::ParseError(err).throw(),
-            };
-            if config.raw {
-                dbg!(&instr);
+        VmError {
+            msg,
+            kind: self,
+            output: Box::default(),
+        }
+    }
+}
+
+#[derive(Debug)]
+#[allow(dead_code)]
+pub struct VmError {
+    pub(crate) msg: String,
+    pub(crate) kind: VmErrorKind,
+    pub(crate) output: Box<[u8]>,
+}
+
+impl VmError {
+    /// Whether the error is the input reporting that no input is available yet, e.g. an empty
+    /// `InputQueue`. Executing the read instruction again continues the read.
+    pub fn would_block(&self) -> bool {
+        matches!(&self.kind, VmErrorKind::Io(err) if err.kind() == ErrorKind::WouldBlock)
+    }
+
+    /// Returns the output the program produced before the error occurred. Only filled in by
+    /// `execute`, an interpreter capturing its output keeps it available via `take_output`.
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+}
+
+impl Into<JsValue> for VmError {
+    fn into(self) -> JsValue {
+        JsValue::from(format!("spacey error occured: {}, {}", self.kind, self.msg))
+    }
+}
+
+impl From<std::io::Error> for VmError {
+    fn from(err: std::io::Error) -> Self {
+        VmErrorKind::Io(err).error()
+    }
+}
+
+impl From<ParseIntError> for VmError {
+    fn from(err: ParseIntError) -> Self {
+        VmErrorKind::InvalidIntegerInput(err).error()
+    }
+}
+
+impl From<ParseError> for VmError {
+    fn from(err: ParseError) -> Self {
+        VmErrorKind::ParseError(Box::new(err)).error()
+    }
+}
+
+impl Display for VmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+#[wasm_bindgen]
+impl Vm {
+    /// Creates a new interpreter with the given arguments
+    ///
+    /// - `config` The configuration of the interpreter
+    pub fn new(config: VmConfig) -> Result<Vm, VmError> {
+        let program = Vm::parse(&config)?;
+
+        Vm::from_program(config, program)
+    }
+    /// Returns the next instruction to be executed in a `Some` variant. None if the program has
+    /// reached its end.
+    pub fn next_instruction(&self) -> Option<usize> {
+        if self.done {
+            return None;
+        }
+        if self.instruction_pointer < self.instructions.len() {
+            Some(self.instruction_pointer)
+        } else {
+            None
+        }
+    }
+
+    /// Returns whether the program has terminated, either by executing an `Exit` or by running
+    /// past its last instruction
+    pub fn is_done(&self) -> bool {
+        self.next_instruction().is_none()
+    }
+
+    /// Returns the index of the instruction to be executed next. Past the last instruction once
+    /// the program ran off its end, see `next_instruction` for whether the program is done.
+    pub fn instruction_pointer(&self) -> usize {
+        self.streamed + self.instruction_pointer
+    }
+
+    /// Moves execution to the instruction at the given index, e.g. to jump to an instruction in a
+    /// debugger. The stacks and the heap are left as they are. Seeking into a finished program
+    /// resumes it.
+    ///
+    /// - `index` the index of the instruction to execute next
+    pub fn seek(&mut self, index: usize) -> Result<(), VmError> {
+        if index >= self.instructions.len() {
+            return VmErrorKind::SeekOutOfBounds(index, self.instructions.len()).throw();
+        }
+        self.instruction_pointer = index;
+        self.done = false;
+
+        Ok(())
+    }
+
+    /// Executes the next instruction, if there is one. Returns whether an instruction was executed.
+    /// If the input would block, the error reports `would_block` and the same instruction is
+    /// executed again by the next step.
+    pub fn step(&mut self) -> Result<bool, VmError> {
+        if self.is_done() {
+            return Ok(false);
+        }
+        self.exec()?;
+
+        Ok(true)
+    }
+
+    /// Executes all instructions - runs the program.
+    pub fn run(&mut self) -> Result<(), VmError> {
+        let mut executed = 0;
+        let mut depths = VecDeque::new();
+        let mut stream = self.open_stream()?;
+        loop {
+            while let Some(_) = self.next_instruction() {
+                self.exec()?;
+                if self.config.on_stack_growth.is_some() {
+                    executed += 1;
+                    if executed % self.config.stack_growth_window == 0 {
+                        self.check_stack_growth(&mut depths);
+                    }
+                }
+            }
+            if !self.load_streamed(&mut stream)? {
+                break;
+            }
+        }
+
+        self.check_termination()
+    }
+
+    /// Parses the source again if the program is run while parsing it, skipping the instructions
+    /// already loaded, so an interrupted run, e.g. by input that would block, continues after them
+    fn open_stream(&self) -> Result<Option<Box<dyn Parser>>, VmError> {
+        if !self.streaming || self.done {
+            return Ok(None);
+        }
+        let mut parser = Vm::parser(&self.config)?;
+        for _ in 0..self.streamed + self.instructions.len() {
+            if let Some(next) = Program::next_instruction(&mut parser, false) {
+                next?;
+            }
+        }
+
+        Ok(Some(parser))
+    }
+
+    /// Replaces the executed instruction with the next one parsed from the stream, if the program
+    /// is run while parsing it. Returns whether there was one.
+    fn load_streamed(&mut self, stream: &mut Option<Box<dyn Parser>>) -> Result<bool, VmError> {
+        if self.done {
+            return Ok(false);
+        }
+        let next = match stream {
+            Some(stream) => Program::next_instruction(stream, self.config.raw),
+            None => return Ok(false),
+        };
+        match next {
+            Some(next) => {
+                let instr = next?.0;
+                self.streamed += self.instructions.len();
+                self.instructions = vec![instr];
+                self.instruction_pointer = 0;
+
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Records the stack depth at the end of a window and reports it once the stack grew in every
+    /// one of the last `STACK_GROWTH_WINDOWS` windows
+    fn check_stack_growth(&mut self, depths: &mut VecDeque<usize>) {
+        if depths.len() == STACK_GROWTH_WINDOWS + 1 {
+            depths.pop_front();
+        }
+        depths.push_back(self.stack.len());
+        let growing = depths.len() == STACK_GROWTH_WINDOWS + 1
+            && depths.iter().zip(depths.iter().skip(1)).all(|(a, b)| a < b);
+        if let (true, Some(on_stack_growth)) = (growing, &mut self.config.on_stack_growth) {
+            on_stack_growth(self.stack.len());
+            // the next report needs as many windows of growth again
+            depths.clear();
+            depths.push_back(self.stack.len());
+        }
+    }
+
+    /// Executes at most `n` instructions and leaves the interpreter as it is afterwards, e.g. to
+    /// compare the state after a fixed number of steps against a reference. Stops early once the
+    /// program terminated, which is checked like by `run`.
+    ///
+    /// - `n` the maximum number of instructions to execute
+    pub fn run_n(&mut self, n: u64) -> Result<(), VmError> {
+        for _ in 0..n {
+            if !self.step()? {
+                break;
             }
-            let instr = match instr.translate() {
-                Ok(instr) => instr,
-                Err(err) => return VmErrorKind::TranslateError(err).throw(),
+        }
+        if self.is_done() {
+            self.check_termination()?;
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the program stopped by executing an `Exit`, wherever it is in the program,
+    /// unless `implicit_exit` is enabled. Instructions after the `Exit`, e.g. trailing labels, are
+    /// never required to be executed. A missing `Exit` is reported with the call depth and the
+    /// last executed instructions, since runaway recursion often ends up running off the end. A
+    /// program that executed nothing, e.g. an empty one, did not exit either.
+    fn check_termination(&self) -> Result<(), VmError> {
+        if !self.done && !self.config.implicit_exit {
+            return VmErrorKind::NoTermination(
+                self.last_executed
+                    .and_then(|last| self.instructions.get(last - self.streamed))
+                    .cloned(),
+                self.call_stack.len(),
+                self.history.iter().copied().collect(),
+            )
+            .throw();
+        }
+        if self.config.check_balanced_calls && !self.call_stack.is_empty() {
+            return VmErrorKind::UnbalancedCalls(self.call_stack.len()).throw();
+        }
+
+        Ok(())
+    }
+
+    /// Resets the internal interpreter state/the VM without re-parsing the source file
+    pub fn reset(&mut self) {
+        self.reset_keep_heap();
+        self.heap.fill(0);
+        self.written.clear();
+        self.heap_journal = None;
+    }
+
+    /// Resets the interpreter like `reset`, but leaves the heap intact, e.g. to run the program
+    /// again on heap data loaded once. The stack, the call stack, the instruction pointer, the
+    /// pending input, the gas and the output limit start over. Heap checkpoints stay valid.
+    pub fn reset_keep_heap(&mut self) {
+        self.stack.clear();
+        self.call_stack.clear();
+        self.instruction_pointer = 0;
+        self.done = false;
+        self.last_executed = None;
+        self.history.clear();
+        self.region = None;
+        self.pending_token.clear();
+        self.pending_character.clear();
+        self.gas = self.config.gas_budget;
+        self.output.reset_limit();
+        if self.streaming {
+            self.streamed = 0;
+            self.instructions.clear();
+        }
+        self.generate_input();
+    }
+
+    /// Restarts the generated or seeded input, if one is configured
+    fn generate_input(&mut self) {
+        if let Some(line) = &self.config.generated_input {
+            self.input = Some(Box::new(GeneratedInput::new(line)));
+        } else if let Some(seed) = self.config.input_seed {
+            self.input = Some(Box::new(SeededInput::new(seed)));
+        }
+    }
+
+    /// Truncates a value to the configured word size, wrapping around on overflow
+    fn wrap(&self, value: i64) -> i64 {
+        match self.config.word_size {
+            WordSize::I32 => value as i32 as i64,
+            WordSize::I64 => value,
+        }
+    }
+
+    /// Fits the exact result of an arithmetic instruction into the configured word size, wrapping
+    /// around on overflow unless arithmetic is checked
+    fn arithmetic_result(&self, value: i128) -> Result<i64, VmError> {
+        let fits = match self.config.word_size {
+            WordSize::I32 => i32::try_from(value).is_ok(),
+            WordSize::I64 => i64::try_from(value).is_ok(),
+        };
+        if !fits && self.config.checked_arithmetic {
+            return VmErrorKind::ArithmeticOverflow(
+                self.instructions[self.instruction_pointer].clone(),
+            )
+            .throw();
+        }
+
+        Ok(self.wrap(value as i64))
+    }
+
+    /// Collects the heap cells selected by the heap dump filter and range. The dump is ordered by
+    /// address, independent of the order of the writes, so dumps of the same program are
+    /// reproducible and can be diffed.
+    fn generate_debug_heap_dump(&self) -> BTreeMap<usize, i64> {
+        let (start, end) = self.config.heap_dump_range.unwrap_or((0, self.heap_size));
+        let end = end.min(self.heap_size);
+        let mut heap_map = BTreeMap::new();
+        for addr in start.min(end)..end {
+            let val = self.heap.get(addr).copied().unwrap_or_default();
+            let include = match self.config.heap_dump_filter {
+                HeapDumpFilter::NonZero => val != 0,
+                HeapDumpFilter::Written => self.written.contains(&addr),
+                HeapDumpFilter::All => true,
             };
-            instructions.push(instr);
+            if include {
+                heap_map.insert(addr, val);
+            }
         }
-        let stack = vec![];
-        let call_stack = vec![];
-        let heap = vec![0; config.heap_size];
-        let mut labels = HashMap::new();
-        let instruction_pointer = 0;
-        let done = false;
+        heap_map
+    }
 
-        for (i, instr) in instructions.iter().enumerate() {
-            match instr {
-                Instruction::Mark(label) => {
-                    labels.insert(label.value.clone(), i);
+    /// Formats the heap dump one cell per line, ordered by address, showing the value and, for
+    /// printable ASCII, its character. Runs of consecutive zero cells of at least the collapse
+    /// threshold are written as a single line with the range of skipped addresses, so the dump
+    /// stays readable for large heaps and still describes every cell.
+    pub fn format_heap_dump(&self) -> String {
+        let cells: Vec<(usize, i64)> = self.generate_debug_heap_dump().into_iter().collect();
+        let mut out = String::new();
+        let mut index = 0;
+        while index < cells.len() {
+            let (addr, val) = cells[index];
+            let zeros = cells[index..]
+                .iter()
+                .enumerate()
+                .take_while(|(offset, cell)| **cell == (addr + offset, 0))
+                .count();
+            if self.config.heap_dump_collapse > 0 && zeros >= self.config.heap_dump_collapse {
+                out.push_str(&format!(
+                    "* {}..{}: {} zero cells\n",
+                    addr,
+                    addr + zeros,
+                    zeros
+                ));
+                index += zeros;
+                continue;
+            }
+            match u8::try_from(val) {
+                Ok(byte) if byte.is_ascii_graphic() || byte == b' ' => {
+                    out.push_str(&format!("{}: {} '{}'\n", addr, val, byte as char))
                 }
-                _ => (),
+                _ => out.push_str(&format!("{}: {}\n", addr, val)),
             }
+            index += 1;
+        }
+
+        out
+    }
+
+    /// Checks that the address popped off the stack points into the heap
+    fn heap_address(&self, addr: i64) -> Result<usize, VmError> {
+        if self.heap_size == 0 {
+            return VmErrorKind::HeapDisabled(self.instructions[self.instruction_pointer].clone())
+                .throw();
         }
 
-        for instr in &mut instructions {
-            match instr {
-                Instruction::Mark(label)
-                | Instruction::Call(label)
-                | Instruction::Jump(label)
-                | Instruction::JumpZero(label)
-                | Instruction::JumpNegative(label) => {
-                    if let Some(index) = labels.get(&label.value) {
-                        label.index = *index;
-                    }
+        self.index(addr, self.heap_size)
+    }
+
+    /// Checks that the value is an index below `len`, see `checked_index`
+    fn index(&self, value: i64, len: usize) -> Result<usize, VmError> {
+        match checked_index(value, len) {
+            Some(index) => Ok(index),
+            None => VmErrorKind::NumberOutOfBoundsError(
+                self.instructions[self.instruction_pointer].clone(),
+                value,
+                0,
+                i64::try_from(len).unwrap_or(i64::MAX) - 1,
+            )
+            .throw(),
+        }
+    }
+
+    /// Returns the value of the heap cell, `None` outside of the heap. Cells of a heap that has
+    /// not been allocated yet are 0.
+    fn heap_cell(&self, addr: usize) -> Option<i64> {
+        (addr < self.heap_size).then(|| self.heap.get(addr).copied().unwrap_or_default())
+    }
+
+    /// Stores a value on the heap, remembering the address as written if the heap dump needs it.
+    /// The heap is only allocated by the first write, so programs that never touch the heap do
+    /// not pay for it.
+    fn write_heap(&mut self, addr: usize, val: i64) {
+        if self.heap.len() < self.heap_size {
+            self.heap.resize(self.heap_size, 0);
+        }
+        if let Some(journal) = &mut self.heap_journal {
+            journal.push((addr, self.heap[addr], self.written.contains(&addr)));
+        }
+        self.heap[addr] = val;
+        if self.config.heap_dump_filter == HeapDumpFilter::Written {
+            self.written.insert(addr);
+        }
+        if let Some(on_heap_write) = &mut self.config.on_heap_write {
+            on_heap_write(addr, val);
+        }
+    }
+
+    fn push_stack(&mut self) -> Result<(), VmError> {
+        if let Instruction::PushStack(num) = self.instructions[self.instruction_pointer] {
+            self.stack.push(self.wrap(num.value));
+
+            return Ok(());
+        };
+
+        unreachable!();
+    }
+
+    fn duplicate_stack(&mut self) -> Result<(), VmError> {
+        if let Some(val) = self.stack.last() {
+            self.stack.push(*val);
+
+            return Ok(());
+        }
+
+        VmErrorKind::InsufficientStack(self.instructions[self.instruction_pointer].clone(), 1, 0)
+            .throw()
+    }
+
+    fn copy_nth_stack(&mut self) -> Result<(), VmError> {
+        if let Instruction::CopyNthStack(num) = self.instructions[self.instruction_pointer] {
+            let addr = self.index(num.value, self.stack.len())?;
+            // counted from the top of the stack like the reference interpreter: `copy 0`
+            // duplicates the top, `copy len-1` copies the bottom and `copy len` is out of bounds
+            let addr = self.stack.len() - 1 - addr;
+            let val = self.stack[addr];
+            self.stack.push(val);
+
+            return Ok(());
+        }
+
+        unreachable!();
+    }
+
+    fn swap_stack(&mut self) -> Result<(), VmError> {
+        let len = self.stack.len();
+        if len < 2 {
+            return VmErrorKind::InsufficientStack(
+                self.instructions[self.instruction_pointer].clone(),
+                2,
+                len,
+            )
+            .throw();
+        }
+        self.stack.swap(len - 1, len - 2);
+
+        Ok(())
+    }
+
+    fn discard_stack(&mut self) -> Result<(), VmError> {
+        if self.stack.pop().is_some() {
+            return Ok(());
+        }
+
+        VmErrorKind::StackUnderflow(self.instructions[self.instruction_pointer].clone()).throw()
+    }
+
+    /// Discards `n` values below the top of the stack. A negative `n` is an error that leaves the
+    /// stack untouched. Like the reference interpreter, sliding more values than there are below
+    /// the top is not an error, it discards all of them and keeps only the top.
+    fn slide_n_stack(&mut self) -> Result<(), VmError> {
+        if let Instruction::SlideNStack(num) = self.instructions[self.instruction_pointer] {
+            if num.value < 0 {
+                return VmErrorKind::NumberOutOfBoundsError(
+                    self.instructions[self.instruction_pointer].clone(),
+                    num.value,
+                    0,
+                    i64::MAX,
+                )
+                .throw();
+            }
+            if let Some(top) = self.stack.pop() {
+                let count = usize::try_from(num.value).unwrap_or(usize::MAX);
+                self.stack.truncate(self.stack.len().saturating_sub(count));
+                self.stack.push(top);
+
+                return Ok(());
+            }
+
+            return VmErrorKind::StackUnderflow(
+                self.instructions[self.instruction_pointer].clone(),
+            )
+            .throw();
+        };
+
+        unreachable!();
+    }
+
+    fn add(&mut self) -> Result<(), VmError> {
+        if let Some(right) = self.stack.pop() {
+            if let Some(left) = self.stack.pop() {
+                let result = self.arithmetic_result(left as i128 + right as i128)?;
+                self.stack.push(result);
+
+                return Ok(());
+            }
+        }
+
+        VmErrorKind::StackUnderflow(self.instructions[self.instruction_pointer].clone()).throw()
+    }
+
+    fn subtract(&mut self) -> Result<(), VmError> {
+        if let Some(right) = self.stack.pop() {
+            if let Some(left) = self.stack.pop() {
+                let result = self.arithmetic_result(left as i128 - right as i128)?;
+                self.stack.push(result);
+
+                return Ok(());
+            }
+        }
+
+        VmErrorKind::StackUnderflow(self.instructions[self.instruction_pointer].clone()).throw()
+    }
+
+    fn multiply(&mut self) -> Result<(), VmError> {
+        if let Some(right) = self.stack.pop() {
+            if let Some(left) = self.stack.pop() {
+                let result = self.arithmetic_result(left as i128 * right as i128)?;
+                self.stack.push(result);
+
+                return Ok(());
+            }
+        }
+
+        VmErrorKind::StackUnderflow(self.instructions[self.instruction_pointer].clone()).throw()
+    }
+
+    /// Divides by a non-zero divisor, rounding as configured, and returns the quotient and the
+    /// remainder
+    fn divide(&self, left: i64, right: i64) -> (i128, i128) {
+        let (left, right) = (left as i128, right as i128);
+        let (quotient, remainder) = (left / right, left % right);
+        match self.config.division_rounding {
+            DivisionRounding::Floor if remainder != 0 && (remainder < 0) != (right < 0) => {
+                (quotient - 1, remainder + right)
+            }
+            _ => (quotient, remainder),
+        }
+    }
+
+    fn integer_division(&mut self) -> Result<(), VmError> {
+        if let Some(right) = self.stack.pop() {
+            if let Some(left) = self.stack.pop() {
+                if right == 0 {
+                    return VmErrorKind::DivisionByZero(
+                        self.instructions[self.instruction_pointer].clone(),
+                    )
+                    .throw();
+                }
+                let (quotient, _) = self.divide(left, right);
+                let result = self.arithmetic_result(quotient)?;
+                self.stack.push(result);
+
+                return Ok(());
+            }
+        }
+
+        VmErrorKind::StackUnderflow(self.instructions[self.instruction_pointer].clone()).throw()
+    }
+
+    fn modulo(&mut self) -> Result<(), VmError> {
+        if let Some(right) = self.stack.pop() {
+            if let Some(left) = self.stack.pop() {
+                if right == 0 {
+                    return VmErrorKind::DivisionByZero(
+                        self.instructions[self.instruction_pointer].clone(),
+                    )
+                    .throw();
+                }
+                let (_, remainder) = self.divide(left, right);
+                let result = self.arithmetic_result(remainder)?;
+                self.stack.push(result);
+
+                return Ok(());
+            }
+        }
+
+        VmErrorKind::StackUnderflow(self.instructions[self.instruction_pointer].clone()).throw()
+    }
+
+    fn store_heap(&mut self) -> Result<(), VmError> {
+        if let Some(val) = self.stack.pop() {
+            if let Some(addr) = self.stack.pop() {
+                let addr = self.heap_address(addr)?;
+
+                self.write_heap(addr, val);
+
+                return Ok(());
+            }
+        }
+
+        VmErrorKind::StackUnderflow(self.instructions[self.instruction_pointer].clone()).throw()
+    }
+
+    fn retrieve_heap(&mut self) -> Result<(), VmError> {
+        if let Some(addr) = self.stack.pop() {
+            let addr = self.heap_address(addr)?;
+
+            self.stack
+                .push(self.heap.get(addr).copied().unwrap_or_default());
+
+            return Ok(());
+        }
+
+        VmErrorKind::StackUnderflow(self.instructions[self.instruction_pointer].clone()).throw()
+    }
+
+    fn mark(&mut self) -> Result<(), VmError> {
+        Ok(())
+    }
+
+    fn call(&mut self) -> Result<(), VmError> {
+        if let Instruction::Call(label) = &self.instructions[self.instruction_pointer] {
+            self.call_stack.push(self.instruction_pointer);
+            self.instruction_pointer = label.index;
+
+            return Ok(());
+        }
+
+        unreachable!();
+    }
+
+    fn jump(&mut self) -> Result<(), VmError> {
+        if let Instruction::Jump(label) = &self.instructions[self.instruction_pointer] {
+            self.instruction_pointer = label.index;
+
+            return Ok(());
+        }
+
+        unreachable!();
+    }
+
+    fn jump_dynamic(&mut self) -> Result<(), VmError> {
+        if let Some(target) = self.stack.pop() {
+            let target = self.index(target, self.instructions.len())?;
+            // the instruction pointer is advanced after every instruction
+            self.instruction_pointer = target.wrapping_sub(1);
+
+            return Ok(());
+        }
+
+        VmErrorKind::StackUnderflow(self.instructions[self.instruction_pointer].clone()).throw()
+    }
+
+    fn syscall(&mut self) -> Result<(), VmError> {
+        if let Instruction::Syscall(num) = &self.instructions[self.instruction_pointer] {
+            return match self.config.syscalls.get_mut(&num.value) {
+                Some(syscall) => {
+                    let before = self.stack.clone();
+                    syscall(&mut self.stack);
+                    // the results have not been checked against the word size yet, they may
+                    // replace popped arguments, so every changed value is checked
+                    for index in 0..self.stack.len() {
+                        if before.get(index) != Some(&self.stack[index]) {
+                            self.stack[index] =
+                                self.arithmetic_result(i128::from(self.stack[index]))?;
+                        }
+                    }
+
+                    Ok(())
+                }
+                None => {
+                    VmErrorKind::UnknownSyscall(self.instructions[self.instruction_pointer].clone())
+                        .throw()
+                }
+            };
+        }
+
+        unreachable!();
+    }
+
+    fn jump_zero(&mut self) -> Result<(), VmError> {
+        if let Instruction::JumpZero(label) = &self.instructions[self.instruction_pointer] {
+            if let Some(val) = self.stack.pop() {
+                if val != 0 {
+                    return Ok(());
+                }
+                self.instruction_pointer = label.index;
+
+                return Ok(());
+            }
+
+            return VmErrorKind::StackUnderflow(
+                self.instructions[self.instruction_pointer].clone(),
+            )
+            .throw();
+        }
+
+        unreachable!();
+    }
+
+    fn jump_negative(&mut self) -> Result<(), VmError> {
+        if let Instruction::JumpNegative(label) = &self.instructions[self.instruction_pointer] {
+            if let Some(val) = self.stack.pop() {
+                if val >= 0 {
+                    return Ok(());
+                }
+                self.instruction_pointer = label.index;
+
+                return Ok(());
+            }
+            return VmErrorKind::StackUnderflow(
+                self.instructions[self.instruction_pointer].clone(),
+            )
+            .throw();
+        }
+
+        unreachable!();
+    }
+
+    fn r#return(&mut self) -> Result<(), VmError> {
+        if let Some(frame) = self.call_stack.pop() {
+            self.instruction_pointer = frame;
+
+            return Ok(());
+        }
+
+        VmErrorKind::StackUnderflow(self.instructions[self.instruction_pointer].clone()).throw()
+    }
+
+    fn exit(&mut self) -> Result<(), VmError> {
+        self.done = true;
+        self.flush_output()?;
+
+        Ok(())
+    }
+
+    /// Creates the output sink configured by the interpreter configuration
+    fn output_sink(config: &VmConfig, mut sink: OutputSink) -> OutputSink {
+        sink.set_buffering(config.output_buffering);
+        sink.set_encoding(config.output_encoding);
+        sink.set_limit(config.max_output_bytes);
+
+        sink
+    }
+
+    /// Handles a failed write to the output, failing when passing the output limit and applying
+    /// the configured policy to a failed write
+    fn output_error(&mut self, err: OutputError) -> Result<(), VmError> {
+        if let OutputError::LimitExceeded(limit) = err {
+            return VmErrorKind::OutputLimitExceeded(
+                self.instructions[self.instruction_pointer].clone(),
+                limit,
+            )
+            .throw();
+        }
+        match self.config.on_output_error {
+            OutputErrorPolicy::Fail => {
+                VmErrorKind::IOError(self.instructions[self.instruction_pointer].clone()).throw()
+            }
+            OutputErrorPolicy::StopSilently => {
+                self.done = true;
+
+                Ok(())
+            }
+            OutputErrorPolicy::Ignore => Ok(()),
+        }
+    }
+
+    fn flush_output(&mut self) -> std::io::Result<()> {
+        self.output.flush()
+    }
+
+    /// Reads a single byte from the injected input, `None` on end of input
+    fn read_input_byte(input: &mut dyn Read) -> std::io::Result<Option<u8>> {
+        let mut byte = [0];
+        match input.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+
+    /// Reads the next whitespace separated token from the input, consuming the whitespace
+    /// before it and the single whitespace byte ending it. `None` if the input ended before the
+    /// token started. The bytes read so far are kept in `token`, so a read that would block can
+    /// be continued once more input is available.
+    fn read_input_token(
+        input: &mut dyn Read,
+        token: &mut Vec<u8>,
+    ) -> std::io::Result<Option<String>> {
+        loop {
+            match Vm::read_input_byte(input)? {
+                Some(byte) if byte.is_ascii_whitespace() && token.is_empty() => continue,
+                Some(byte) if byte.is_ascii_whitespace() => break,
+                Some(byte) => token.push(byte),
+                None if token.is_empty() => return Ok(None),
+                None => break,
+            }
+        }
+        let text = String::from_utf8_lossy(token).into_owned();
+        token.clear();
+
+        Ok(Some(text))
+    }
+
+    /// Reads a single UTF-8 encoded character from the injected input or the terminal, `None` on
+    /// end of input. Characters read from the terminal are echoed.
+    fn read_character(&mut self) -> std::io::Result<Option<char>> {
+        self.flush_output()?;
+        loop {
+            // the bytes of an incomplete character are kept, so a read that would block can be
+            // continued once more input is available
+            match std::str::from_utf8(&self.pending_character) {
+                Ok(text) => {
+                    if let Some(character) = text.chars().next() {
+                        self.pending_character.clear();
+                        if self.input.is_none() {
+                            self.char_input.echo(character)?;
+                        }
+
+                        return Ok(Some(character));
+                    }
+                }
+                Err(err) => {
+                    if let Some(len) = err.error_len() {
+                        // the byte that made the sequence invalid may start the next character
+                        self.pending_character.drain(..err.valid_up_to() + len);
+
+                        return Err(std::io::Error::new(
+                            ErrorKind::InvalidData,
+                            "input is not valid UTF-8",
+                        ));
+                    }
+                }
+            }
+            let byte = match &mut self.input {
+                Some(input) => Vm::read_input_byte(input.as_mut())?,
+                None => self.char_input.read_byte()?,
+            };
+            let byte = match byte {
+                Some(byte) => byte,
+                None if self.pending_character.is_empty() => return Ok(None),
+                None => {
+                    self.pending_character.clear();
+                    return Err(std::io::Error::new(
+                        ErrorKind::InvalidData,
+                        "input ended in the middle of a UTF-8 character",
+                    ));
+                }
+            };
+            self.pending_character.push(byte);
+        }
+    }
+
+    fn out_char(&mut self) -> Result<(), VmError> {
+        if let Some(character) = self.stack.pop() {
+            if character < 0 {
+                return VmErrorKind::NumberOutOfBoundsError(
+                    self.instructions[self.instruction_pointer].clone(),
+                    character,
+                    0,
+                    i64::MAX,
+                )
+                .throw();
+            }
+
+            if self.config.suppress_output {
+                return Ok(());
+            }
+
+            if let Some(character) = u32::try_from(character).ok().and_then(char::from_u32) {
+                if let Err(err) = self.output.write_char(character) {
+                    return self.output_error(err);
+                }
+
+                return Ok(());
+            }
+        }
+
+        VmErrorKind::StackUnderflow(self.instructions[self.instruction_pointer].clone()).throw()
+    }
+
+    fn out_int(&mut self) -> Result<(), VmError> {
+        if let Some(num) = self.stack.pop() {
+            if self.config.suppress_output {
+                return Ok(());
+            }
+            // formatted from the i64 cell, never negated, so the minimum of the word size is
+            // printed like any other value
+            let text = match self.config.int_width {
+                Some(width) => format!("{:>width$}", num, width = width),
+                None => num.to_string(),
+            };
+            if let Err(err) = self.output.write_bytes(text.as_bytes()) {
+                return self.output_error(err);
+            }
+
+            return Ok(());
+        }
+
+        VmErrorKind::StackUnderflow(self.instructions[self.instruction_pointer].clone()).throw()
+    }
+
+    /// Stores the configured end-of-input value at the address on top of the stack, or fails if
+    /// there is none
+    fn end_of_input(&mut self, addr: usize) -> Result<(), VmError> {
+        match self.config.eof_value {
+            Some(val) => {
+                self.stack.pop();
+                self.write_heap(addr, val as i64);
+
+                Ok(())
+            }
+            None => {
+                VmErrorKind::EndOfInput(self.instructions[self.instruction_pointer].clone()).throw()
+            }
+        }
+    }
+
+    fn read_char(&mut self) -> Result<(), VmError> {
+        // the address is only popped once the input is read, so a read that would block can be
+        // retried
+        if let Some(&addr) = self.stack.last() {
+            let addr = self.heap_address(addr)?;
+
+            return match self.read_character()? {
+                Some(val) => {
+                    self.stack.pop();
+                    self.write_heap(addr, val as i64);
+
+                    Ok(())
+                }
+                None => self.end_of_input(addr),
+            };
+        }
+
+        VmErrorKind::StackUnderflow(self.instructions[self.instruction_pointer].clone()).throw()
+    }
+
+    /// Truncates a decimal number read by `ReadInteger` towards zero, failing if it does not fit
+    /// into a word
+    fn truncate_input(&self, val: f64) -> Result<i64, VmError> {
+        let (min, max) = match self.config.word_size {
+            WordSize::I32 => (i32::MIN as f64, i32::MAX as f64),
+            WordSize::I64 => (i64::MIN as f64, i64::MAX as f64),
+        };
+        let val = val.trunc();
+        // i64::MAX is not representable as f64 and rounds up, so the upper bound is exclusive
+        if val < min || val >= max + 1.0 {
+            return VmErrorKind::ArithmeticOverflow(
+                self.instructions[self.instruction_pointer].clone(),
+            )
+            .throw();
+        }
+
+        Ok(val as i64)
+    }
+
+    fn read_int(&mut self) -> Result<(), VmError> {
+        if let Some(&addr) = self.stack.last() {
+            let addr = self.heap_address(addr)?;
+            self.flush_output()?;
+            let token = match &mut self.input {
+                Some(input) => Vm::read_input_token(input.as_mut(), &mut self.pending_token),
+                None => Vm::read_input_token(&mut stdin(), &mut self.pending_token),
+            };
+            let token = match token? {
+                Some(val) => val,
+                None => return self.end_of_input(addr),
+            };
+
+            let parsed = match self.config.word_size {
+                WordSize::I32 => token.parse::<i32>().map(i64::from),
+                WordSize::I64 => token.parse::<i64>(),
+            };
+            let num = match (parsed, self.config.int_input) {
+                (Ok(num), _) => num,
+                (Err(err), IntInputMode::Strict) => return Err(err.into()),
+                (Err(err), IntInputMode::TruncateFloat) => match token.parse::<f64>() {
+                    Ok(val) if val.is_finite() => self.truncate_input(val)?,
+                    _ => return Err(err.into()),
+                },
+            };
+            self.stack.pop();
+            self.write_heap(addr, num);
+
+            return Ok(());
+        }
+
+        VmErrorKind::IOError(self.instructions[self.instruction_pointer].clone()).throw()
+    }
+
+    pub fn exec(&mut self) -> Result<(), VmError> {
+        let cost = self.charge_gas()?;
+        self.instruction_count += 1;
+        if self.config.debug {
+            dbg!(&self.stack);
+            dbg!(&self.call_stack);
+            dbg!(&self.instruction_pointer);
+            dbg!(&self.instructions[self.instruction_pointer]);
+        }
+        if self.config.debug_heap {
+            eprint!("heap dump:\n{}", self.format_heap_dump());
+        }
+        let index = self.instruction_pointer;
+        self.last_executed = Some(self.streamed + index);
+        if self.config.history_len > 0 {
+            if self.history.len() == self.config.history_len {
+                self.history.pop_front();
+            }
+            self.history.push_back(self.streamed + index);
+        }
+        let depth = self.stack.len();
+        let res = match self.instructions[self.instruction_pointer] {
+            Instruction::PushStack(_) => self.push_stack(),
+            Instruction::DuplicateStack => self.duplicate_stack(),
+            Instruction::CopyNthStack(_) => self.copy_nth_stack(),
+            Instruction::SwapStack => self.swap_stack(),
+            Instruction::DiscardStack => self.discard_stack(),
+            Instruction::SlideNStack(_) => self.slide_n_stack(),
+            Instruction::Add => self.add(),
+            Instruction::Subtract => self.subtract(),
+            Instruction::Multiply => self.multiply(),
+            Instruction::IntegerDivision => self.integer_division(),
+            Instruction::Modulo => self.modulo(),
+            Instruction::StoreHeap => self.store_heap(),
+            Instruction::RetrieveHeap => self.retrieve_heap(),
+            Instruction::Mark(_) => self.mark(),
+            Instruction::Call(_) => self.call(),
+            Instruction::Jump(_) => self.jump(),
+            Instruction::JumpZero(_) => self.jump_zero(),
+            Instruction::JumpNegative(_) => self.jump_negative(),
+            Instruction::JumpDynamic => self.jump_dynamic(),
+            Instruction::Syscall(_) => self.syscall(),
+            Instruction::Return => self.r#return(),
+            Instruction::Exit => self.exit(),
+            Instruction::OutCharacter => self.out_char(),
+            Instruction::OutInteger => self.out_int(),
+            Instruction::ReadCharacter => self.read_char(),
+            Instruction::ReadInteger => self.read_int(),
+        };
+        if let Err(err) = &res {
+            if err.would_block() {
+                // retry the same instruction once input is available
+                self.instruction_count -= 1;
+                if let Some(gas) = &mut self.gas {
+                    *gas += cost;
+                }
+                return res;
+            }
+        }
+        // instructions run while streaming are not covered, see `new_streaming`
+        if let Some(covered) = self.coverage.get_mut(index) {
+            *covered = true;
+        }
+        if let Instruction::Call(_)
+        | Instruction::Jump(_)
+        | Instruction::JumpZero(_)
+        | Instruction::JumpNegative(_) = self.instructions[index]
+        {
+            // a jump continues after the label it targets, which counts as executed as well
+            self.coverage[self.instruction_pointer] = true;
+        }
+
+        if let (Some(on_stack_change), true) = (&mut self.config.on_stack_change, res.is_ok()) {
+            if self.stack.len() != depth {
+                on_stack_change(self.stack.len());
+            }
+        }
+        // wraps around after a dynamic jump to the first instruction
+        self.instruction_pointer = self.instruction_pointer.wrapping_add(1);
+        if let (Some(on_enter_label), false) = (&mut self.config.on_enter_label, self.done) {
+            let region = self
+                .regions
+                .get(self.instruction_pointer)
+                .cloned()
+                .flatten();
+            if region != self.region {
+                if let Some(label) = &region {
+                    on_enter_label(label);
+                }
+                self.region = region;
+            }
+        }
+
+        res
+    }
+}
+
+impl Vm {
+    /// Creates a new interpreter running an already parsed program. The source in the config is
+    /// not read.
+    ///
+    /// - `config` The configuration of the interpreter
+    /// - `program` the program to run
+    pub fn from_program(config: VmConfig, program: Program) -> Result<Vm, VmError> {
+        let carriage_returns = lint::carriage_returns(&program.source);
+        let stack = vec![];
+        let call_stack = vec![];
+        let heap = vec![];
+        let heap_size = config.heap_size;
+        let instruction_pointer = 0;
+        let done = false;
+        let gas = config.gas_budget;
+        let output = Vm::output_sink(&config, OutputSink::stdout());
+
+        let mut vm = Vm {
+            config,
+            instructions: vec![],
+            labels: HashMap::new(),
+            breakpoints: BTreeSet::new(),
+            stack,
+            call_stack,
+            heap,
+            heap_size,
+            written: BTreeSet::new(),
+            heap_journal: None,
+            instruction_pointer,
+            done,
+            last_executed: None,
+            history: VecDeque::new(),
+            carriage_returns,
+            input: None,
+            char_input: Box::new(TerminalInput),
+            pending_token: vec![],
+            pending_character: vec![],
+            output,
+            gas,
+            coverage: vec![],
+            regions: vec![],
+            region: None,
+            streaming: false,
+            streamed: 0,
+            instruction_count: 0,
+        };
+        vm.load_instructions(program.instructions)?;
+        vm.generate_input();
+
+        Ok(vm)
+    }
+
+    /// Returns the label of the region each instruction belongs to, the label of the closest
+    /// `Mark` at or before it. `None` before the first `Mark`.
+    fn label_regions(instructions: &[Instruction]) -> Vec<Option<Arc<str>>> {
+        let mut region = None;
+        instructions
+            .iter()
+            .map(|instr| {
+                if let Instruction::Mark(label) = instr {
+                    region = Some(label.value.clone());
+                }
+                region.clone()
+            })
+            .collect()
+    }
+
+    /// Checks, optimizes and loads the instructions as configured and resolves their labels
+    fn load_instructions(&mut self, mut instructions: Vec<Instruction>) -> Result<(), VmError> {
+        if self.config.check_labels {
+            if let Some(diagnostic) = lint::label_diagnostics(&instructions).into_iter().next() {
+                return Err(diagnostic.error);
+            }
+        }
+        let extension = instructions
+            .iter()
+            .find(|instr| matches!(instr, Instruction::JumpDynamic | Instruction::Syscall(_)));
+        if let Some(instr) = extension {
+            if !self.config.extensions {
+                return VmErrorKind::ExtensionDisabled(instr.clone()).throw();
+            }
+        }
+        let dynamic = instructions
+            .iter()
+            .any(|instr| matches!(instr, Instruction::JumpDynamic));
+        // dynamic jump targets are indices, which optimizing would shift
+        if self.config.optimize && !dynamic {
+            instructions = optimize::optimize(instructions);
+        }
+        self.labels = lint::resolve_labels(&mut instructions)
+            .into_iter()
+            .map(|(label, index)| (label.to_string(), index))
+            .collect();
+        self.coverage = vec![false; instructions.len()];
+        self.regions = Vm::label_regions(&instructions);
+        self.instructions = instructions;
+        self.instruction_pointer = 0;
+
+        Ok(())
+    }
+
+    /// Creates a new interpreter like `new`, but `run` executes a program without labels and
+    /// jumps as it is parsed, so straight-line programs, e.g. programs embedding large amounts of
+    /// data, are never held in memory as a whole. A pre-pass parses the whole program first, so
+    /// parse errors are reported before anything runs. Programs with any instruction changing the
+    /// flow are loaded like by `new`.
+    ///
+    /// `run` parses the source again, so sources that cannot be read twice, stdin and URLs, are
+    /// loaded like by `new` as well. Only `run` and `reset` know about the streamed program, e.g.
+    /// `step`, breakpoints and coverage see the instruction being executed at most. Instruction
+    /// indices, e.g. of `last_executed`, count from the start of the program.
+    ///
+    /// - `config` The configuration of the interpreter
+    pub fn new_streaming(config: VmConfig) -> Result<Vm, VmError> {
+        if !Vm::rereadable(&config) {
+            return Vm::new(config);
+        }
+        let mut parser = Vm::parser(&config)?;
+        while let Some(next) = Program::next_instruction(&mut parser, false) {
+            if lint::changes_flow(&next?.0) {
+                return Vm::new(config);
+            }
+        }
+        let carriage_returns = lint::carriage_returns(parser.source());
+        let mut vm = Vm::from_program(config, Program::default())?;
+        vm.carriage_returns = carriage_returns;
+        vm.streaming = true;
+
+        Ok(vm)
+    }
+
+    /// Whether parsing the source again reads the same program
+    #[cfg(not(target_arch = "wasm32"))]
+    fn rereadable(config: &VmConfig) -> bool {
+        let file_name = &config.file_name;
+
+        file_name != "-" && !file_name.starts_with("http://") && !file_name.starts_with("https://")
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn rereadable(_config: &VmConfig) -> bool {
+        true
+    }
+
+    /// Creates a new interpreter starting from the given stack and heap instead of empty ones
+    ///
+    /// - `config` The configuration of the interpreter
+    /// - `stack` the initial stack, bottom first
+    /// - `heap` the initial values of the heap cells, starting at address 0, at most as many as
+    ///   the configured heap size
+    ///
+    /// The values are wrapped to the configured word size like the results of instructions.
+    pub fn with_initial_state(
+        config: VmConfig,
+        stack: Vec<i64>,
+        heap: Vec<i64>,
+    ) -> Result<Vm, VmError> {
+        let mut vm = Vm::new(config)?;
+        if heap.len() > vm.heap_size {
+            return VmErrorKind::InitialHeapTooLarge(heap.len(), vm.heap_size).throw();
+        }
+        vm.stack = stack.into_iter().map(|val| vm.wrap(val)).collect();
+        let heap: Vec<i64> = heap.into_iter().map(|val| vm.wrap(val)).collect();
+        if !heap.is_empty() {
+            vm.heap.resize(vm.heap_size, 0);
+            vm.heap[..heap.len()].copy_from_slice(&heap);
+        }
+        if vm.config.heap_dump_filter == HeapDumpFilter::Written {
+            vm.written.extend(0..heap.len());
+        }
+
+        Ok(vm)
+    }
+
+    /// Statically checks the program for undefined and duplicate labels as well as an unreachable
+    /// exit, collecting every issue instead of stopping at the first one
+    ///
+    /// - `config` The configuration of the interpreter
+    pub fn lint(config: VmConfig) -> Result<Vec<Diagnostic>, VmError> {
+        let program = Vm::parse(&config)?;
+        let mut diagnostics = lint::lint(&program.instructions);
+        diagnostics.extend(lint::encoding_diagnostics(&program.source));
+
+        Ok(diagnostics)
+    }
+
+    /// Parses the program and returns its size without creating an interpreter
+    ///
+    /// - `config` The configuration of the interpreter
+    pub fn program_size(config: VmConfig) -> Result<ProgramSize, VmError> {
+        Ok(Vm::parse(&config)?.size())
+    }
+
+    /// Parses the program and returns how often each command appears in it without creating an
+    /// interpreter, see `Program::instruction_histogram`
+    ///
+    /// - `config` The configuration of the interpreter
+    pub fn instruction_histogram(config: VmConfig) -> Result<Vec<(WsCommandKind, usize)>, VmError> {
+        Ok(Vm::parse(&config)?.instruction_histogram())
+    }
+
+    /// Scans the program for a handful of local instruction patterns that can be written shorter,
+    /// e.g. `push 0; add`, which has no effect
+    ///
+    /// - `config` The configuration of the interpreter
+    pub fn suggest_optimizations(config: VmConfig) -> Result<Vec<Suggestion>, VmError> {
+        let program = Vm::parse(&config)?;
+
+        Ok(lint::suggest_optimizations(&program.instructions))
+    }
+
+    /// Runs the program like `run` and records every executed instruction with the stack depth
+    /// after it, e.g. to compare two runs with `compare_traces`
+    pub fn run_traced(&mut self) -> Result<Vec<TraceEvent>, VmError> {
+        let mut trace = vec![];
+        while let Some(index) = self.next_instruction() {
+            self.exec()?;
+            trace.push(TraceEvent {
+                instruction_index: index,
+                stack_depth: self.stack.len(),
+            });
+        }
+        self.check_termination()?;
+
+        Ok(trace)
+    }
+
+    /// Executes up to `budget` instructions, so several interpreters can take turns running on
+    /// one thread. Call it again to continue the program where the slice stopped.
+    ///
+    /// - `budget` the maximum number of instructions to execute
+    pub fn run_slice(&mut self, budget: u64) -> Result<RunSliceOutcome, VmError> {
+        for _ in 0..budget {
+            if self.is_done() {
+                break;
+            }
+            match self.exec() {
+                Ok(()) => {}
+                Err(err) if err.would_block() => return Ok(RunSliceOutcome::Blocked),
+                Err(err) => return Err(err),
+            }
+        }
+        if !self.is_done() {
+            return Ok(RunSliceOutcome::BudgetExhausted);
+        }
+        self.check_termination()?;
+
+        Ok(RunSliceOutcome::Finished)
+    }
+
+    /// Runs the program like `run`, but yields to the async runtime every few thousand
+    /// instructions, so other tasks are not starved. The program reads its input from the given
+    /// queue, fed by other tasks, and never from the terminal. While the queue is empty, the
+    /// future is pending until input is pushed or the queue is closed. The output is written to
+    /// the configured sink, capture it or write it to a non-blocking writer to never block the
+    /// runtime.
+    ///
+    /// - `input` the queue the program reads its input from
+    #[cfg(feature = "async")]
+    pub async fn run_async(&mut self, input: InputQueue) -> Result<(), VmError> {
+        self.set_input(Box::new(input.clone()));
+        loop {
+            match self.run_slice(ASYNC_SLICE)? {
+                RunSliceOutcome::Finished => return Ok(()),
+                RunSliceOutcome::BudgetExhausted => YieldNow { yielded: false }.await,
+                RunSliceOutcome::Blocked => InputReady { queue: &input }.await,
+            }
+        }
+    }
+
+    /// Reads the program input from the given reader instead of the terminal
+    ///
+    /// - `input` the reader to read input from, `Send` so the interpreter can be moved to another
+    ///   thread
+    pub fn set_input(&mut self, input: Box<dyn Read + Send>) {
+        self.input = Some(input);
+    }
+
+    /// Reads the key presses of `ReadCharacter` with the given reader instead of the default
+    /// terminal input. Input injected with `set_input` takes precedence.
+    ///
+    /// - `char_input` the reader of the terminal
+    pub fn set_char_input(&mut self, char_input: Box<dyn CharInput>) {
+        self.char_input = char_input;
+    }
+
+    /// Writes the program output to the given writer instead of stdout
+    ///
+    /// - `output` the writer to write output to, `Send` so the interpreter can be moved to another
+    ///   thread
+    pub fn set_output(&mut self, output: Box<dyn Write + Send>) {
+        self.output.redirect(OutputSink::writer(output));
+    }
+
+    /// Writes the program output to the given sink. Its buffering, encoding and byte limit
+    /// replace the ones of the configuration.
+    ///
+    /// - `sink` the configured sink to write output to
+    pub fn set_output_sink(&mut self, sink: OutputSink) {
+        self.output = sink;
+    }
+
+    /// Collects the program output in memory instead of writing it to stdout, see `take_output`
+    pub fn capture_output(&mut self) {
+        self.output.redirect(OutputSink::captured());
+    }
+
+    /// Returns the output captured so far and clears the capture buffer. Empty if the output is
+    /// not captured. The output stays available after the program failed with an error.
+    pub fn take_output(&mut self) -> Vec<u8> {
+        self.output.take_captured()
+    }
+
+    /// Returns the index of the `Mark` instruction of every label, by label name. Label names
+    /// are the raw space and tab sequences from the source.
+    pub fn labels(&self) -> &HashMap<String, usize> {
+        &self.labels
+    }
+
+    /// Sets a breakpoint, `resume` stops before executing the instruction at the given index
+    ///
+    /// - `index` the index of the instruction to stop at
+    pub fn add_breakpoint(&mut self, index: usize) {
+        self.breakpoints.insert(index);
+    }
+
+    /// Sets a breakpoint at the first instruction following the `Mark` of the given label, which
+    /// is where execution continues both when jumping to the label and when falling through it.
+    /// A label at the end of the program has no instruction following it, the breakpoint is then
+    /// set at its `Mark` and only hit when falling through it. Returns the index of the breakpoint.
+    ///
+    /// - `label` the name of the label, as raw space and tab sequence
+    pub fn add_breakpoint_at_label(&mut self, label: &str) -> Result<usize, VmError> {
+        let index = match self.labels.get(label) {
+            Some(index) => *index,
+            None => return VmErrorKind::LabelNotFound(label.to_string()).throw(),
+        };
+        let index = if index + 1 < self.instructions.len() {
+            index + 1
+        } else {
+            index
+        };
+        self.breakpoints.insert(index);
+
+        Ok(index)
+    }
+
+    /// Removes the breakpoint at the given index, returns whether there was one
+    ///
+    /// - `index` the index of the instruction of the breakpoint
+    pub fn remove_breakpoint(&mut self, index: usize) -> bool {
+        self.breakpoints.remove(&index)
+    }
+
+    /// Returns the indices of all instructions with a breakpoint, in ascending order
+    pub fn breakpoints(&self) -> &BTreeSet<usize> {
+        &self.breakpoints
+    }
+
+    /// Executes instructions until the next instruction has a breakpoint or the program ends.
+    /// Always executes at least one instruction, so resuming from a breakpoint makes progress.
+    /// Returns the index of the breakpoint hit, `None` if the program ended.
+    pub fn resume(&mut self) -> Result<Option<usize>, VmError> {
+        while self.next_instruction().is_some() {
+            self.exec()?;
+            if let Some(index) = self.next_instruction() {
+                if self.breakpoints.contains(&index) {
+                    return Ok(Some(index));
+                }
+            }
+        }
+        self.check_termination()?;
+
+        Ok(None)
+    }
+
+    /// Calls the subroutine at the given label like a function, for reusing a program as a
+    /// library. The arguments are pushed onto the stack and the subroutine runs until its
+    /// matching `Return`. The values the subroutine leaves above the stack depth before the call
+    /// are popped and returned, bottom first. Afterwards, also after an error, the interpreter
+    /// continues where it was before the call, with the stack cut back to its depth before the
+    /// call. A subroutine ending the program instead of returning is an error.
+    ///
+    /// - `label` the name of the label, as raw space and tab sequence
+    /// - `args` the arguments, pushed in order so the last one is on top
+    pub fn call_label(&mut self, label: &str, args: &[i64]) -> Result<Vec<i64>, VmError> {
+        let index = match self.labels.get(label) {
+            Some(index) => *index,
+            None => return VmErrorKind::LabelNotFound(label.to_string()).throw(),
+        };
+        let depth = self.stack.len();
+        let frames = self.call_stack.len();
+        let (instruction_pointer, done) = (self.instruction_pointer, self.done);
+        self.stack.extend_from_slice(args);
+        self.instruction_pointer = index + 1;
+        self.done = false;
+
+        let returned = self.run_until_return(frames);
+        self.instruction_pointer = instruction_pointer;
+        self.done = done;
+        self.call_stack.truncate(frames);
+        let returned = match returned {
+            Ok(true) => Ok(()),
+            Ok(false) => VmErrorKind::NoReturn(label.to_string()).throw(),
+            Err(err) => Err(err),
+        };
+        if let Err(err) = returned {
+            self.stack.truncate(depth);
+
+            return Err(err);
+        }
+
+        Ok(self.stack.split_off(depth.min(self.stack.len())))
+    }
+
+    /// Returns the gas left for the program, `None` without a gas budget
+    pub fn remaining_gas(&self) -> Option<u64> {
+        self.gas
+    }
+
+    /// Deducts the gas for the next instruction from the remaining gas and returns the charged
+    /// gas. Fails without deducting anything if the remaining gas does not suffice.
+    fn charge_gas(&mut self) -> Result<u64, VmError> {
+        let gas = match self.gas {
+            Some(gas) => gas,
+            None => return Ok(0),
+        };
+        let instr = &self.instructions[self.instruction_pointer];
+        let cost = self.config.cost_model.cost(lint::command_kind(instr));
+        if cost > gas {
+            return VmErrorKind::OutOfGas(instr.clone(), gas).throw();
+        }
+        self.gas = Some(gas - cost);
+
+        Ok(cost)
+    }
+
+    /// Executes instructions until reaching a `Return` with the given call stack depth, without
+    /// executing it. Returns whether the `Return` was reached before the program ended.
+    fn run_until_return(&mut self, frames: usize) -> Result<bool, VmError> {
+        while let Some(index) = self.next_instruction() {
+            if self.call_stack.len() == frames && self.instructions[index] == Instruction::Return {
+                return Ok(true);
+            }
+            self.exec()?;
+        }
+
+        Ok(false)
+    }
+
+    /// Reads `count` characters into consecutive heap cells starting at `addr`, like executing
+    /// `ReadCharacter` for each of the addresses. This is a library extension, there is no
+    /// instruction for it in the whitespace language.
+    ///
+    /// - `addr` the heap address to store the first character at
+    /// - `count` the number of characters to read
+    pub fn read_into_heap(&mut self, addr: usize, count: usize) -> Result<(), VmError> {
+        if self.heap_size == 0 {
+            return VmErrorKind::HeapDisabled(Instruction::ReadCharacter).throw();
+        }
+        if addr.saturating_add(count) > self.heap_size {
+            return VmErrorKind::NumberOutOfBoundsError(
+                Instruction::ReadCharacter,
+                addr.saturating_add(count) as i64 - 1,
+                0,
+                self.heap_size as i64 - 1,
+            )
+            .throw();
+        }
+        for cell in addr..addr + count {
+            match self.read_character()? {
+                Some(val) => self.write_heap(cell, val as i64),
+                None => return VmErrorKind::EndOfInput(Instruction::ReadCharacter).throw(),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the stack, bottom first, to consecutive heap cells starting at `start`, for
+    /// inspecting or checkpointing the stack. The stack is left unchanged. This is a library
+    /// extension, there is no instruction for it in the whitespace language.
+    ///
+    /// - `start` the heap address to store the bottom of the stack at
+    pub fn spill_stack_to_heap(&mut self, start: usize) -> Result<(), VmError> {
+        if self.heap_size == 0 {
+            return VmErrorKind::HeapDisabled(Instruction::StoreHeap).throw();
+        }
+        let count = self.stack.len();
+        if start.saturating_add(count) > self.heap_size {
+            return VmErrorKind::NumberOutOfBoundsError(
+                Instruction::StoreHeap,
+                start.saturating_add(count) as i64 - 1,
+                0,
+                self.heap_size as i64 - 1,
+            )
+            .throw();
+        }
+        for i in 0..count {
+            self.write_heap(start + i, self.stack[i]);
+        }
+
+        Ok(())
+    }
+
+    /// Starts recording the heap writes, so they can be undone by `rollback_heap`. Checkpoints
+    /// can be nested, rolling back to a checkpoint also undoes the writes of the checkpoints
+    /// taken after it.
+    pub fn checkpoint_heap(&mut self) -> HeapDelta {
+        HeapDelta {
+            start: self.heap_journal.get_or_insert_with(Vec::new).len(),
+        }
+    }
+
+    /// Restores the heap cells written since the checkpoint to their values at the checkpoint.
+    /// Rolling back the outermost checkpoint stops recording heap writes.
+    ///
+    /// - `delta` the checkpoint to roll back to
+    pub fn rollback_heap(&mut self, delta: HeapDelta) {
+        let journal = match &mut self.heap_journal {
+            Some(journal) => journal,
+            None => return,
+        };
+        while journal.len() > delta.start {
+            if let Some((addr, val, written)) = journal.pop() {
+                self.heap[addr] = val;
+                if !written {
+                    self.written.remove(&addr);
+                }
+            }
+        }
+        if delta.start == 0 {
+            self.heap_journal = None;
+        }
+    }
+
+    /// Returns all instructions of the program
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    /// Returns the instruction at the given index, `None` past the end of the program
+    ///
+    /// - `index` the index of the instruction
+    pub fn instruction_at(&self, index: usize) -> Option<&Instruction> {
+        self.instructions.get(index)
+    }
+
+    /// Returns the number of instructions of the program
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    /// Whether the program has no instructions at all
+    pub fn is_empty(&self) -> bool {
+        self.instructions.is_empty()
+    }
+
+    /// Returns the index of the instruction executed last, `None` if nothing has been executed yet
+    pub fn last_executed(&self) -> Option<usize> {
+        self.last_executed
+    }
+
+    /// Returns the instruction to be executed next, `None` if the program has reached its end
+    pub fn current_instruction(&self) -> Option<&Instruction> {
+        self.next_instruction()
+            .and_then(|index| self.instruction_at(index))
+    }
+
+    /// Formats the state of the interpreter for debugging, e.g. after an error: the instruction
+    /// pointer, the disassembled instruction executed last, which is the faulting one after an
+    /// error, the stack, the call stack and the heap as selected by the heap dump filter
+    pub fn dump_state(&self) -> String {
+        let last_executed = match self.last_executed {
+            Some(index) => format!(
+                "{}: {}",
+                index,
+                asm::disassemble(&self.instructions[index - self.streamed..=index - self.streamed])
+            ),
+            None => "none\n".to_string(),
+        };
+
+        format!(
+            "instruction pointer: {}\nlast executed: {}stack (bottom first): {:?}\ncall stack: {:?}\nheap: {:?}\n",
+            self.instruction_pointer(),
+            last_executed,
+            self.stack,
+            self.call_stack,
+            self.generate_debug_heap_dump()
+        )
+    }
+
+    /// Returns the current stack, bottom first
+    pub fn stack(&self) -> &[i64] {
+        &self.stack
+    }
+
+    /// Returns the entire heap. The heap is allocated by the first write to it, until then it is
+    /// empty and every cell up to `heap_size` is 0.
+    pub fn heap(&self) -> &[i64] {
+        &self.heap
+    }
+
+    /// Returns the number of cells of the heap, whether it is allocated yet or not
+    pub fn heap_size(&self) -> usize {
+        self.heap_size
+    }
+
+    /// Returns the issues of the source found while loading it that do not keep the program from
+    /// running, e.g. carriage returns, see `Vm::lint` for all checks
+    pub fn warnings(&self) -> Vec<Diagnostic> {
+        lint::carriage_return_diagnostics(self.carriage_returns.clone())
+    }
+
+    /// Returns for every instruction whether it was executed. A label counts as executed once it
+    /// is jumped to. Coverage accumulates across runs, `reset` keeps it, see `clear_coverage`.
+    pub fn coverage(&self) -> &[bool] {
+        &self.coverage
+    }
+
+    /// Returns the percentage of the instructions that were executed, 100 for an empty program
+    pub fn coverage_percent(&self) -> f64 {
+        if self.coverage.is_empty() {
+            return 100.0;
+        }
+        let executed = self.coverage.iter().filter(|executed| **executed).count();
+
+        executed as f64 * 100.0 / self.coverage.len() as f64
+    }
+
+    /// Marks every instruction as not executed, so coverage is collected from scratch
+    pub fn clear_coverage(&mut self) {
+        self.coverage.fill(false);
+    }
+
+    /// Panics unless the stack, bottom first, equals the expected values. The message shows both
+    /// stacks in full, for use in tests.
+    ///
+    /// - `expected` the expected stack, bottom first
+    #[track_caller]
+    pub fn assert_stack(&self, expected: &[i64]) {
+        if self.stack != expected {
+            panic!(
+                "stack mismatch\n  expected (bottom first): {:?}\n  actual (bottom first):   {:?}",
+                expected, self.stack
+            );
+        }
+    }
+
+    /// Panics unless every given heap cell holds the expected value. Cells not listed are not
+    /// checked. The message lists the mismatching cells and all non-zero cells of the heap, for
+    /// use in tests.
+    ///
+    /// - `expected` the expected value of each cell, by address
+    #[track_caller]
+    pub fn assert_heap_cells(&self, expected: &[(usize, i64)]) {
+        let mismatches: Vec<String> = expected
+            .iter()
+            .filter(|(addr, val)| self.heap_cell(*addr) != Some(*val))
+            .map(|(addr, val)| match self.heap_cell(*addr) {
+                Some(actual) => format!("{}: expected {}, actual {}", addr, val, actual),
+                None => format!("{}: expected {}, outside of the heap", addr, val),
+            })
+            .collect();
+        if !mismatches.is_empty() {
+            let non_zero: BTreeMap<usize, i64> = self
+                .heap
+                .iter()
+                .enumerate()
+                .filter(|(_, val)| **val != 0)
+                .map(|(addr, val)| (addr, *val))
+                .collect();
+            panic!(
+                "heap mismatch\n  {}\n  non-zero cells: {:?}",
+                mismatches.join("\n  "),
+                non_zero
+            );
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn ws_parser(config: &VmConfig) -> Result<Box<dyn Parser>, ParseError> {
+        if config.gzip {
+            return WsParser::new_gzip(&config.file_name);
+        }
+
+        WsParser::new(&config.file_name)
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn ws_parser(config: &VmConfig) -> Result<Box<dyn Parser>, ParseError> {
+        WsParser::new(&config.source)
+    }
+
+    fn parser(config: &VmConfig) -> Result<Box<dyn Parser>, VmError> {
+        match config.source_type {
+            SourceType::Whitespace => match Vm::ws_parser(config) {
+                Ok(mut content) => {
+                    content.set_extensions(config.extensions);
+                    Ok(content)
+                }
+                Err(err) => VmErrorKind::ParseError(Box::new(err)).throw(),
+            },
+            SourceType::Malbolge => unimplemented!(),
+            SourceType::Brainfuck => unimplemented!(),
+        }
+    }
+
+    fn parse(config: &VmConfig) -> Result<Program, VmError> {
+        Program::from_parser(Vm::parser(config)?, config.raw)
+    }
+}
+
+/// Runs a program to completion on the given input, collecting its output and final state
+///
+/// - `source` the path to the source file on disk (the source itself on wasm)
+/// - `source_type` the type of the source
+/// - `input` the input the program reads from
+///
+/// ```
+/// use spacey::{execute, SourceType, VmError};
+///
+/// let result = execute("resources/ws/hello_world.ws", SourceType::Whitespace, b"")?;
+/// assert_eq!(result.output, b"Hello, world!");
+/// # Ok::<(), VmError>(())
+/// ```
+pub fn execute(
+    source: &str,
+    source_type: SourceType,
+    input: &[u8],
+) -> Result<ExecutionResult, VmError> {
+    let config = VmConfig::default_heap(source, source_type);
+    let mut vm = Vm::new(config)?;
+    vm.set_input(Box::new(Cursor::new(input.to_vec())));
+    vm.capture_output();
+    if let Err(mut err) = vm.run() {
+        err.output = vm.take_output().into_boxed_slice();
+        return Err(err);
+    }
+
+    Ok(ExecutionResult {
+        output: vm.take_output(),
+        stack: vm.stack.clone(),
+        heap: vm.generate_debug_heap_dump(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        checked_index, execute, Buffering, ChainedInput, CharInput, CompatProfile, CostModel,
+        HeapDumpFilter, InputQueue, Instruction, IntInputMode, OutputEncoding, OutputErrorPolicy,
+        RunSliceOutcome, SourceType, Vm, VmConfig, VmError, VmErrorKind, WordSize, WsCommandKind,
+        DEFAULT_HEAP_SIZE,
+    };
+    use crate::ir::Number;
+    use crate::output::OutputSink;
+    use crate::{asm, Program};
+    use std::collections::{BTreeMap, VecDeque};
+    use std::io::{Cursor, ErrorKind, Write};
+    use std::sync::{Arc, Mutex};
+
+    struct BrokenPipe;
+
+    impl Write for BrokenPipe {
+        fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+            Err(ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Records everything written, the number of writes and the number of flushes
+    #[derive(Clone, Default)]
+    struct RecordingWriter(Arc<Mutex<(Vec<u8>, usize, usize)>>);
+
+    impl Write for RecordingWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            let mut record = self.0.lock().unwrap();
+            record.0.extend_from_slice(buf);
+            record.1 += 1;
+
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.0.lock().unwrap().2 += 1;
+
+            Ok(())
+        }
+    }
+
+    fn run_with_broken_pipe(policy: OutputErrorPolicy) -> Result<Vm, VmError> {
+        let mut config =
+            VmConfig::default_heap("resources/ws/hello_world.ws", SourceType::Whitespace);
+        config.set_on_output_error(policy);
+        let mut interpreter = Vm::new(config)?;
+        interpreter.set_output(Box::new(BrokenPipe));
+
+        interpreter.run()?;
+
+        Ok(interpreter)
+    }
+
+    /// Runs the program of the configuration on the given input, returning the interpreter and
+    /// the captured output
+    fn run_config(config: VmConfig, input: &[u8]) -> Result<(Vm, Vec<u8>), VmError> {
+        run_vm(Vm::new(config)?, input)
+    }
+
+    /// Runs the loaded program on the given input, returning the interpreter and the captured
+    /// output. Like `execute`, an error carries the output written before it.
+    fn run_vm(mut interpreter: Vm, input: &[u8]) -> Result<(Vm, Vec<u8>), VmError> {
+        interpreter.set_input(Box::new(Cursor::new(input.to_vec())));
+        interpreter.capture_output();
+        if let Err(mut err) = interpreter.run() {
+            err.output = interpreter.take_output().into_boxed_slice();
+            return Err(err);
+        }
+        let output = interpreter.take_output();
+
+        Ok((interpreter, output))
+    }
+
+    #[test]
+    fn int_width() -> Result<(), VmError> {
+        let mut config = VmConfig::default_heap("resources/ws/out_ints.ws", SourceType::Whitespace);
+        config.set_int_width(4);
+        let mut interpreter = Vm::new(config)?;
+        interpreter.capture_output();
+        interpreter.run()?;
+
+        assert_eq!(interpreter.take_output(), b"  -5  42123456");
+
+        Ok(())
+    }
+
+    #[test]
+    fn out_int_min() -> Result<(), VmError> {
+        for (int_width, expected) in [(None, "-2147483648"), (Some(12), " -2147483648")] {
+            let mut config =
+                VmConfig::default_no_heap("resources/ws/out_int_min.ws", SourceType::Whitespace);
+            if let Some(int_width) = int_width {
+                config.set_int_width(int_width);
+            }
+            let mut interpreter = Vm::new(config)?;
+            interpreter.capture_output();
+            interpreter.run()?;
+
+            assert_eq!(interpreter.take_output(), expected.as_bytes());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn compat_profile() -> Result<(), VmError> {
+        let config = |profile| {
+            let mut config =
+                VmConfig::default_no_heap("resources/ws/compat.ws", SourceType::Whitespace);
+            config.set_compat_profile(profile);
+
+            config
+        };
+
+        assert_eq!(
+            run_config(config(CompatProfile::Spacey), b"")?.1,
+            b"-3 -1 -2147483648"
+        );
+        assert_eq!(
+            run_config(config(CompatProfile::WspaceHaskell), b"")?.1,
+            b"-4 1 2147483648"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn custom_char_input() -> Result<(), VmError> {
+        struct Scripted {
+            bytes: VecDeque<u8>,
+            echoed: Arc<Mutex<String>>,
+        }
+
+        impl CharInput for Scripted {
+            fn read_byte(&mut self) -> std::io::Result<Option<u8>> {
+                Ok(self.bytes.pop_front())
+            }
+
+            fn echo(&mut self, character: char) -> std::io::Result<()> {
+                self.echoed.lock().unwrap().push(character);
+                Ok(())
+            }
+        }
+
+        let config = VmConfig::default_heap("resources/ws/read_char.ws", SourceType::Whitespace);
+        let mut interpreter = Vm::new(config)?;
+        let echoed = Arc::new(Mutex::new(String::new()));
+        interpreter.set_char_input(Box::new(Scripted {
+            bytes: "é".bytes().collect(),
+            echoed: echoed.clone(),
+        }));
+        interpreter.run()?;
+
+        interpreter.assert_stack(&['é' as i64]);
+        assert_eq!(*echoed.lock().unwrap(), "é");
+
+        // the end of the terminal input is handled like the end of injected input
+        let mut config =
+            VmConfig::default_heap("resources/ws/read_char.ws", SourceType::Whitespace);
+        config.set_eof_value(-1);
+        let mut interpreter = Vm::new(config)?;
+        interpreter.set_char_input(Box::new(Scripted {
+            bytes: VecDeque::new(),
+            echoed,
+        }));
+        interpreter.run()?;
+
+        interpreter.assert_stack(&[-1]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn jump_table() -> Result<(), VmError> {
+        let config = || {
+            let mut config =
+                VmConfig::default_no_heap("resources/ws/jump_table.ws", SourceType::Whitespace);
+            config.set_extensions(true);
+            config
+        };
+        for (stack, expected) in [
+            (vec![0], b"a"),
+            (vec![1], b"b"),
+            (vec![2], b"c"),
+            (vec![1, -3], b"b"),
+        ] {
+            let mut interpreter = Vm::with_initial_state(config(), stack, vec![])?;
+            interpreter.capture_output();
+            interpreter.run()?;
+
+            assert_eq!(interpreter.take_output(), expected);
+        }
+
+        let mut interpreter = Vm::with_initial_state(config(), vec![20], vec![])?;
+        let err = interpreter.run().unwrap_err();
+
+        assert!(matches!(
+            err.kind,
+            VmErrorKind::NumberOutOfBoundsError(Instruction::JumpDynamic, 23, 0, 17)
+        ));
+
+        // without extensions, the program is not even parsed
+        let config =
+            VmConfig::default_no_heap("resources/ws/jump_table.ws", SourceType::Whitespace);
+        let err = Vm::new(config).err().unwrap();
+
+        assert!(matches!(err.kind, VmErrorKind::ParseError(_)));
+        assert!(err.msg.contains("expected one of ['\\n']"));
+
+        let source = std::fs::read("resources/ws/jump_table.ws")?;
+        let program = Program::from_bytes_with_extensions(&source, SourceType::Whitespace)?;
+        let config = VmConfig::default_no_heap("jump_table", SourceType::Whitespace);
+        let err = Vm::from_program(config, program).err().unwrap();
+
+        assert!(matches!(
+            err.kind,
+            VmErrorKind::ExtensionDisabled(Instruction::JumpDynamic)
+        ));
+        assert!(Program::from_bytes(&source, SourceType::Whitespace).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn syscall() -> Result<(), VmError> {
+        let mut config =
+            VmConfig::default_no_heap("resources/ws/syscall.ws", SourceType::Whitespace);
+        config.set_extensions(true);
+        config.register_syscall(7, |stack| stack.push(40));
+        let mut interpreter = Vm::new(config)?;
+        interpreter.capture_output();
+        let err = interpreter.run().unwrap_err();
+
+        assert_eq!(interpreter.take_output(), b"42");
+        assert!(matches!(
+            err.kind,
+            VmErrorKind::UnknownSyscall(Instruction::Syscall(Number { value: 8 }))
+        ));
+
+        // the results are fit into the word size like the results of arithmetic
+        let config = |checked| {
+            let mut config =
+                VmConfig::default_no_heap("resources/ws/syscall.ws", SourceType::Whitespace);
+            config.set_extensions(true);
+            config.set_word_size(WordSize::I32);
+            config.set_checked_arithmetic(checked);
+            config.register_syscall(7, |stack| stack.push(i64::from(i32::MAX) + 1));
+
+            config
+        };
+        let err = run_config(config(false), b"").err().unwrap();
+
+        assert_eq!(err.output(), b"-2147483646");
+        assert!(matches!(err.kind, VmErrorKind::UnknownSyscall(_)));
+
+        let err = run_config(config(true), b"").err().unwrap();
+
+        assert!(err.output().is_empty());
+        assert!(matches!(
+            err.kind,
+            VmErrorKind::ArithmeticOverflow(Instruction::Syscall(_))
+        ));
+
+        // a result replacing the popped argument is fit as well
+        let source = asm::assemble("push 2\nsyscall 7\noutn\nend")
+            .map_err(|err| VmErrorKind::ParseError(Box::new(err)).error())?;
+        let source = asm::encode(&source);
+        let interpreter = |checked| {
+            let program = Program::from_bytes_with_extensions(&source, SourceType::Whitespace)?;
+            let mut config = VmConfig::default_no_heap("syscall", SourceType::Whitespace);
+            config.set_extensions(true);
+            config.set_word_size(WordSize::I32);
+            config.set_checked_arithmetic(checked);
+            config.register_syscall(7, |stack| {
+                let a = stack.pop().unwrap();
+                stack.push(a * 4_000_000_000)
+            });
+
+            Vm::from_program(config, program)
+        };
+
+        assert_eq!(run_vm(interpreter(false)?, b"")?.1, b"-589934592");
+
+        let err = run_vm(interpreter(true)?, b"").err().unwrap();
+
+        assert!(err.output().is_empty());
+        assert!(matches!(
+            err.kind,
+            VmErrorKind::ArithmeticOverflow(Instruction::Syscall(_))
+        ));
+
+        let config = VmConfig::default_no_heap("resources/ws/syscall.ws", SourceType::Whitespace);
+        let err = Vm::new(config).err().unwrap();
+
+        assert!(matches!(err.kind, VmErrorKind::ParseError(_)));
+
+        let source = std::fs::read("resources/ws/syscall.ws")?;
+        let program = Program::from_bytes_with_extensions(&source, SourceType::Whitespace)?;
+        let config = VmConfig::default_no_heap("syscall", SourceType::Whitespace);
+        let err = Vm::from_program(config, program).err().unwrap();
+
+        assert!(matches!(
+            err.kind,
+            VmErrorKind::ExtensionDisabled(Instruction::Syscall(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn reset_keep_heap() -> Result<(), VmError> {
+        let config =
+            VmConfig::default_heap("resources/ws/increment_cell.ws", SourceType::Whitespace);
+        let mut interpreter = Vm::with_initial_state(config, vec![], vec![41])?;
+
+        interpreter.run()?;
+        interpreter.reset_keep_heap();
+
+        interpreter.assert_heap_cells(&[(0, 42)]);
+
+        interpreter.run()?;
+
+        interpreter.assert_heap_cells(&[(0, 43)]);
+
+        interpreter.reset();
+
+        interpreter.assert_heap_cells(&[(0, 0)]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn coverage() -> Result<(), VmError> {
+        let config = VmConfig::default_heap("resources/ws/branch.ws", SourceType::Whitespace);
+        let mut interpreter = Vm::new(config)?;
+        interpreter.set_input(Box::new(Cursor::new(b"1\n")));
+        interpreter.capture_output();
+        interpreter.run()?;
+
+        assert_eq!(
+            interpreter.coverage(),
+            [true, true, true, true, true, true, true, true, false, false, false, false]
+        );
+        assert!((interpreter.coverage_percent() - 200.0 / 3.0).abs() < 1e-9);
+
+        interpreter.reset();
+        interpreter.set_input(Box::new(Cursor::new(b"0\n")));
+        interpreter.run()?;
+
+        assert!(interpreter.coverage().iter().all(|executed| *executed));
+        assert_eq!(interpreter.coverage_percent(), 100.0);
+
+        interpreter.clear_coverage();
+
+        assert_eq!(interpreter.coverage_percent(), 0.0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn new_streaming() -> Result<(), VmError> {
+        for (file, heap) in [
+            ("resources/ws/interpret_stack.ws", false),
+            ("resources/ws/interpret_heap.ws", true),
+            ("resources/ws/interpret_flow.ws", false),
+        ] {
+            let config = || match heap {
+                true => VmConfig::default_heap_suppressed(file, SourceType::Whitespace),
+                false => VmConfig::default_no_heap_suppressed(file, SourceType::Whitespace),
+            };
+            let mut buffered = Vm::new(config())?;
+            buffered.run()?;
+            let mut streamed = Vm::new_streaming(config())?;
+            streamed.run()?;
+
+            assert!(streamed.is_done());
+            assert_eq!(streamed.stack(), buffered.stack());
+            assert_eq!(streamed.heap(), buffered.heap());
+            assert_eq!(streamed.last_executed(), buffered.last_executed());
+
+            streamed.reset();
+            streamed.run()?;
+
+            assert_eq!(streamed.stack(), buffered.stack());
+        }
+
+        let config =
+            VmConfig::default_no_heap_suppressed("resources/ws/no_exit.ws", SourceType::Whitespace);
+        let result = Vm::new_streaming(config)?.run();
+
+        match result.unwrap_err().kind {
+            VmErrorKind::NoTermination(Some(Instruction::OutInteger), 0, history) => {
+                assert_eq!(history, [0, 1, 2, 3])
+            }
+            kind => panic!("unexpected error {:?}", kind),
+        }
+
+        // the whole program is parsed before the output in front of the parse error is written
+        let config = VmConfig::default_no_heap(
+            "resources/ws/output_then_parse_error.ws",
+            SourceType::Whitespace,
+        );
+        let result = Vm::new_streaming(config);
+
+        assert!(matches!(
+            result.map(|_| ()).unwrap_err().kind,
+            VmErrorKind::ParseError(_)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn no_termination_reports_recursion() -> Result<(), VmError> {
+        let config =
+            VmConfig::default_no_heap("resources/ws/recursion_no_exit.ws", SourceType::Whitespace);
+        let err = Vm::new(config)?.run().unwrap_err();
+
+        match &err.kind {
+            VmErrorKind::NoTermination(Some(Instruction::DiscardStack), 3, history) => {
+                assert_eq!(history, &[2, 3, 4, 5, 6, 2, 3, 9])
+            }
+            kind => panic!("unexpected error {:?}", kind),
+        }
+        assert!(err.msg.contains("with call depth 3"));
+
+        let mut config =
+            VmConfig::default_no_heap("resources/ws/recursion_no_exit.ws", SourceType::Whitespace);
+        config.set_history_len(2);
+        let err = Vm::new(config)?.run().unwrap_err();
+
+        assert!(matches!(
+            err.kind,
+            VmErrorKind::NoTermination(_, 3, history) if history == [3, 9]
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn implicit_exit() -> Result<(), VmError> {
+        let err = execute("resources/ws/no_exit.ws", SourceType::Whitespace, b"").unwrap_err();
+
+        assert!(matches!(
+            err.kind,
+            VmErrorKind::NoTermination(Some(Instruction::OutInteger), 0, _)
+        ));
+        assert_eq!(err.output(), b"24");
+
+        let mut config = VmConfig::default_heap("resources/ws/no_exit.ws", SourceType::Whitespace);
+        config.set_implicit_exit(true);
+        let mut interpreter = Vm::new(config)?;
+        interpreter.capture_output();
+        interpreter.run()?;
+
+        assert_eq!(interpreter.take_output(), b"24");
+
+        let empty = |implicit_exit| -> Result<(), VmError> {
+            let mut config = VmConfig::default_no_heap("empty", SourceType::Whitespace);
+            config.set_implicit_exit(implicit_exit);
+            let program = Program::from_bytes(b"", SourceType::Whitespace)?;
+
+            Vm::from_program(config, program)?.run()
+        };
+
+        assert!(matches!(
+            empty(false).unwrap_err().kind,
+            VmErrorKind::NoTermination(None, 0, _)
+        ));
+        assert!(empty(true).is_ok());
+
+        let mut config = VmConfig::default_heap("resources/ws/no_exit.ws", SourceType::Whitespace);
+        config.set_implicit_exit(true);
+        config.set_strict(true);
+        let mut interpreter = Vm::new(config)?;
+        interpreter.capture_output();
+
+        assert!(matches!(
+            interpreter.run().unwrap_err().kind,
+            VmErrorKind::NoTermination(Some(Instruction::OutInteger), 0, _)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn instruction_at() -> Result<(), VmError> {
+        let config = VmConfig::default_no_heap_suppressed(
+            "resources/ws/interpret_stack.ws",
+            SourceType::Whitespace,
+        );
+        let interpreter = Vm::new(config)?;
+
+        assert_eq!(interpreter.len(), 9);
+        assert!(!interpreter.is_empty());
+        assert_eq!(interpreter.instruction_at(8), Some(&Instruction::Exit));
+        assert_eq!(interpreter.instruction_at(9), None);
+        assert_eq!(interpreter.instruction_at(usize::MAX), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn output_buffering() -> Result<(), VmError> {
+        let run = |buffering| -> Result<(Vec<u8>, usize, usize), VmError> {
+            let mut config =
+                VmConfig::default_heap("resources/ws/hello_world.ws", SourceType::Whitespace);
+            config.set_output_buffering(buffering);
+            let mut interpreter = Vm::new(config)?;
+            let writer = RecordingWriter::default();
+            interpreter.set_output(Box::new(writer.clone()));
+            interpreter.run()?;
+            let record = writer.0.lock().unwrap().clone();
+
+            Ok(record)
+        };
+
+        assert_eq!(
+            run(Buffering::PerChar)?,
+            (b"Hello, world!".to_vec(), 13, 14)
+        );
+        assert_eq!(run(Buffering::Line)?, (b"Hello, world!".to_vec(), 13, 1));
+        assert_eq!(run(Buffering::Block)?, (b"Hello, world!".to_vec(), 1, 1));
+
+        Ok(())
+    }
+
+    #[test]
+    fn output_sink() -> Result<(), VmError> {
+        let config =
+            || VmConfig::default_heap("resources/ws/hello_world.ws", SourceType::Whitespace);
+        let sink = |limit, writer: &RecordingWriter| {
+            let mut sink = OutputSink::writer(Box::new(writer.clone()));
+            sink.set_buffering(Buffering::Block);
+            sink.set_limit(Some(limit));
+
+            sink
+        };
+
+        let writer = RecordingWriter::default();
+        let mut interpreter = Vm::new(config())?;
+        interpreter.set_output_sink(sink(13, &writer));
+        interpreter.run()?;
+        drop(interpreter);
+
+        assert_eq!(*writer.0.lock().unwrap(), (b"Hello, world!".to_vec(), 1, 2));
+
+        let writer = RecordingWriter::default();
+        let mut interpreter = Vm::new(config())?;
+        interpreter.set_output_sink(sink(5, &writer));
+        let err = interpreter.run().unwrap_err();
+        drop(interpreter);
+
+        assert!(matches!(
+            err.kind,
+            VmErrorKind::OutputLimitExceeded(Instruction::OutCharacter, 5)
+        ));
+        assert_eq!(*writer.0.lock().unwrap(), (b"Hello".to_vec(), 1, 1));
+
+        // the limit is kept when the output is captured, only the destination changes
+        let mut interpreter = Vm::new(config())?;
+        interpreter.set_output_sink(sink(5, &writer));
+        let err = run_vm(interpreter, b"").err().unwrap();
+
+        assert!(matches!(
+            err.kind,
+            VmErrorKind::OutputLimitExceeded(Instruction::OutCharacter, 5)
+        ));
+        assert_eq!(err.output(), b"Hello");
+
+        Ok(())
+    }
+
+    #[test]
+    fn interpret_stack() -> Result<(), VmError> {
+        let config = VmConfig::default_no_heap_suppressed(
+            "resources/ws/interpret_stack.ws",
+            SourceType::Whitespace,
+        );
+        let mut interpreter = Vm::new(config)?;
+
+        interpreter.run()?;
+
+        assert_eq!(interpreter.stack, vec![-1]);
+        assert!(interpreter.heap.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn heap_allocated_on_first_write() -> Result<(), VmError> {
+        let config =
+            VmConfig::default_heap("resources/ws/interpret_stack.ws", SourceType::Whitespace);
+        let mut interpreter = Vm::new(config)?;
+
+        interpreter.run()?;
+
+        assert_eq!(interpreter.heap.capacity(), 0);
+        assert_eq!(interpreter.heap_size(), DEFAULT_HEAP_SIZE);
+
+        let config =
+            VmConfig::default_heap("resources/ws/interpret_heap.ws", SourceType::Whitespace);
+        let mut interpreter = Vm::new(config)?;
+        assert_eq!(interpreter.heap.capacity(), 0);
+
+        interpreter.run()?;
+
+        assert_eq!(interpreter.heap.len(), interpreter.heap_size());
+
+        Ok(())
+    }
+
+    #[test]
+    fn interpret_arithmetic() -> Result<(), VmError> {
+        let config = VmConfig::default_no_heap_suppressed(
+            "resources/ws/interpret_arithmetic.ws",
+            SourceType::Whitespace,
+        );
+        let mut interpreter = Vm::new(config)?;
+
+        interpreter.run()?;
+
+        assert_eq!(interpreter.stack, vec![4]);
+        assert!(interpreter.heap.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn interpret_heap() -> Result<(), VmError> {
+        let config = VmConfig::default_heap_suppressed(
+            "resources/ws/interpret_heap.ws",
+            SourceType::Whitespace,
+        );
+        let mut interpreter = Vm::new(config)?;
+
+        interpreter.run()?;
+
+        assert_eq!(interpreter.stack, vec![-8, 10]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn interpret_flow() -> Result<(), VmError> {
+        let config = VmConfig::default_no_heap_suppressed(
+            "resources/ws/interpret_flow.ws",
+            SourceType::Whitespace,
+        );
+        let mut interpreter = Vm::new(config)?;
+
+        interpreter.run()?;
+        assert_eq!(interpreter.stack, Vec::<i64>::new());
+
+        Ok(())
+    }
+
+    #[test]
+    fn interpret_io() -> Result<(), VmError> {
+        let config = VmConfig::default_no_heap_suppressed(
+            "resources/ws/interpret_io.ws",
+            SourceType::Whitespace,
+        );
+        let mut interpreter = Vm::new(config)?;
+
+        interpreter.run()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn interpret_word_size_i64() -> Result<(), VmError> {
+        let mut config = VmConfig::default_no_heap_suppressed(
+            "resources/ws/word_size.ws",
+            SourceType::Whitespace,
+        );
+        config.set_word_size(WordSize::I64);
+        let mut interpreter = Vm::new(config)?;
+
+        interpreter.run()?;
+
+        assert_eq!(interpreter.stack, vec![6_000_000_000]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn interpret_word_size_i32_wraps() -> Result<(), VmError> {
+        let config = VmConfig::default_no_heap_suppressed(
+            "resources/ws/word_size.ws",
+            SourceType::Whitespace,
+        );
+        let mut interpreter = Vm::new(config)?;
+
+        interpreter.run()?;
+
+        assert_eq!(
+            interpreter.stack,
+            vec![(3_000_000_000_i64 as i32).wrapping_mul(2) as i64]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn interpret_swap_insufficient_stack() -> Result<(), VmError> {
+        let config = VmConfig::default_no_heap_suppressed(
+            "resources/ws/swap_one.ws",
+            SourceType::Whitespace,
+        );
+        let mut interpreter = Vm::new(config)?;
+
+        let err = interpreter.run().unwrap_err();
+
+        assert!(matches!(err.kind, VmErrorKind::InsufficientStack(_, 2, 1)));
+        assert_eq!(err.msg, "SwapStack needs 2 value(s) on the stack, found 1");
+
+        Ok(())
+    }
+
+    #[test]
+    fn interpret_duplicate_insufficient_stack() -> Result<(), VmError> {
+        let config = VmConfig::default_no_heap_suppressed(
+            "resources/ws/duplicate_empty.ws",
+            SourceType::Whitespace,
+        );
+        let mut interpreter = Vm::new(config)?;
+
+        let err = interpreter.run().unwrap_err();
+
+        assert!(matches!(err.kind, VmErrorKind::InsufficientStack(_, 1, 0)));
+        assert_eq!(
+            err.msg,
+            "DuplicateStack needs 1 value(s) on the stack, found 0"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn execute_increment() -> Result<(), VmError> {
+        let result = execute("resources/ws/increment.ws", SourceType::Whitespace, b"41\n")?;
+
+        assert_eq!(result.output, b"42");
+        assert_eq!(result.stack, vec![42]);
+        assert_eq!(result.heap, BTreeMap::from([(0, 41)]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn int_input_mode() -> Result<(), VmError> {
+        let config = |mode| {
+            let mut config =
+                VmConfig::default_heap("resources/ws/increment.ws", SourceType::Whitespace);
+            config.set_int_input(mode);
+
+            config
+        };
+
+        for input in [&b"3.14\n"[..], b"-2.9\n", b"pi\n"] {
+            assert!(matches!(
+                run_config(config(IntInputMode::Strict), input)
+                    .err()
+                    .unwrap()
+                    .kind,
+                VmErrorKind::InvalidIntegerInput(_)
+            ));
+        }
+        let truncate = || config(IntInputMode::TruncateFloat);
+
+        assert_eq!(run_config(truncate(), b"3.14\n")?.1, b"4");
+        assert_eq!(run_config(truncate(), b"-2.9\n")?.1, b"-1");
+        assert_eq!(run_config(truncate(), b"41\n")?.1, b"42");
+        assert!(matches!(
+            run_config(truncate(), b"pi\n").err().unwrap().kind,
+            VmErrorKind::InvalidIntegerInput(_)
+        ));
+        assert!(matches!(
+            run_config(truncate(), b"3e10\n").err().unwrap().kind,
+            VmErrorKind::ArithmeticOverflow(Instruction::ReadInteger)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn execute_increment_invalid_input() {
+        let err = execute(
+            "resources/ws/increment.ws",
+            SourceType::Whitespace,
+            b"forty-one\n",
+        )
+        .unwrap_err();
+
+        assert!(matches!(err.kind, VmErrorKind::InvalidIntegerInput(_)));
+
+        let err = execute("resources/ws/increment.ws", SourceType::Whitespace, b"+\n").unwrap_err();
+
+        assert!(matches!(err.kind, VmErrorKind::InvalidIntegerInput(_)));
+
+        // whitespace only separates integers, so an empty line is no integer at all
+        let err = execute("resources/ws/increment.ws", SourceType::Whitespace, b"\n").unwrap_err();
+
+        assert!(matches!(err.kind, VmErrorKind::EndOfInput(_)));
+    }
+
+    #[test]
+    fn execute_gzip() -> Result<(), VmError> {
+        let plain = execute("resources/ws/hello_world.ws", SourceType::Whitespace, b"")?;
+        let compressed = execute(
+            "resources/ws/hello_world.ws.gz",
+            SourceType::Whitespace,
+            b"",
+        )?;
+
+        assert_eq!(compressed, plain);
+
+        Ok(())
+    }
+
+    #[test]
+    fn interpret_generated_input() -> Result<(), VmError> {
+        let mut config = VmConfig::default_heap("resources/ws/echo.ws", SourceType::Whitespace);
+        config.set_generated_input("hi");
+        let mut interpreter = Vm::new(config)?;
+        interpreter.capture_output();
+        interpreter.run()?;
+        interpreter.reset();
+        interpreter.run()?;
+
+        assert_eq!(interpreter.take_output(), b"hi\nhi\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn heap_dump_filter() -> Result<(), VmError> {
+        let mut config =
+            VmConfig::default_heap_suppressed("resources/ws/store_zero.ws", SourceType::Whitespace);
+        config.set_heap_dump_range(0, 4);
+        let mut interpreter = Vm::new(config)?;
+        interpreter.run()?;
+
+        assert_eq!(
+            interpreter.generate_debug_heap_dump(),
+            BTreeMap::from([(1, 5)])
+        );
+
+        let mut config =
+            VmConfig::default_heap_suppressed("resources/ws/store_zero.ws", SourceType::Whitespace);
+        config.set_heap_dump_filter(HeapDumpFilter::Written);
+        let mut interpreter = Vm::new(config)?;
+        interpreter.run()?;
+
+        assert_eq!(
+            interpreter.generate_debug_heap_dump(),
+            BTreeMap::from([(0, 0), (1, 5)])
+        );
+
+        let mut config =
+            VmConfig::default_heap_suppressed("resources/ws/store_zero.ws", SourceType::Whitespace);
+        config.set_heap_dump_filter(HeapDumpFilter::All);
+        config.set_heap_dump_range(1, 3);
+        let mut interpreter = Vm::new(config)?;
+        interpreter.run()?;
+
+        assert_eq!(
+            interpreter.generate_debug_heap_dump(),
+            BTreeMap::from([(1, 5), (2, 0)])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn output_before_error() -> Result<(), VmError> {
+        let config =
+            VmConfig::default_heap("resources/ws/output_then_trap.ws", SourceType::Whitespace);
+        let mut interpreter = Vm::new(config)?;
+        interpreter.capture_output();
+
+        assert!(interpreter.run().is_err());
+        assert_eq!(interpreter.take_output(), b"Hi");
+
+        let err = execute(
+            "resources/ws/output_then_trap.ws",
+            SourceType::Whitespace,
+            b"",
+        )
+        .unwrap_err();
+
+        assert!(matches!(err.kind, VmErrorKind::DivisionByZero(_)));
+        assert_eq!(err.output(), b"Hi");
+
+        Ok(())
+    }
+
+    fn encode(character: char, encoding: OutputEncoding) -> Result<Vec<u8>, VmError> {
+        let mut config =
+            VmConfig::default_no_heap("resources/ws/out_char.ws", SourceType::Whitespace);
+        config.set_output_encoding(encoding);
+        let mut interpreter = Vm::with_initial_state(config, vec![character as i64], vec![])?;
+        interpreter.capture_output();
+        interpreter.run()?;
+
+        Ok(interpreter.take_output())
+    }
+
+    #[test]
+    fn output_encoding() -> Result<(), VmError> {
+        assert_eq!(encode('é', OutputEncoding::Utf8)?, [0xc3, 0xa9]);
+        assert_eq!(
+            encode('😀', OutputEncoding::Utf8)?,
+            [0xf0, 0x9f, 0x98, 0x80]
+        );
+        assert_eq!(encode('é', OutputEncoding::Utf16Le)?, [0xe9, 0x00]);
+        assert_eq!(
+            encode('😀', OutputEncoding::Utf16Le)?,
+            [0x3d, 0xd8, 0x00, 0xde]
+        );
+        assert_eq!(encode('é', OutputEncoding::Raw)?, [0xe9, 0x00, 0x00, 0x00]);
+        assert_eq!(encode('😀', OutputEncoding::Raw)?, [0x00, 0xf6, 0x01, 0x00]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn interpret_seeded_input() -> Result<(), VmError> {
+        let run = |seed| -> Result<Vec<u8>, VmError> {
+            let mut config =
+                VmConfig::default_heap("resources/ws/read_twice.ws", SourceType::Whitespace);
+            config.set_input_seed(seed);
+            let mut interpreter = Vm::new(config)?;
+            interpreter.capture_output();
+            interpreter.run()?;
+            interpreter.reset();
+            interpreter.run()?;
+
+            Ok(interpreter.take_output())
+        };
+        let output = run(42)?;
+
+        assert!(!output.is_empty());
+        assert_eq!(run(42)?, output);
+        assert_eq!(output[..output.len() / 2], output[output.len() / 2..]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn interpreter_is_send() {
+        fn assert_send<T: Send>() {}
+        assert_send::<Vm>();
+        assert_send::<VmConfig>();
+        assert_send::<InputQueue>();
+    }
+
+    #[test]
+    fn on_heap_write() -> Result<(), VmError> {
+        let writes = Arc::new(Mutex::new(vec![]));
+        let mut config =
+            VmConfig::default_heap_suppressed("resources/ws/store_zero.ws", SourceType::Whitespace);
+        let recorded = writes.clone();
+        config.set_on_heap_write(move |addr, val| recorded.lock().unwrap().push((addr, val)));
+        let mut interpreter = Vm::with_initial_state(config, vec![], vec![7])?;
+        interpreter.run()?;
+
+        assert_eq!(*writes.lock().unwrap(), vec![(0, 0), (1, 5)]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn on_stack_change() -> Result<(), VmError> {
+        let depths = Arc::new(Mutex::new(vec![]));
+        let mut config = VmConfig::default_no_heap_suppressed(
+            "resources/ws/stack_depth.ws",
+            SourceType::Whitespace,
+        );
+        let recorded = depths.clone();
+        config.set_on_stack_change(move |depth| recorded.lock().unwrap().push(depth));
+        let mut interpreter = Vm::new(config)?;
+        interpreter.run()?;
+
+        assert_eq!(*depths.lock().unwrap(), vec![1, 2, 1, 2, 1, 0]);
+
+        // the failing add pops its right operand before it underflows
+        depths.lock().unwrap().clear();
+        let source = asm::assemble("push 1\nadd\nend")
+            .map_err(|err| VmErrorKind::ParseError(Box::new(err)).error())?;
+        let program = Program::from_bytes(&asm::encode(&source), SourceType::Whitespace)?;
+        let mut config = VmConfig::default_no_heap_suppressed("underflow", SourceType::Whitespace);
+        let recorded = depths.clone();
+        config.set_on_stack_change(move |depth| recorded.lock().unwrap().push(depth));
+        let mut interpreter = Vm::from_program(config, program)?;
+
+        assert!(interpreter.run().is_err());
+        assert_eq!(*depths.lock().unwrap(), vec![1]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn on_enter_label() -> Result<(), VmError> {
+        let labels = Arc::new(Mutex::new(vec![]));
+        let mut config =
+            VmConfig::default_no_heap("resources/ws/two_subroutines.ws", SourceType::Whitespace);
+        let recorded = labels.clone();
+        config.set_on_enter_label(move |label| recorded.lock().unwrap().push(label.to_string()));
+        let mut interpreter = Vm::new(config)?;
+        interpreter.run()?;
+
+        // main calls the first subroutine, then the second one, which calls the first one again
+        assert_eq!(*labels.lock().unwrap(), [" ", "\t", " ", "\t"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn on_stack_growth() -> Result<(), VmError> {
+        let depths = Arc::new(Mutex::new(vec![]));
+        let config = |file| {
+            let mut config = VmConfig::default_no_heap_suppressed(file, SourceType::Whitespace);
+            let recorded = depths.clone();
+            config.set_on_stack_growth(70, move |depth| recorded.lock().unwrap().push(depth));
+
+            config
+        };
+
+        // every iteration of the loop leaves one more item on the stack
+        run_config(config("resources/ws/push_loop.ws"), b"")?;
+        let reported = std::mem::take(&mut *depths.lock().unwrap());
+
+        assert!(!reported.is_empty());
+        assert!(reported.windows(2).all(|pair| pair[0] < pair[1]));
+
+        run_config(config("resources/ws/count.ws"), b"")?;
+
+        assert!(depths.lock().unwrap().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn run_slice_interleaved() -> Result<(), VmError> {
+        let sources = ["resources/ws/hello_world.ws", "resources/ws/count.ws"];
+        let mut interpreters = vec![];
+        for source in sources {
+            let mut interpreter = Vm::new(VmConfig::default_heap(source, SourceType::Whitespace))?;
+            interpreter.capture_output();
+            interpreters.push(interpreter);
+        }
+        let mut finished = [false; 2];
+        let mut slices = 0;
+        while finished.contains(&false) {
+            for (interpreter, finished) in interpreters.iter_mut().zip(finished.iter_mut()) {
+                if !*finished {
+                    *finished = interpreter.run_slice(5)? == RunSliceOutcome::Finished;
+                }
+            }
+            slices += 1;
+        }
+
+        assert!(slices > 1);
+        for (interpreter, source) in interpreters.iter_mut().zip(sources) {
+            let expected = execute(source, SourceType::Whitespace, b"")?;
+
+            assert_eq!(interpreter.take_output(), expected.output);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn interpret_read_int_tokens() -> Result<(), VmError> {
+        let mut config =
+            VmConfig::default_heap("resources/ws/read_twice.ws", SourceType::Whitespace);
+        config.set_eof_value(-1);
+        let mut interpreter = Vm::new(config)?;
+        interpreter.set_input(Box::new(Cursor::new(b"  -5\t+6 7".to_vec())));
+        interpreter.capture_output();
+        interpreter.run()?;
+
+        assert_eq!(interpreter.take_output(), b"-56");
+
+        interpreter.reset();
+        interpreter.run()?;
+
+        assert_eq!(interpreter.take_output(), b"7-1");
+
+        Ok(())
+    }
+
+    #[test]
+    fn chained_input() -> Result<(), VmError> {
+        let config = VmConfig::default_heap("resources/ws/read_twice.ws", SourceType::Whitespace);
+        let mut interpreter = Vm::new(config)?;
+        let input = ChainedInput::new()
+            .then(Box::new(Cursor::new(b"1".to_vec())))
+            .then(Box::new(Cursor::new(b"2\n34\n".to_vec())));
+        interpreter.set_input(Box::new(input));
+        interpreter.capture_output();
+        interpreter.run()?;
+
+        assert_eq!(interpreter.take_output(), b"1234");
+
+        Ok(())
+    }
+
+    #[test]
+    fn run_slice_blocked_on_input() -> Result<(), VmError> {
+        let config = VmConfig::default_heap("resources/ws/read_twice.ws", SourceType::Whitespace);
+        let mut interpreter = Vm::new(config)?;
+        let input = InputQueue::new();
+        interpreter.set_input(Box::new(input.clone()));
+        interpreter.capture_output();
+
+        assert_eq!(interpreter.run_slice(100)?, RunSliceOutcome::Blocked);
+
+        input.push(b"12\n3");
+
+        assert_eq!(interpreter.run_slice(100)?, RunSliceOutcome::Blocked);
+        assert_eq!(interpreter.take_output(), b"12");
+
+        input.push(b"4\n");
+
+        assert_eq!(interpreter.run_slice(100)?, RunSliceOutcome::Finished);
+        assert_eq!(interpreter.take_output(), b"34");
+
+        Ok(())
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn run_async_with_input_from_another_task() -> Result<(), VmError> {
+        use std::future::Future;
+
+        let config = VmConfig::default_heap("resources/ws/read_twice.ws", SourceType::Whitespace);
+        let mut interpreter = Vm::new(config)?;
+        let input = InputQueue::new();
+        interpreter.capture_output();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let feed = input.clone();
+        // the program waiting for input must neither block the thread running this task nor be
+        // polled again until input arrives
+        runtime.spawn(async move {
+            for line in [b"4\n", b"2\n"] {
+                for _ in 0..100 {
+                    tokio::task::yield_now().await;
                 }
-                _ => {}
+                feed.push(line);
             }
-        }
+            feed.close();
+        });
+        let polls = {
+            let mut polls = 0;
+            let run = interpreter.run_async(input);
+            tokio::pin!(run);
+            runtime.block_on(std::future::poll_fn(|cx| {
+                polls += 1;
+                run.as_mut().poll(cx)
+            }))?;
+            polls
+        };
 
-        Ok(Vm {
-            config,
-            instructions,
-            stack,
-            call_stack,
-            heap,
-            instruction_pointer,
-            done,
-            instruction_count: 0,
-        })
-    }
+        assert_eq!(interpreter.take_output(), b"42");
+        assert!(polls <= 3, "polled {} times", polls);
 
-    /// Returns the next instruction to be executed in a `Some` variant. None if the program has
-    /// reached its end.
-    pub fn next_instruction(&self) -> Option<usize> {
-        if self.done {
-            return None;
-        }
-        if self.instruction_pointer < self.instructions.len() {
-            Some(self.instruction_pointer)
-        } else {
-            None
-        }
+        Ok(())
     }
 
-    /// Executes all instructions - runs the program.
-    pub fn run(&mut self) -> Result<(), VmError> {
-        while let Some(_) = self.next_instruction() {
-            self.exec()?;
-        }
+    #[test]
+    fn execute_copy_counts_from_top() -> Result<(), VmError> {
+        let result = execute("resources/ws/copy_one.ws", SourceType::Whitespace, b"")?;
 
-        let last = &self.instructions[self.instruction_pointer - 1];
-        if *last != Instruction::Exit {
-            return VmErrorKind::NoTermination(last.clone()).throw();
-        }
+        assert_eq!(result.output, b"2");
+        assert_eq!(result.stack, vec![1, 2, 3]);
 
         Ok(())
     }
 
-    /// Resets the internal interpreter state/the VM without re-parsing the source file
-    pub fn reset(&mut self) {
-        self.stack.clear();
-        self.call_stack.clear();
-        self.heap = vec![0; self.heap.len()];
-        self.instruction_pointer = 0;
-        self.done = false;
-    }
-
-    fn generate_debug_heap_dump(&self) -> BTreeMap<usize, i32> {
-        let mut heap_map = BTreeMap::new();
-        for (addr, val) in self.heap.iter().enumerate() {
-            if *val != 0 {
-                heap_map.insert(addr, *val);
-            }
-        }
-        heap_map
+    #[test]
+    fn checked_index_boundaries() {
+        assert_eq!(checked_index(0, 3usize), Some(0));
+        assert_eq!(checked_index(2, 3usize), Some(2));
+        assert_eq!(checked_index(3, 3usize), None);
+        assert_eq!(checked_index(-1, 3usize), None);
+        assert_eq!(checked_index(i64::MIN, usize::MAX), None);
+        // a 16 bit index type stands in for the usize of small targets
+        assert_eq!(checked_index(65534, u16::MAX), Some(65534));
+        assert_eq!(checked_index(65535, u16::MAX), None);
+        assert_eq!(checked_index(65536, u16::MAX), None);
+        assert_eq!(checked_index(i64::from(i32::MAX), u16::MAX), None);
     }
 
-    fn push_stack(&mut self) -> Result<(), VmError> {
-        if let Instruction::PushStack(num) = self.instructions[self.instruction_pointer] {
-            self.stack.push(num.value);
-
-            return Ok(());
+    #[test]
+    fn copy_boundaries() -> Result<(), VmError> {
+        let copy = |index: i64| -> Result<Vm, VmError> {
+            let source = asm::assemble(&format!("push 1\npush 2\npush 3\ncopy {}\nend", index))
+                .map_err(|err| VmErrorKind::ParseError(Box::new(err)).error())?;
+            let program = Program::from_bytes(&asm::encode(&source), SourceType::Whitespace)?;
+            let config = VmConfig::default_no_heap("copy", SourceType::Whitespace);
+
+            Vm::from_program(config, program)
         };
 
-        unreachable!();
+        assert_eq!(run_vm(copy(0)?, b"")?.0.stack(), [1, 2, 3, 3]);
+        assert_eq!(run_vm(copy(2)?, b"")?.0.stack(), [1, 2, 3, 1]);
+        assert!(matches!(
+            run_vm(copy(3)?, b"").err().unwrap().kind,
+            VmErrorKind::NumberOutOfBoundsError(Instruction::CopyNthStack(_), 3, 0, 2)
+        ));
+
+        Ok(())
     }
 
-    fn duplicate_stack(&mut self) -> Result<(), VmError> {
-        if let Some(val) = self.stack.pop() {
-            self.stack.push(val);
-            self.stack.push(val);
+    #[test]
+    fn interpret_slide_negative() -> Result<(), VmError> {
+        let config =
+            VmConfig::default_heap("resources/ws/slide_negative.ws", SourceType::Whitespace);
+        let mut interpreter = Vm::new(config)?;
+        let err = interpreter.run().unwrap_err();
 
-            return Ok(());
-        }
+        assert!(matches!(
+            err.kind,
+            VmErrorKind::NumberOutOfBoundsError(Instruction::SlideNStack(_), -1, 0, i64::MAX)
+        ));
+        assert_eq!(interpreter.stack, vec![1, 2]);
 
-        VmErrorKind::StackUnderflow(self.instructions[self.instruction_pointer].clone()).throw()
+        Ok(())
     }
 
-    fn copy_nth_stack(&mut self) -> Result<(), VmError> {
-        if let Instruction::CopyNthStack(num) = self.instructions[self.instruction_pointer] {
-            let addr = num.value;
-            if addr < 0 || addr as usize >= self.stack.len() {
-                return VmErrorKind::NumberOutOfBoundsError(
-                    self.instructions[self.instruction_pointer].clone(),
-                    addr,
-                    0,
-                    self.stack.len() as i32 - 1,
-                )
-                .throw();
-            }
-            let addr = addr as usize;
-            let val = self.stack[addr];
-            self.stack.push(val);
+    #[test]
+    fn execute_slide_more_than_stack() -> Result<(), VmError> {
+        let result = execute("resources/ws/slide_over.ws", SourceType::Whitespace, b"")?;
 
-            return Ok(());
-        }
+        assert_eq!(result.stack, vec![3]);
 
-        unreachable!();
+        Ok(())
     }
 
-    fn swap_stack(&mut self) -> Result<(), VmError> {
-        if let Some(val) = self.stack.pop() {
-            if let Some(other) = self.stack.pop() {
-                self.stack.push(val);
-                self.stack.push(other);
+    #[test]
+    fn execute_read_utf8_character() -> Result<(), VmError> {
+        let result = execute(
+            "resources/ws/read_char.ws",
+            SourceType::Whitespace,
+            "😀".as_bytes(),
+        )?;
 
-                return Ok(());
-            }
+        assert_eq!(result.stack, vec![0x1f600]);
 
-            return VmErrorKind::StackUnderflow(
-                self.instructions[self.instruction_pointer].clone(),
-            )
-            .throw();
-        }
+        let err =
+            execute("resources/ws/read_char.ws", SourceType::Whitespace, b"\xff").unwrap_err();
 
-        VmErrorKind::StackUnderflow(self.instructions[self.instruction_pointer].clone()).throw()
-    }
+        assert!(matches!(err.kind, VmErrorKind::Io(_)));
 
-    fn discard_stack(&mut self) -> Result<(), VmError> {
-        if self.stack.pop().is_some() {
-            return Ok(());
-        }
+        let err = execute(
+            "resources/ws/read_char.ws",
+            SourceType::Whitespace,
+            b"\xf0\x9f",
+        )
+        .unwrap_err();
 
-        VmErrorKind::StackUnderflow(self.instructions[self.instruction_pointer].clone()).throw()
-    }
+        assert!(matches!(err.kind, VmErrorKind::Io(_)));
 
-    fn slide_n_stack(&mut self) -> Result<(), VmError> {
-        if let Instruction::SlideNStack(num) = self.instructions[self.instruction_pointer] {
-            if let Some(top) = self.stack.pop() {
-                if num.value < 0 {
-                    return VmErrorKind::NumberOutOfBoundsError(
-                        self.instructions[self.instruction_pointer].clone(),
-                        num.value,
-                        0,
-                        i32::MAX,
-                    )
-                    .throw();
-                }
-                for _i in 0..num.value {
-                    self.stack.pop();
-                }
-                self.stack.push(top);
+        // the byte after an incomplete character is read as the next character
+        let source = asm::assemble("push 0\nreadc\npush 1\nreadc\nend")
+            .map_err(|err| VmErrorKind::ParseError(Box::new(err)).error())?;
+        let program = Program::from_bytes(&asm::encode(&source), SourceType::Whitespace)?;
+        let config = VmConfig::default_heap("read_chars", SourceType::Whitespace);
+        let mut interpreter = Vm::from_program(config, program)?;
+        interpreter.set_input(Box::new(Cursor::new(b"\xc3A".to_vec())));
 
-                return Ok(());
-            }
+        assert!(matches!(
+            interpreter.run().unwrap_err().kind,
+            VmErrorKind::Io(_)
+        ));
 
-            return VmErrorKind::StackUnderflow(
-                self.instructions[self.instruction_pointer].clone(),
-            )
-            .throw();
-        };
+        interpreter.run()?;
 
-        unreachable!();
-    }
+        assert_eq!(&interpreter.heap()[..2], [0, 'A' as i64]);
 
-    fn add(&mut self) -> Result<(), VmError> {
-        if let Some(right) = self.stack.pop() {
-            if let Some(left) = self.stack.pop() {
-                self.stack.push(left + right);
+        Ok(())
+    }
 
-                return Ok(());
-            }
-        }
+    #[test]
+    fn output_error_fail() {
+        let err = run_with_broken_pipe(OutputErrorPolicy::Fail).err().unwrap();
 
-        VmErrorKind::StackUnderflow(self.instructions[self.instruction_pointer].clone()).throw()
+        assert!(matches!(err.kind, VmErrorKind::IOError(_)));
     }
 
-    fn subtract(&mut self) -> Result<(), VmError> {
-        if let Some(right) = self.stack.pop() {
-            if let Some(left) = self.stack.pop() {
-                self.stack.push(left - right);
+    #[test]
+    fn output_error_stop_silently() -> Result<(), VmError> {
+        let interpreter = run_with_broken_pipe(OutputErrorPolicy::StopSilently)?;
+        let complete = run_with_broken_pipe(OutputErrorPolicy::Ignore)?;
 
-                return Ok(());
-            }
-        }
+        assert!(interpreter.next_instruction().is_none());
+        assert!(interpreter.instruction_count < complete.instruction_count);
 
-        VmErrorKind::StackUnderflow(self.instructions[self.instruction_pointer].clone()).throw()
+        Ok(())
     }
 
-    fn multiply(&mut self) -> Result<(), VmError> {
-        if let Some(right) = self.stack.pop() {
-            if let Some(left) = self.stack.pop() {
-                self.stack.push(left * right);
+    #[test]
+    fn max_output_bytes() -> Result<(), VmError> {
+        let mut config = VmConfig::default_no_heap("resources/ws/count.ws", SourceType::Whitespace);
+        config.set_max_output_bytes(10);
+        let mut interpreter = Vm::new(config)?;
+        interpreter.capture_output();
+        let err = interpreter.run().unwrap_err();
 
-                return Ok(());
-            }
-        }
+        assert!(matches!(
+            err.kind,
+            VmErrorKind::OutputLimitExceeded(Instruction::OutInteger, 10)
+        ));
+        assert_eq!(interpreter.take_output(), b"1\n2\n3\n4\n5\n");
 
-        VmErrorKind::StackUnderflow(self.instructions[self.instruction_pointer].clone()).throw()
+        interpreter.reset();
+        assert!(interpreter.run().is_err());
+        assert_eq!(interpreter.take_output(), b"1\n2\n3\n4\n5\n");
+
+        Ok(())
     }
 
-    fn integer_division(&mut self) -> Result<(), VmError> {
-        if let Some(right) = self.stack.pop() {
-            if let Some(left) = self.stack.pop() {
-                self.stack.push(left / right);
+    #[test]
+    fn labels() -> Result<(), VmError> {
+        let config = VmConfig::default_no_heap_suppressed(
+            "resources/ws/interpret_flow.ws",
+            SourceType::Whitespace,
+        );
+        let interpreter = Vm::new(config)?;
 
-                return Ok(());
-            }
-        }
+        assert_eq!(interpreter.labels().len(), 2);
+        assert_eq!(interpreter.labels().get(" \t    \t\t"), Some(&1));
+        assert_eq!(interpreter.labels().get(" \t   \t \t"), Some(&13));
 
-        VmErrorKind::StackUnderflow(self.instructions[self.instruction_pointer].clone()).throw()
+        Ok(())
     }
 
-    fn modulo(&mut self) -> Result<(), VmError> {
-        if let Some(right) = self.stack.pop() {
-            if let Some(left) = self.stack.pop() {
-                self.stack.push(left % right);
+    #[test]
+    fn breakpoint_at_label() -> Result<(), VmError> {
+        let config = VmConfig::default_no_heap_suppressed(
+            "resources/ws/interpret_flow.ws",
+            SourceType::Whitespace,
+        );
+        let mut interpreter = Vm::new(config)?;
+        assert_eq!(interpreter.add_breakpoint_at_label(" \t   \t \t")?, 14);
+        assert_eq!(interpreter.resume()?, Some(14));
+        assert_eq!(interpreter.stack, vec![11]);
+        assert_eq!(interpreter.resume()?, None);
+        assert!(interpreter.add_breakpoint_at_label("\t\t").is_err());
 
-                return Ok(());
-            }
-        }
+        let source = asm::assemble("push 1\nlabel L1")
+            .map_err(|err| VmErrorKind::ParseError(Box::new(err)).error())?;
+        let program = Program::from_bytes(&asm::encode(&source), SourceType::Whitespace)?;
+        let config = VmConfig::default_no_heap("trailing", SourceType::Whitespace);
+        let mut interpreter = Vm::from_program(config, program)?;
 
-        VmErrorKind::StackUnderflow(self.instructions[self.instruction_pointer].clone()).throw()
+        assert_eq!(interpreter.add_breakpoint_at_label("\t")?, 1);
+        assert_eq!(interpreter.resume()?, Some(1));
+
+        Ok(())
     }
 
-    fn store_heap(&mut self) -> Result<(), VmError> {
-        if let Some(val) = self.stack.pop() {
-            if let Some(addr) = self.stack.pop() {
-                if addr < 0 || addr as usize >= self.heap.len() {
-                    return VmErrorKind::NumberOutOfBoundsError(
-                        self.instructions[self.instruction_pointer].clone(),
-                        addr,
-                        0,
-                        self.heap.len() as i32 - 1,
-                    )
-                    .throw();
-                }
+    #[test]
+    fn call_label() -> Result<(), VmError> {
+        let config =
+            VmConfig::default_no_heap_suppressed("resources/ws/library.ws", SourceType::Whitespace);
+        let mut interpreter = Vm::new(config)?;
 
-                self.heap[addr as usize] = val;
+        assert_eq!(interpreter.call_label(" ", &[2, 3])?, vec![5]);
+        assert_eq!(interpreter.call_label("\t", &[21])?, vec![42]);
+        assert_eq!(interpreter.call_label(" ", &[1, 2, 3])?, vec![1, 5]);
+        assert!(interpreter.stack.is_empty());
+        assert_eq!(interpreter.next_instruction(), Some(0));
+        assert!(interpreter.call_label(" ", &[1]).is_err());
+        assert!(interpreter.call_label("\t\t", &[]).is_err());
+        interpreter.run()?;
 
-                return Ok(());
-            }
-        }
+        let source = asm::assemble("end\nlabel L0\npush 0\ndiv\nret\nlabel L1\npush 7\nend")
+            .map_err(|err| VmErrorKind::ParseError(Box::new(err)).error())?;
+        let program = Program::from_bytes(&asm::encode(&source), SourceType::Whitespace)?;
+        let config = VmConfig::default_no_heap_suppressed("library", SourceType::Whitespace);
+        let mut interpreter = Vm::from_program(config, program)?;
+
+        assert!(matches!(
+            interpreter.call_label(" ", &[1, 2]).unwrap_err().kind,
+            VmErrorKind::DivisionByZero(_)
+        ));
+        assert!(interpreter.stack.is_empty());
+        assert!(matches!(
+            interpreter.call_label("\t", &[1]).unwrap_err().kind,
+            VmErrorKind::NoReturn(label) if label == "\t"
+        ));
+        assert!(interpreter.stack.is_empty());
+        assert_eq!(interpreter.next_instruction(), Some(0));
 
-        VmErrorKind::StackUnderflow(self.instructions[self.instruction_pointer].clone()).throw()
+        Ok(())
     }
 
-    fn retrieve_heap(&mut self) -> Result<(), VmError> {
-        if let Some(addr) = self.stack.pop() {
-            if addr < 0 || addr as usize >= self.heap.len() {
-                return VmErrorKind::NumberOutOfBoundsError(
-                    self.instructions[self.instruction_pointer].clone(),
-                    addr,
-                    0,
-                    self.heap.len() as i32 - 1,
-                )
-                .throw();
+    #[test]
+    fn gas_budget() -> Result<(), VmError> {
+        let run = |gas_budget, cost_model: Option<CostModel>| {
+            let mut config = VmConfig::default_heap_suppressed(
+                "resources/ws/interpret_heap.ws",
+                SourceType::Whitespace,
+            );
+            config.set_gas_budget(gas_budget);
+            if let Some(cost_model) = cost_model {
+                config.set_cost_model(cost_model);
             }
+            let mut interpreter = Vm::new(config)?;
+            let res = interpreter.run();
+            Ok::<_, VmError>((interpreter, res))
+        };
+        let mut cost_model = CostModel::new();
+        cost_model.set_cost(WsCommandKind::StoreHeap, 10);
+        cost_model.set_cost(WsCommandKind::RetrieveHeap, 10);
+        cost_model.set_cost(WsCommandKind::PushStack, 0);
+
+        let (interpreter, res) = run(7, None)?;
+        assert!(res.is_ok());
+        assert_eq!(interpreter.remaining_gas(), Some(0));
+        let (interpreter, res) = run(21, Some(cost_model.clone()))?;
+        assert!(res.is_ok());
+        assert_eq!(interpreter.remaining_gas(), Some(0));
+        let (mut interpreter, res) = run(20, Some(cost_model))?;
+        assert!(matches!(
+            res.unwrap_err().kind,
+            VmErrorKind::OutOfGas(Instruction::Exit, 0)
+        ));
+        assert_eq!(interpreter.stack, vec![-8, 10]);
+        assert_eq!(interpreter.instruction_count, 6);
+        interpreter.reset();
+        assert_eq!(interpreter.remaining_gas(), Some(20));
 
-            self.stack.push(self.heap[addr as usize]);
+        Ok(())
+    }
 
-            return Ok(());
-        }
+    #[test]
+    fn interpret_initial_state() -> Result<(), VmError> {
+        let config = || {
+            VmConfig::new(
+                "resources/ws/add.ws",
+                SourceType::Whitespace,
+                1,
+                false,
+                false,
+                false,
+                true,
+            )
+        };
+        let mut interpreter = Vm::with_initial_state(config(), vec![2, 3], vec![7])?;
 
-        VmErrorKind::StackUnderflow(self.instructions[self.instruction_pointer].clone()).throw()
-    }
+        interpreter.run()?;
+
+        interpreter.assert_stack(&[5]);
+        interpreter.assert_heap_cells(&[(0, 7)]);
+        assert_eq!(interpreter.heap_size(), 1);
+
+        assert!(matches!(
+            Vm::with_initial_state(config(), vec![], vec![7, 8])
+                .map(|_| ())
+                .unwrap_err()
+                .kind,
+            VmErrorKind::InitialHeapTooLarge(2, 1)
+        ));
+
+        let interpreter = Vm::with_initial_state(config(), vec![1 << 32], vec![(1 << 32) + 7])?;
+
+        interpreter.assert_stack(&[0]);
+        interpreter.assert_heap_cells(&[(0, 7)]);
 
-    fn mark(&mut self) -> Result<(), VmError> {
         Ok(())
     }
 
-    fn call(&mut self) -> Result<(), VmError> {
-        if let Instruction::Call(label) = &self.instructions[self.instruction_pointer] {
-            self.call_stack.push(self.instruction_pointer);
-            self.instruction_pointer = label.index;
+    #[test]
+    #[should_panic(expected = "expected (bottom first): [5]\n  actual (bottom first):   [2, 3]")]
+    fn assert_stack_mismatch() {
+        let config =
+            VmConfig::default_no_heap_suppressed("resources/ws/add.ws", SourceType::Whitespace);
+        let interpreter = Vm::with_initial_state(config, vec![2, 3], vec![]).unwrap();
 
-            return Ok(());
-        }
+        interpreter.assert_stack(&[5]);
+    }
 
-        unreachable!();
+    #[test]
+    #[should_panic(
+        expected = "0: expected 1, actual 0\n  5: expected 0, outside of the heap\n  non-zero cells: {1: 7}"
+    )]
+    fn assert_heap_cells_mismatch() {
+        let config = VmConfig::new(
+            "resources/ws/add.ws",
+            SourceType::Whitespace,
+            2,
+            false,
+            false,
+            false,
+            true,
+        );
+        let interpreter = Vm::with_initial_state(config, vec![], vec![0, 7]).unwrap();
+
+        interpreter.assert_heap_cells(&[(1, 7), (0, 1), (5, 0)]);
     }
 
-    fn jump(&mut self) -> Result<(), VmError> {
-        if let Instruction::Jump(label) = &self.instructions[self.instruction_pointer] {
-            self.instruction_pointer = label.index;
+    #[test]
+    fn interpret_unbalanced_calls() -> Result<(), VmError> {
+        let mut config = VmConfig::default_no_heap_suppressed(
+            "resources/ws/unbalanced_calls.ws",
+            SourceType::Whitespace,
+        );
+        let mut interpreter = Vm::new(config)?;
+        interpreter.run()?;
 
-            return Ok(());
-        }
+        config = VmConfig::default_no_heap_suppressed(
+            "resources/ws/unbalanced_calls.ws",
+            SourceType::Whitespace,
+        );
+        config.set_check_balanced_calls(true);
+        let mut interpreter = Vm::new(config)?;
+        let err = interpreter.run().unwrap_err();
 
-        unreachable!();
-    }
+        assert!(matches!(err.kind, VmErrorKind::UnbalancedCalls(1)));
 
-    fn jump_zero(&mut self) -> Result<(), VmError> {
-        if let Instruction::JumpZero(label) = &self.instructions[self.instruction_pointer] {
-            if let Some(val) = self.stack.pop() {
-                if val != 0 {
-                    return Ok(());
-                }
-                self.instruction_pointer = label.index;
+        Ok(())
+    }
 
-                return Ok(());
-            }
+    #[test]
+    fn interpret_eof_value() -> Result<(), VmError> {
+        let config = VmConfig::default_heap("resources/ws/read_char.ws", SourceType::Whitespace);
+        let mut interpreter = Vm::new(config)?;
+        interpreter.set_input(Box::new(Cursor::new(vec![])));
 
-            return VmErrorKind::StackUnderflow(
-                self.instructions[self.instruction_pointer].clone(),
-            )
-            .throw();
-        }
+        let err = interpreter.run().unwrap_err();
 
-        unreachable!();
-    }
+        assert!(matches!(
+            err.kind,
+            VmErrorKind::EndOfInput(Instruction::ReadCharacter)
+        ));
 
-    fn jump_negative(&mut self) -> Result<(), VmError> {
-        if let Instruction::JumpNegative(label) = &self.instructions[self.instruction_pointer] {
-            if let Some(val) = self.stack.pop() {
-                if val >= 0 {
-                    return Ok(());
-                }
-                self.instruction_pointer = label.index;
+        let mut config =
+            VmConfig::default_heap("resources/ws/read_char.ws", SourceType::Whitespace);
+        config.set_eof_value(-1);
+        let mut interpreter = Vm::new(config)?;
+        interpreter.set_input(Box::new(Cursor::new(vec![])));
+        interpreter.run()?;
 
-                return Ok(());
+        assert_eq!(interpreter.stack, vec![-1]);
+
+        let mut config =
+            VmConfig::default_heap("resources/ws/read_twice.ws", SourceType::Whitespace);
+        config.set_eof_value(-1);
+        let mut interpreter = Vm::new(config)?;
+        interpreter.set_input(Box::new(Cursor::new(b"42\n".to_vec())));
+        interpreter.capture_output();
+        interpreter.run()?;
+
+        assert_eq!(interpreter.take_output(), b"42-1");
+
+        let strict = |eof_value_first| {
+            let mut config =
+                VmConfig::default_heap("resources/ws/read_char.ws", SourceType::Whitespace);
+            if eof_value_first {
+                config.set_eof_value(-1);
+                config.set_strict(true);
+            } else {
+                config.set_strict(true);
+                config.set_eof_value(-1);
             }
-            return VmErrorKind::StackUnderflow(
-                self.instructions[self.instruction_pointer].clone(),
-            )
-            .throw();
-        }
 
-        unreachable!();
+            config
+        };
+
+        assert!(matches!(
+            run_config(strict(true), b"").err().unwrap().kind,
+            VmErrorKind::EndOfInput(Instruction::ReadCharacter)
+        ));
+        assert_eq!(run_config(strict(false), b"")?.0.stack, vec![-1]);
+
+        Ok(())
     }
 
-    fn r#return(&mut self) -> Result<(), VmError> {
-        if let Some(frame) = self.call_stack.pop() {
-            self.instruction_pointer = frame;
+    #[test]
+    fn read_into_heap() -> Result<(), VmError> {
+        let config =
+            VmConfig::default_heap_suppressed("resources/ws/add.ws", SourceType::Whitespace);
+        let mut interpreter = Vm::new(config)?;
+        interpreter.set_input(Box::new(Cursor::new(b"abcd".to_vec())));
 
-            return Ok(());
-        }
+        interpreter.read_into_heap(0, 3)?;
 
-        VmErrorKind::StackUnderflow(self.instructions[self.instruction_pointer].clone()).throw()
+        assert_eq!(interpreter.heap[..4], [97, 98, 99, 0]);
+        assert!(interpreter
+            .read_into_heap(interpreter.heap.len() - 1, 2)
+            .is_err());
+
+        Ok(())
     }
 
-    fn exit(&mut self) -> Result<(), VmError> {
-        self.done = true;
+    #[test]
+    fn spill_stack_to_heap() -> Result<(), VmError> {
+        let config =
+            VmConfig::default_heap_suppressed("resources/ws/copy_one.ws", SourceType::Whitespace);
+        let mut interpreter = Vm::new(config)?;
+        interpreter.capture_output();
+        interpreter.run()?;
+        interpreter.spill_stack_to_heap(10)?;
+
+        assert_eq!(interpreter.heap[9..14], [0, 1, 2, 3, 0]);
+        assert_eq!(interpreter.stack, vec![1, 2, 3]);
+        assert!(interpreter
+            .spill_stack_to_heap(interpreter.heap.len() - 2)
+            .is_err());
 
         Ok(())
     }
 
-    fn out_char(&mut self) -> Result<(), VmError> {
-        if let Some(character) = self.stack.pop() {
-            if character < 0 {
-                return VmErrorKind::NumberOutOfBoundsError(
-                    self.instructions[self.instruction_pointer].clone(),
-                    character,
-                    0,
-                    i32::MAX,
-                )
-                .throw();
-            }
+    #[test]
+    fn format_heap_dump() -> Result<(), VmError> {
+        let config = || {
+            let mut config = VmConfig::default_heap_suppressed(
+                "resources/ws/heap_gap.ws",
+                SourceType::Whitespace,
+            );
+            config.set_heap_dump_filter(HeapDumpFilter::All);
+            config.set_heap_dump_range(0, 42);
+            config.set_heap_dump_collapse(8);
+            config
+        };
+        let mut interpreter = Vm::new(config())?;
+        interpreter.run()?;
 
-            if self.config.suppress_output {
-                return Ok(());
-            }
+        assert_eq!(
+            interpreter.format_heap_dump(),
+            "0: 72 'H'\n1: 105 'i'\n* 2..40: 38 zero cells\n40: 7\n41: 0\n"
+        );
 
-            if let Some(character) = char::from_u32(character as u32) {
-                match write!(stdout(), "{}", character) {
-                    Ok(val) => val,
-                    Err(_) => {
-                        return VmErrorKind::IOError(
-                            self.instructions[self.instruction_pointer].clone(),
-                        )
-                        .throw()
-                    }
-                };
-                match stdout().flush() {
-                    Ok(val) => val,
-                    Err(_) => {
-                        return VmErrorKind::IOError(
-                            self.instructions[self.instruction_pointer].clone(),
-                        )
-                        .throw()
-                    }
-                };
+        let mut config = config();
+        config.set_heap_dump_collapse(0);
+        let mut interpreter = Vm::new(config)?;
+        interpreter.run()?;
 
-                return Ok(());
-            }
-        }
+        assert_eq!(interpreter.format_heap_dump().lines().count(), 42);
 
-        VmErrorKind::StackUnderflow(self.instructions[self.instruction_pointer].clone()).throw()
+        Ok(())
     }
 
-    fn out_int(&mut self) -> Result<(), VmError> {
-        if let Some(num) = self.stack.pop() {
-            if self.config.suppress_output {
-                return Ok(());
-            }
-            match write!(stdout(), "{}", num) {
-                Ok(val) => val,
-                Err(_) => {
-                    return VmErrorKind::IOError(
-                        self.instructions[self.instruction_pointer].clone(),
-                    )
-                    .throw()
-                }
-            };
-            match stdout().flush() {
-                Ok(val) => val,
-                Err(_) => {
-                    return VmErrorKind::IOError(
-                        self.instructions[self.instruction_pointer].clone(),
-                    )
-                    .throw()
-                }
-            };
+    #[test]
+    fn heap_dump_ordered_by_address() -> Result<(), VmError> {
+        let config = |filter| {
+            let mut config = VmConfig::default_heap_suppressed(
+                "resources/ws/store_descending.ws",
+                SourceType::Whitespace,
+            );
+            config.set_heap_dump_filter(filter);
+
+            config
+        };
+        let expected = vec![(1, 3), (3, 2), (9, 1)];
 
-            return Ok(());
+        for filter in [HeapDumpFilter::NonZero, HeapDumpFilter::Written] {
+            let (interpreter, _) = run_config(config(filter), b"")?;
+            let dump = interpreter.generate_debug_heap_dump();
+
+            assert_eq!(dump.into_iter().collect::<Vec<_>>(), expected);
         }
 
-        VmErrorKind::StackUnderflow(self.instructions[self.instruction_pointer].clone()).throw()
+        Ok(())
     }
 
-    fn read_char(&mut self) -> Result<(), VmError> {
-        #[cfg(target_arch = "wasm32")]
-        unimplemented!();
-        #[cfg(not(target_arch = "wasm32"))]
-        if let Some(addr) = self.stack.pop() {
-            if addr < 0 || addr as usize >= self.heap.len() {
-                return VmErrorKind::NumberOutOfBoundsError(
-                    self.instructions[self.instruction_pointer].clone(),
-                    addr,
-                    0,
-                    i32::MAX,
-                )
-                .throw();
-            }
-
-            match stdout().flush() {
-                Ok(val) => val,
-                Err(_) => {
-                    return VmErrorKind::IOError(
-                        self.instructions[self.instruction_pointer].clone(),
-                    )
-                    .throw()
-                }
-            };
-            return match Getch::new().getch() {
-                Ok(val) => {
-                    self.heap[addr as usize] = val as i32;
-                    match write!(stdout(), "{}", char::from_u32(val as u32).unwrap()) {
-                        Ok(val) => val,
-                        Err(_) => {
-                            return VmErrorKind::IOError(
-                                self.instructions[self.instruction_pointer].clone(),
-                            )
-                            .throw()
-                        }
-                    };
-                    match stdout().flush() {
-                        Ok(val) => val,
-                        Err(_) => {
-                            return VmErrorKind::IOError(
-                                self.instructions[self.instruction_pointer].clone(),
-                            )
-                            .throw()
-                        }
-                    };
-
-                    Ok(())
-                }
-                Err(_) => {
-                    return VmErrorKind::IOError(
-                        self.instructions[self.instruction_pointer].clone(),
-                    )
-                    .throw()
-                }
-            };
+    #[test]
+    fn rollback_heap() -> Result<(), VmError> {
+        let mut config =
+            VmConfig::default_heap_suppressed("resources/ws/store_zero.ws", SourceType::Whitespace);
+        config.set_heap_dump_filter(HeapDumpFilter::Written);
+        let mut interpreter = Vm::with_initial_state(config, vec![], vec![7])?;
+        let outer = interpreter.checkpoint_heap();
+        for _ in 0..3 {
+            interpreter.step()?;
         }
+        let inner = interpreter.checkpoint_heap();
+        interpreter.run()?;
+        assert_eq!(interpreter.heap[..2], [0, 5]);
 
-        VmErrorKind::StackUnderflow(self.instructions[self.instruction_pointer].clone()).throw()
+        interpreter.rollback_heap(inner);
+        assert_eq!(interpreter.heap[..2], [0, 0]);
+        assert_eq!(
+            interpreter.generate_debug_heap_dump(),
+            BTreeMap::from([(0, 0)])
+        );
+        interpreter.rollback_heap(outer);
+        assert_eq!(interpreter.heap[..2], [7, 0]);
+        assert!(interpreter.heap_journal.is_none());
+
+        Ok(())
     }
 
-    fn read_int(&mut self) -> Result<(), VmError> {
-        if let Some(addr) = self.stack.pop() {
-            if addr < 0 || addr as usize >= self.heap.len() {
-                return VmErrorKind::NumberOutOfBoundsError(
-                    self.instructions[self.instruction_pointer].clone(),
-                    addr,
-                    0,
-                    self.heap.len() as i32 - 1,
-                )
-                .throw();
-            }
-            match stdout().flush() {
-                Ok(val) => val,
-                Err(_) => {
-                    return VmErrorKind::IOError(
-                        self.instructions[self.instruction_pointer].clone(),
-                    )
-                    .throw()
-                }
-            };
-            let mut input_text = String::new();
-            match stdin().read_line(&mut input_text) {
-                Ok(val) => val,
-                Err(_) => {
-                    return VmErrorKind::IOError(
-                        self.instructions[self.instruction_pointer].clone(),
-                    )
-                    .throw()
-                }
-            };
+    #[test]
+    fn interpret_checked_arithmetic() -> Result<(), VmError> {
+        let config = VmConfig::default_no_heap_suppressed(
+            "resources/ws/overflow.ws",
+            SourceType::Whitespace,
+        );
+        let mut interpreter = Vm::new(config)?;
+        interpreter.run()?;
 
-            let trimmed = input_text.trim();
-            let num = match trimmed.parse::<i32>() {
-                Ok(val) => val,
-                Err(_) => {
-                    return VmErrorKind::IOError(
-                        self.instructions[self.instruction_pointer].clone(),
-                    )
-                    .throw()
-                }
-            };
-            self.heap[addr as usize] = num;
+        assert_eq!(interpreter.stack, vec![i32::MIN as i64]);
 
-            return Ok(());
-        }
+        let mut config = VmConfig::default_no_heap_suppressed(
+            "resources/ws/overflow.ws",
+            SourceType::Whitespace,
+        );
+        config.set_checked_arithmetic(true);
+        let mut interpreter = Vm::new(config)?;
+        let err = interpreter.run().unwrap_err();
 
-        VmErrorKind::IOError(self.instructions[self.instruction_pointer].clone()).throw()
+        assert!(matches!(err.kind, VmErrorKind::ArithmeticOverflow(_)));
+
+        Ok(())
     }
 
-    pub fn exec(&mut self) -> Result<(), VmError> {
-        self.instruction_count += 1;
-        if self.config.debug {
-            dbg!(&self.stack);
-            dbg!(&self.call_stack);
-            dbg!(&self.instruction_pointer);
-            dbg!(&self.instructions[self.instruction_pointer]);
-        }
-        if self.config.debug_heap {
-            dbg!(self.generate_debug_heap_dump());
-        }
-        let res = match self.instructions[self.instruction_pointer] {
-            Instruction::PushStack(_) => self.push_stack(),
-            Instruction::DuplicateStack => self.duplicate_stack(),
-            Instruction::CopyNthStack(_) => self.copy_nth_stack(),
-            Instruction::SwapStack => self.swap_stack(),
-            Instruction::DiscardStack => self.discard_stack(),
-            Instruction::SlideNStack(_) => self.slide_n_stack(),
-            Instruction::Add => self.add(),
-            Instruction::Subtract => self.subtract(),
-            Instruction::Multiply => self.multiply(),
-            Instruction::IntegerDivision => self.integer_division(),
-            Instruction::Modulo => self.modulo(),
-            Instruction::StoreHeap => self.store_heap(),
-            Instruction::RetrieveHeap => self.retrieve_heap(),
-            Instruction::Mark(_) => self.mark(),
-            Instruction::Call(_) => self.call(),
-            Instruction::Jump(_) => self.jump(),
-            Instruction::JumpZero(_) => self.jump_zero(),
-            Instruction::JumpNegative(_) => self.jump_negative(),
-            Instruction::Return => self.r#return(),
-            Instruction::Exit => self.exit(),
-            Instruction::OutCharacter => self.out_char(),
-            Instruction::OutInteger => self.out_int(),
-            Instruction::ReadCharacter => self.read_char(),
-            Instruction::ReadInteger => self.read_int(),
-        };
+    #[test]
+    fn interpret_division_by_zero() -> Result<(), VmError> {
+        let config = VmConfig::default_no_heap_suppressed(
+            "resources/ws/division_by_zero.ws",
+            SourceType::Whitespace,
+        );
+        let mut interpreter = Vm::new(config)?;
+        let err = interpreter.run().unwrap_err();
 
-        self.instruction_pointer += 1;
+        assert!(matches!(err.kind, VmErrorKind::DivisionByZero(_)));
 
-        res
+        Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::{SourceType, Vm, VmConfig, VmError};
+    #[test]
+    fn strict_rejects_undefined_labels() {
+        let mut config = VmConfig::default_no_heap_suppressed(
+            "resources/ws/lint_undefined_labels.ws",
+            SourceType::Whitespace,
+        );
+        config.set_strict(true);
+        let err = Vm::new(config).err().unwrap();
+
+        assert!(matches!(err.kind, VmErrorKind::UndefinedLabel(_)));
+    }
 
     #[test]
-    fn interpret_stack() -> Result<(), VmError> {
+    fn interpret_exit_in_the_middle() -> Result<(), VmError> {
         let config = VmConfig::default_no_heap_suppressed(
-            "resources/ws/interpret_stack.ws",
+            "resources/ws/exit_middle.ws",
             SourceType::Whitespace,
         );
         let mut interpreter = Vm::new(config)?;
-
         interpreter.run()?;
 
-        assert_eq!(interpreter.stack, vec![-1]);
-        assert!(interpreter.heap.is_empty());
+        assert_eq!(interpreter.stack, vec![1]);
+        assert_eq!(interpreter.last_executed(), Some(3));
+        assert_eq!(interpreter.instructions[3], Instruction::Exit);
+        assert_eq!(interpreter.next_instruction(), None);
+        assert!(!interpreter.step()?);
 
         Ok(())
     }
 
     #[test]
-    fn interpret_arithmetic() -> Result<(), VmError> {
+    fn interpret_exit_before_trailing_label() -> Result<(), VmError> {
         let config = VmConfig::default_no_heap_suppressed(
-            "resources/ws/interpret_arithmetic.ws",
+            "resources/ws/exit_trailing_label.ws",
             SourceType::Whitespace,
         );
         let mut interpreter = Vm::new(config)?;
+        interpreter.run()?;
+
+        assert!(matches!(
+            interpreter.instructions.last(),
+            Some(Instruction::Mark(_))
+        ));
+        assert_eq!(interpreter.stack, vec![]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn seek_into_loop() -> Result<(), VmError> {
+        let config =
+            VmConfig::default_no_heap("resources/ws/interpret_flow.ws", SourceType::Whitespace);
+        let mut interpreter = Vm::new(config)?;
+        interpreter.capture_output();
+        // stop after printing the first number, before its line feed
+        while interpreter.instruction_pointer() != 4 {
+            interpreter.step()?;
+        }
+        interpreter.seek(2)?;
+
+        assert_eq!(interpreter.instruction_pointer(), 2);
+        assert_eq!(
+            interpreter.current_instruction(),
+            Some(&Instruction::DuplicateStack)
+        );
 
         interpreter.run()?;
 
-        assert_eq!(interpreter.stack, vec![4]);
-        assert!(interpreter.heap.is_empty());
+        assert_eq!(
+            interpreter.take_output(),
+            b"11\n2\n3\n4\n5\n6\n7\n8\n9\n10\n"
+        );
+        assert!(interpreter.is_done());
+
+        interpreter.seek(0)?;
+
+        assert!(!interpreter.is_done());
+        assert!(matches!(
+            interpreter
+                .seek(interpreter.instructions().len())
+                .unwrap_err()
+                .kind,
+            VmErrorKind::SeekOutOfBounds(..)
+        ));
 
         Ok(())
     }
 
     #[test]
-    fn interpret_heap() -> Result<(), VmError> {
-        let config = VmConfig::default_heap_suppressed(
-            "resources/ws/interpret_heap.ws",
+    fn run_n() -> Result<(), VmError> {
+        let config = VmConfig::default_no_heap_suppressed(
+            "resources/ws/interpret_flow.ws",
             SourceType::Whitespace,
         );
         let mut interpreter = Vm::new(config)?;
+        interpreter.run_n(5)?;
 
-        interpreter.run()?;
+        assert_eq!(interpreter.stack(), [1, 10]);
+        assert_eq!(interpreter.instruction_pointer(), 5);
+        assert_eq!(interpreter.instruction_count, 5);
 
-        assert_eq!(interpreter.stack, vec![-8, 10]);
+        interpreter.run_n(1000)?;
+
+        assert!(interpreter.is_done());
+        assert!(interpreter.instruction_count < 1005);
 
         Ok(())
     }
 
     #[test]
-    fn interpret_flow() -> Result<(), VmError> {
+    fn step_until_done() -> Result<(), VmError> {
         let config = VmConfig::default_no_heap_suppressed(
-            "resources/ws/interpret_flow.ws",
+            "resources/ws/exit_middle.ws",
             SourceType::Whitespace,
         );
         let mut interpreter = Vm::new(config)?;
+        while interpreter.current_instruction() != Some(&Instruction::Exit) {
+            assert!(!interpreter.is_done());
+            interpreter.step()?;
+        }
 
-        interpreter.run()?;
-        assert_eq!(interpreter.stack, Vec::<i32>::new());
+        assert!(!interpreter.is_done());
+        assert!(interpreter.step()?);
+        assert!(interpreter.is_done());
+
+        interpreter.reset();
+
+        assert!(!interpreter.is_done());
 
         Ok(())
     }
 
     #[test]
-    fn interpret_io() -> Result<(), VmError> {
+    fn interpret_heap_disabled() -> Result<(), VmError> {
         let config = VmConfig::default_no_heap_suppressed(
-            "resources/ws/interpret_io.ws",
+            "resources/ws/interpret_heap.ws",
             SourceType::Whitespace,
         );
         let mut interpreter = Vm::new(config)?;
+        let err = interpreter.run().unwrap_err();
 
-        interpreter.run()?;
+        assert!(matches!(
+            err.kind,
+            VmErrorKind::HeapDisabled(Instruction::StoreHeap)
+        ));
 
         Ok(())
     }
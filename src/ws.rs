@@ -5,15 +5,28 @@ use crate::parser::ParseErrorKind;
 use crate::parser::Parser;
 use crate::{ir::Number, Instruction};
 #[cfg(not(target_arch = "wasm32"))]
+use flate2::read::GzDecoder;
+#[cfg(not(target_arch = "wasm32"))]
 use memmap::Mmap;
 #[cfg(not(target_arch = "wasm32"))]
 use std::fs::File;
-use std::rc::Rc;
+#[cfg(not(target_arch = "wasm32"))]
+use std::io::stdin;
+use std::io::Read;
+#[cfg(not(target_arch = "wasm32"))]
+use std::ops::Deref;
+use std::ops::Range;
+use std::sync::Arc;
 
 pub const SPACE: u8 = b' ';
 pub const TAB: u8 = b'\t';
 pub const LINE_FEED: u8 = b'\n';
 
+/// Returns whether the byte is a token of the language, every other byte is part of a comment
+pub fn is_token(byte: u8) -> bool {
+    matches!(byte, SPACE | TAB | LINE_FEED)
+}
+
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub(crate) enum WsImpKind {
     Stack,
@@ -23,8 +36,9 @@ pub(crate) enum WsImpKind {
     IO,
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
-pub(crate) enum WsCommandKind {
+/// The command of a whitespace instruction, without its parameter
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum WsCommandKind {
     PushStack,
     DuplicateStack,
     CopyNthStack,
@@ -49,14 +63,100 @@ pub(crate) enum WsCommandKind {
     OutInteger,
     ReadCharacter,
     ReadInteger,
+    /// Pops the index of the next instruction from the stack, a dialect extension that is only
+    /// parsed and run with `VmConfig::set_extensions`
+    JumpDynamic,
+    /// Calls the host function registered under its parameter, a dialect extension that is only
+    /// parsed and run with `VmConfig::set_extensions`
+    Syscall,
 }
 
+/// Every command, in the order of the instruction set
+const ALL_COMMAND_KINDS: [WsCommandKind; 26] = [
+    WsCommandKind::PushStack,
+    WsCommandKind::DuplicateStack,
+    WsCommandKind::CopyNthStack,
+    WsCommandKind::SwapStack,
+    WsCommandKind::DiscardStack,
+    WsCommandKind::SlideNStack,
+    WsCommandKind::Add,
+    WsCommandKind::Subtract,
+    WsCommandKind::Multiply,
+    WsCommandKind::IntegerDivision,
+    WsCommandKind::Modulo,
+    WsCommandKind::StoreHeap,
+    WsCommandKind::RetrieveHeap,
+    WsCommandKind::Mark,
+    WsCommandKind::Call,
+    WsCommandKind::Jump,
+    WsCommandKind::JumpZero,
+    WsCommandKind::JumpNegative,
+    WsCommandKind::Return,
+    WsCommandKind::Exit,
+    WsCommandKind::OutCharacter,
+    WsCommandKind::OutInteger,
+    WsCommandKind::ReadCharacter,
+    WsCommandKind::ReadInteger,
+    WsCommandKind::JumpDynamic,
+    WsCommandKind::Syscall,
+];
+
 impl WsCommandKind {
-    fn param_kind(&self) -> Option<WsParamKind> {
+    /// Returns every command, in the order of the instruction set: stack manipulation,
+    /// arithmetic, heap access, flow control and I/O, followed by the dialect extensions
+    pub fn all() -> &'static [WsCommandKind] {
+        &ALL_COMMAND_KINDS
+    }
+
+    /// Returns the assembly mnemonic of the command, e.g. `push`. Mnemonics are stable, tools
+    /// can rely on them.
+    pub fn mnemonic(&self) -> &'static str {
         match self {
-            WsCommandKind::PushStack | WsCommandKind::CopyNthStack | WsCommandKind::SlideNStack => {
-                Some(WsParamKind::Number(0))
-            }
+            WsCommandKind::PushStack => "push",
+            WsCommandKind::DuplicateStack => "dup",
+            WsCommandKind::CopyNthStack => "copy",
+            WsCommandKind::SwapStack => "swap",
+            WsCommandKind::DiscardStack => "drop",
+            WsCommandKind::SlideNStack => "slide",
+            WsCommandKind::Add => "add",
+            WsCommandKind::Subtract => "sub",
+            WsCommandKind::Multiply => "mul",
+            WsCommandKind::IntegerDivision => "div",
+            WsCommandKind::Modulo => "mod",
+            WsCommandKind::StoreHeap => "store",
+            WsCommandKind::RetrieveHeap => "retrieve",
+            WsCommandKind::Mark => "label",
+            WsCommandKind::Call => "call",
+            WsCommandKind::Jump => "jmp",
+            WsCommandKind::JumpZero => "jz",
+            WsCommandKind::JumpNegative => "jn",
+            WsCommandKind::Return => "ret",
+            WsCommandKind::Exit => "end",
+            WsCommandKind::OutCharacter => "outc",
+            WsCommandKind::OutInteger => "outn",
+            WsCommandKind::ReadCharacter => "readc",
+            WsCommandKind::ReadInteger => "readn",
+            WsCommandKind::JumpDynamic => "jmpdyn",
+            WsCommandKind::Syscall => "syscall",
+        }
+    }
+
+    /// Returns the command with the given assembly mnemonic, `None` if there is none
+    ///
+    /// - `mnemonic` the mnemonic as returned by `mnemonic`
+    pub fn from_mnemonic(mnemonic: &str) -> Option<WsCommandKind> {
+        WsCommandKind::all()
+            .iter()
+            .find(|kind| kind.mnemonic() == mnemonic)
+            .copied()
+    }
+
+    pub(crate) fn param_kind(&self) -> Option<WsParamKind> {
+        match self {
+            WsCommandKind::PushStack
+            | WsCommandKind::CopyNthStack
+            | WsCommandKind::SlideNStack
+            | WsCommandKind::Syscall => Some(WsParamKind::Number(0)),
             WsCommandKind::Mark
             | WsCommandKind::Call
             | WsCommandKind::Jump
@@ -65,12 +165,51 @@ impl WsCommandKind {
             _ => None,
         }
     }
+
+    /// Returns how many stack items the command consumes and how many it produces, e.g. `(2, 1)`
+    /// for `Add`. Flow control and heap access only count their effect on the stack.
+    ///
+    /// The effects of `CopyNthStack` and `SlideNStack` depend on their parameter `n`: `CopyNthStack`
+    /// needs `n + 1` items on the stack but leaves them untouched, so only the copy is counted.
+    /// `SlideNStack` consumes `n + 1` items and produces the former top again, so only the minimum
+    /// for `n = 0` is returned. The effect of `Syscall` is up to the host function, so none is
+    /// counted.
+    pub fn stack_effect(&self) -> (usize, usize) {
+        match self {
+            WsCommandKind::PushStack => (0, 1),
+            WsCommandKind::DuplicateStack => (1, 2),
+            WsCommandKind::CopyNthStack => (0, 1),
+            WsCommandKind::SwapStack => (2, 2),
+            WsCommandKind::DiscardStack => (1, 0),
+            WsCommandKind::SlideNStack => (1, 1),
+            WsCommandKind::Add
+            | WsCommandKind::Subtract
+            | WsCommandKind::Multiply
+            | WsCommandKind::IntegerDivision
+            | WsCommandKind::Modulo => (2, 1),
+            WsCommandKind::StoreHeap => (2, 0),
+            WsCommandKind::RetrieveHeap => (1, 1),
+            WsCommandKind::Mark
+            | WsCommandKind::Call
+            | WsCommandKind::Jump
+            | WsCommandKind::Return
+            | WsCommandKind::Exit
+            | WsCommandKind::Syscall => (0, 0),
+            WsCommandKind::JumpZero | WsCommandKind::JumpNegative | WsCommandKind::JumpDynamic => {
+                (1, 0)
+            }
+            WsCommandKind::OutCharacter
+            | WsCommandKind::OutInteger
+            | WsCommandKind::ReadCharacter
+            | WsCommandKind::ReadInteger => (1, 0),
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub(crate) enum WsParamKind {
-    Number(i32),
-    Label(Rc<str>, usize),
+    Number(i64),
+    Label(Arc<str>, usize),
 }
 
 /// Tokenized representation for a whitespace instruction.
@@ -81,6 +220,7 @@ pub struct WsInstruction {
     pub(crate) cmd: WsCommandKind,
     pub(crate) param: Option<WsParamKind>,
     pub(crate) token_index: usize,
+    pub(crate) token_end: usize,
     pub(crate) instruction_index: usize,
 }
 
@@ -90,6 +230,10 @@ impl Instr for WsInstruction {
         self
     }
 
+    fn span(&self) -> Range<usize> {
+        self.token_index..self.token_end
+    }
+
     fn translate(&self) -> Result<Instruction, ParseError> {
         match self.cmd {
             WsCommandKind::PushStack => {
@@ -164,6 +308,36 @@ impl Instr for WsInstruction {
             WsCommandKind::OutInteger => Ok(Instruction::OutInteger),
             WsCommandKind::ReadCharacter => Ok(Instruction::ReadCharacter),
             WsCommandKind::ReadInteger => Ok(Instruction::ReadInteger),
+            WsCommandKind::JumpDynamic => Ok(Instruction::JumpDynamic),
+            WsCommandKind::Syscall => {
+                if let Some(WsParamKind::Number(num)) = self.param {
+                    return Ok(Instruction::Syscall(Number { value: num }));
+                }
+
+                unreachable!();
+            }
+        }
+    }
+}
+
+/// The bytes of a source file read from disk
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug)]
+enum WsSource {
+    /// A plain source file, mapped into memory
+    Mapped(Mmap),
+    /// A compressed source file, decompressed into memory
+    Owned(Vec<u8>),
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Deref for WsSource {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            WsSource::Mapped(source) => source,
+            WsSource::Owned(source) => source,
         }
     }
 }
@@ -172,15 +346,20 @@ impl Instr for WsInstruction {
 #[derive(Debug)]
 pub struct WsParser {
     #[cfg(not(target_arch = "wasm32"))]
-    source: Mmap,
+    source: WsSource,
     #[cfg(target_arch = "wasm32")]
     source: Vec<u8>,
     token_index: usize,
     instruction_index: usize,
+    extensions: bool,
 }
 
 impl Parser for WsParser {
     fn instruction(&mut self) -> Option<Result<Box<dyn Instr>, ParseError>> {
+        // comments before the instruction are not part of its span
+        while self.token_index < self.source.len() && !is_token(self.source[self.token_index]) {
+            self.token_index += 1;
+        }
         let start_index = self.token_index;
         let imp = self.imp()?;
         if let Ok(imp) = imp {
@@ -202,6 +381,7 @@ impl Parser for WsParser {
                     cmd,
                     param,
                     token_index: start_index,
+                    token_end: self.token_index,
                     instruction_index: index,
                 };
                 self.instruction_index += 1;
@@ -216,11 +396,32 @@ impl Parser for WsParser {
 
         None
     }
+
+    fn source(&self) -> &[u8] {
+        &self.source
+    }
+
+    fn set_extensions(&mut self, extensions: bool) {
+        self.extensions = extensions;
+    }
 }
 
 impl WsParser {
+    /// Creates a parser for the source file on disk. Files ending in `.gz` are decompressed
+    /// before parsing. `-` reads the source from stdin and, with the `net` feature, `http://` and
+    /// `https://` URLs are downloaded.
     #[cfg(not(target_arch = "wasm32"))]
     pub fn new(file_name: &str) -> Result<Box<dyn Parser>, ParseError> {
+        if file_name == "-" {
+            return WsParser::from_reader(stdin());
+        }
+        #[cfg(feature = "net")]
+        if file_name.starts_with("http://") || file_name.starts_with("https://") {
+            return WsParser::from_url(file_name);
+        }
+        if file_name.ends_with(".gz") {
+            return WsParser::new_gzip(file_name);
+        }
         let file = match File::open(&file_name) {
             Ok(content) => content,
             Err(err) => return ParseErrorKind::FileOpenError(Box::new(err)).throw(),
@@ -234,9 +435,31 @@ impl WsParser {
         let index = 0;
 
         Ok(Box::new(WsParser {
-            source,
+            source: WsSource::Mapped(source),
+            token_index: index,
+            instruction_index: index,
+            extensions: false,
+        }))
+    }
+
+    /// Creates a parser for the gzip-compressed source file on disk, regardless of its extension
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn new_gzip(file_name: &str) -> Result<Box<dyn Parser>, ParseError> {
+        let file = match File::open(file_name) {
+            Ok(content) => content,
+            Err(err) => return ParseErrorKind::FileOpenError(Box::new(err)).throw(),
+        };
+        let mut source = vec![];
+        if let Err(err) = GzDecoder::new(file).read_to_end(&mut source) {
+            return ParseErrorKind::DecompressionError(Box::new(err)).throw();
+        }
+        let index = 0;
+
+        Ok(Box::new(WsParser {
+            source: WsSource::Owned(source),
             token_index: index,
             instruction_index: index,
+            extensions: false,
         }))
     }
 
@@ -248,9 +471,49 @@ impl WsParser {
             source: source.to_string().as_bytes().to_vec(),
             token_index: index,
             instruction_index: index,
+            extensions: false,
         }))
     }
 
+    /// Creates a parser for the given source bytes
+    pub fn from_bytes(source: &[u8]) -> Result<Box<dyn Parser>, ParseError> {
+        Ok(WsParser::from_vec(source.to_vec()))
+    }
+
+    fn from_vec(source: Vec<u8>) -> Box<dyn Parser> {
+        let index = 0;
+
+        Box::new(WsParser {
+            #[cfg(not(target_arch = "wasm32"))]
+            source: WsSource::Owned(source),
+            #[cfg(target_arch = "wasm32")]
+            source,
+            token_index: index,
+            instruction_index: index,
+            extensions: false,
+        })
+    }
+
+    /// Creates a parser for the source read from the given reader. Labels need to be resolved
+    /// over the whole program, so the source is read entirely before parsing.
+    pub fn from_reader(mut reader: impl Read) -> Result<Box<dyn Parser>, ParseError> {
+        let mut source = vec![];
+        if let Err(err) = reader.read_to_end(&mut source) {
+            return ParseErrorKind::ReadError(Box::new(err)).throw();
+        }
+
+        Ok(WsParser::from_vec(source))
+    }
+
+    /// Creates a parser for the source downloaded from the URL
+    #[cfg(all(feature = "net", not(target_arch = "wasm32")))]
+    pub fn from_url(url: &str) -> Result<Box<dyn Parser>, ParseError> {
+        match ureq::get(url).call() {
+            Ok(response) => WsParser::from_reader(response.into_reader()),
+            Err(err) => ParseErrorKind::ReadError(Box::new(err)).throw(),
+        }
+    }
+
     fn next(&mut self) -> Option<u8> {
         let tokens = vec![SPACE, TAB, LINE_FEED];
         while self.token_index < self.source.len() {
@@ -495,15 +758,22 @@ impl WsParser {
                 if let Some(val) = self.next() {
                     return match val {
                         LINE_FEED => Some(Ok(WsCommandKind::Exit)),
+                        SPACE if self.extensions => Some(Ok(WsCommandKind::JumpDynamic)),
+                        TAB if self.extensions => Some(Ok(WsCommandKind::Syscall)),
                         _ => Some(
-                            ParseErrorKind::UnexpectedToken(self.token_index, val, vec![LINE_FEED])
-                                .throw(),
+                            ParseErrorKind::UnexpectedToken(
+                                self.token_index,
+                                val,
+                                self.exit_tokens(),
+                            )
+                            .throw(),
                         ),
                     };
                 }
 
                 Some(
-                    ParseErrorKind::UnexpectedToken(self.token_index, val, vec![LINE_FEED]).throw(),
+                    ParseErrorKind::UnexpectedToken(self.token_index, val, self.exit_tokens())
+                        .throw(),
                 )
             }
             _ => Some(
@@ -513,6 +783,15 @@ impl WsParser {
         }
     }
 
+    /// Returns the tokens that can follow two line feeds, the extensions share the prefix of `Exit`
+    fn exit_tokens(&self) -> Vec<u8> {
+        if self.extensions {
+            return vec![SPACE, TAB, LINE_FEED];
+        }
+
+        vec![LINE_FEED]
+    }
+
     fn io(&mut self) -> Option<Result<WsCommandKind, ParseError>> {
         let val = self.next()?;
         match val {
@@ -574,7 +853,11 @@ impl WsParser {
         }
     }
 
-    fn number(&mut self, sign: i32) -> Option<Result<WsParamKind, ParseError>> {
+    /// Parses the bits of a number after its sign, up to the terminating line feed. A number
+    /// without any bits, i.e. a sign directly followed by the line feed, is `0` for either sign,
+    /// as in the reference interpreter. Some interpreters reject it, so `asm::encode` always
+    /// writes at least one bit.
+    fn number(&mut self, sign: i64) -> Option<Result<WsParamKind, ParseError>> {
         let mut places = Vec::new();
         let mut failure = None;
         while let Some(val) = self.next() {
@@ -668,7 +951,7 @@ impl WsParser {
 
 #[cfg(test)]
 mod tests {
-    use crate::parser::Parser;
+    use crate::parser::{Parser, Token};
 
     use super::{ParseError, WsCommandKind, WsImpKind, WsInstruction, WsParamKind, WsParser};
 
@@ -705,6 +988,7 @@ mod tests {
                 cmd: WsCommandKind::PushStack,
                 param: Some(WsParamKind::Number(64)),
                 token_index: 0,
+                token_end: 11,
                 instruction_index: 0,
             },
             WsInstruction {
@@ -712,6 +996,7 @@ mod tests {
                 cmd: WsCommandKind::DuplicateStack,
                 param: None,
                 token_index: 11,
+                token_end: 14,
                 instruction_index: 1,
             },
             WsInstruction {
@@ -719,6 +1004,7 @@ mod tests {
                 cmd: WsCommandKind::CopyNthStack,
                 param: Some(WsParamKind::Number(64)),
                 token_index: 14,
+                token_end: 26,
                 instruction_index: 2,
             },
             WsInstruction {
@@ -726,6 +1012,7 @@ mod tests {
                 cmd: WsCommandKind::SwapStack,
                 param: None,
                 token_index: 26,
+                token_end: 29,
                 instruction_index: 3,
             },
             WsInstruction {
@@ -733,6 +1020,7 @@ mod tests {
                 cmd: WsCommandKind::DiscardStack,
                 param: None,
                 token_index: 29,
+                token_end: 32,
                 instruction_index: 4,
             },
             WsInstruction {
@@ -740,6 +1028,7 @@ mod tests {
                 cmd: WsCommandKind::SlideNStack,
                 param: Some(WsParamKind::Number(64)),
                 token_index: 32,
+                token_end: 44,
                 instruction_index: 5,
             },
             WsInstruction {
@@ -747,6 +1036,7 @@ mod tests {
                 cmd: WsCommandKind::Exit,
                 param: None,
                 token_index: 44,
+                token_end: 47,
                 instruction_index: 6,
             },
         ];
@@ -763,6 +1053,7 @@ mod tests {
                 cmd: WsCommandKind::Add,
                 param: None,
                 token_index: 0,
+                token_end: 4,
                 instruction_index: 0,
             },
             WsInstruction {
@@ -770,6 +1061,7 @@ mod tests {
                 cmd: WsCommandKind::Subtract,
                 param: None,
                 token_index: 4,
+                token_end: 8,
                 instruction_index: 1,
             },
             WsInstruction {
@@ -777,6 +1069,7 @@ mod tests {
                 cmd: WsCommandKind::Multiply,
                 param: None,
                 token_index: 8,
+                token_end: 12,
                 instruction_index: 2,
             },
             WsInstruction {
@@ -784,6 +1077,7 @@ mod tests {
                 cmd: WsCommandKind::IntegerDivision,
                 param: None,
                 token_index: 12,
+                token_end: 16,
                 instruction_index: 3,
             },
             WsInstruction {
@@ -791,6 +1085,7 @@ mod tests {
                 cmd: WsCommandKind::Modulo,
                 param: None,
                 token_index: 16,
+                token_end: 20,
                 instruction_index: 4,
             },
             WsInstruction {
@@ -798,6 +1093,7 @@ mod tests {
                 cmd: WsCommandKind::Exit,
                 param: None,
                 token_index: 20,
+                token_end: 23,
                 instruction_index: 5,
             },
         ];
@@ -814,6 +1110,7 @@ mod tests {
                 cmd: WsCommandKind::StoreHeap,
                 param: None,
                 token_index: 0,
+                token_end: 3,
                 instruction_index: 0,
             },
             WsInstruction {
@@ -821,6 +1118,7 @@ mod tests {
                 cmd: WsCommandKind::RetrieveHeap,
                 param: None,
                 token_index: 3,
+                token_end: 6,
                 instruction_index: 1,
             },
             WsInstruction {
@@ -828,6 +1126,7 @@ mod tests {
                 cmd: WsCommandKind::Exit,
                 param: None,
                 token_index: 6,
+                token_end: 9,
                 instruction_index: 2,
             },
         ];
@@ -844,6 +1143,7 @@ mod tests {
                 cmd: WsCommandKind::Mark,
                 param: Some(WsParamKind::Label(" \t \t \t".to_string().into(), 0)),
                 token_index: 0,
+                token_end: 10,
                 instruction_index: 0,
             },
             WsInstruction {
@@ -851,6 +1151,7 @@ mod tests {
                 cmd: WsCommandKind::Call,
                 param: Some(WsParamKind::Label(" \t \t \t".to_string().into(), 0)),
                 token_index: 10,
+                token_end: 20,
                 instruction_index: 1,
             },
             WsInstruction {
@@ -858,6 +1159,7 @@ mod tests {
                 cmd: WsCommandKind::Jump,
                 param: Some(WsParamKind::Label(" \t \t \t".to_string().into(), 0)),
                 token_index: 20,
+                token_end: 30,
                 instruction_index: 2,
             },
             WsInstruction {
@@ -865,6 +1167,7 @@ mod tests {
                 cmd: WsCommandKind::JumpZero,
                 param: Some(WsParamKind::Label(" \t \t \t".to_string().into(), 0)),
                 token_index: 30,
+                token_end: 40,
                 instruction_index: 3,
             },
             WsInstruction {
@@ -872,6 +1175,7 @@ mod tests {
                 cmd: WsCommandKind::JumpNegative,
                 param: Some(WsParamKind::Label(" \t \t \t".to_string().into(), 0)),
                 token_index: 40,
+                token_end: 50,
                 instruction_index: 4,
             },
             WsInstruction {
@@ -879,6 +1183,7 @@ mod tests {
                 cmd: WsCommandKind::Return,
                 param: None,
                 token_index: 50,
+                token_end: 53,
                 instruction_index: 5,
             },
             WsInstruction {
@@ -886,6 +1191,7 @@ mod tests {
                 cmd: WsCommandKind::Exit,
                 param: None,
                 token_index: 53,
+                token_end: 56,
                 instruction_index: 6,
             },
         ];
@@ -902,6 +1208,7 @@ mod tests {
                 cmd: WsCommandKind::OutCharacter,
                 param: None,
                 token_index: 0,
+                token_end: 4,
                 instruction_index: 0,
             },
             WsInstruction {
@@ -909,6 +1216,7 @@ mod tests {
                 cmd: WsCommandKind::OutInteger,
                 param: None,
                 token_index: 4,
+                token_end: 8,
                 instruction_index: 1,
             },
             WsInstruction {
@@ -916,6 +1224,7 @@ mod tests {
                 cmd: WsCommandKind::ReadCharacter,
                 param: None,
                 token_index: 8,
+                token_end: 12,
                 instruction_index: 2,
             },
             WsInstruction {
@@ -923,6 +1232,7 @@ mod tests {
                 cmd: WsCommandKind::ReadInteger,
                 param: None,
                 token_index: 12,
+                token_end: 16,
                 instruction_index: 3,
             },
             WsInstruction {
@@ -930,10 +1240,118 @@ mod tests {
                 cmd: WsCommandKind::Exit,
                 param: None,
                 token_index: 16,
+                token_end: 19,
                 instruction_index: 4,
             },
         ];
 
         test_parse(&mut parser, results)
     }
+
+    #[test]
+    fn stack_effect() {
+        let effects = [
+            (WsCommandKind::PushStack, (0, 1)),
+            (WsCommandKind::DuplicateStack, (1, 2)),
+            (WsCommandKind::CopyNthStack, (0, 1)),
+            (WsCommandKind::SwapStack, (2, 2)),
+            (WsCommandKind::DiscardStack, (1, 0)),
+            (WsCommandKind::SlideNStack, (1, 1)),
+            (WsCommandKind::Add, (2, 1)),
+            (WsCommandKind::Subtract, (2, 1)),
+            (WsCommandKind::Multiply, (2, 1)),
+            (WsCommandKind::IntegerDivision, (2, 1)),
+            (WsCommandKind::Modulo, (2, 1)),
+            (WsCommandKind::StoreHeap, (2, 0)),
+            (WsCommandKind::RetrieveHeap, (1, 1)),
+            (WsCommandKind::Mark, (0, 0)),
+            (WsCommandKind::Call, (0, 0)),
+            (WsCommandKind::Jump, (0, 0)),
+            (WsCommandKind::JumpZero, (1, 0)),
+            (WsCommandKind::JumpNegative, (1, 0)),
+            (WsCommandKind::Return, (0, 0)),
+            (WsCommandKind::Exit, (0, 0)),
+            (WsCommandKind::OutCharacter, (1, 0)),
+            (WsCommandKind::OutInteger, (1, 0)),
+            (WsCommandKind::ReadCharacter, (1, 0)),
+            (WsCommandKind::ReadInteger, (1, 0)),
+        ];
+
+        for (cmd, effect) in effects {
+            assert_eq!(cmd.stack_effect(), effect, "{:?}", cmd);
+        }
+    }
+
+    #[test]
+    fn parse_empty_number() -> Result<(), ParseError> {
+        let mut parser = WsParser::from_bytes(b"   \n  \t\n")?;
+        let results = vec![
+            WsInstruction {
+                imp: WsImpKind::Stack,
+                cmd: WsCommandKind::PushStack,
+                param: Some(WsParamKind::Number(0)),
+                token_index: 0,
+                token_end: 4,
+                instruction_index: 0,
+            },
+            WsInstruction {
+                imp: WsImpKind::Stack,
+                cmd: WsCommandKind::PushStack,
+                param: Some(WsParamKind::Number(0)),
+                token_index: 4,
+                token_end: 8,
+                instruction_index: 1,
+            },
+        ];
+
+        test_parse(&mut parser, results)
+    }
+
+    #[test]
+    fn tokens() -> Result<(), ParseError> {
+        let parser = WsParser::from_bytes(b"  x\t\n\n\n\n")?;
+        let tokens: Vec<_> = parser.tokens().collect();
+
+        assert_eq!(
+            tokens,
+            vec![
+                (Token::Space, 0..1),
+                (Token::Space, 1..2),
+                (Token::Tab, 3..4),
+                (Token::Newline, 4..5),
+                (Token::Newline, 5..6),
+                (Token::Newline, 6..7),
+                (Token::Newline, 7..8),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn extract_comments() -> Result<(), ParseError> {
+        let parser = WsParser::from_bytes("push\u{b7}1  \t\n\n\n\nend".as_bytes())?;
+
+        assert_eq!(
+            parser.extract_comments(),
+            vec![
+                (0..7, "push\u{b7}1".to_string()),
+                (14..17, "end".to_string())
+            ]
+        );
+        assert!(WsParser::from_bytes(b"  \t\n")?
+            .extract_comments()
+            .is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn mnemonic_round_trip() {
+        assert_eq!(WsCommandKind::all().len(), 26);
+        for cmd in WsCommandKind::all() {
+            assert_eq!(WsCommandKind::from_mnemonic(cmd.mnemonic()), Some(*cmd));
+        }
+        assert_eq!(WsCommandKind::from_mnemonic("nop"), None);
+    }
 }
@@ -0,0 +1,478 @@
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn spacey(args: &[&str], stdin: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_spacey"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin).unwrap();
+
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn debug_breakpoint_and_step() {
+    let output = spacey(
+        &["debug", "resources/ws/interpret_stack.ws"],
+        b"break 7\ncontinue\nstack\nstep\nstack\nquit\n",
+    );
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.status.success());
+    assert!(stdout.contains("breakpoint set at 7"));
+    assert!(stdout.contains("hit breakpoint at 7"));
+    assert!(stdout.contains("7: SlideNStack(Number { value: 3 })"));
+    assert!(stdout.contains("[64, 64, 64, -1]"));
+    assert!(stdout.contains("[-1]"));
+    assert!(stdout.contains("8: Exit"));
+}
+
+#[test]
+fn list_labels() {
+    let output = spacey(
+        &[
+            "--list-labels",
+            "-f",
+            "resources/ws/interpret_flow.ws",
+            "-t",
+            "whitespace",
+        ],
+        b"",
+    );
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        stdout,
+        "1: STSSSSTT -> DuplicateStack\n13: STSSSTST -> DiscardStack\n"
+    );
+}
+
+#[test]
+fn strict_rejects_overflow() {
+    let args = ["-f", "resources/ws/overflow.ws", "-t", "whitespace", "-q"];
+    let output = spacey(&args, b"");
+
+    assert!(output.status.success());
+
+    let output = spacey(&[&args[..], &["--strict"]].concat(), b"");
+
+    assert!(!output.status.success());
+}
+
+#[test]
+fn stats_json() {
+    let output = spacey(
+        &[
+            "-f",
+            "resources/ws/hello_world.ws",
+            "-t",
+            "whitespace",
+            "-q",
+            "--stats-json",
+        ],
+        b"",
+    );
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stats: serde_json::Value = serde_json::from_str(stdout.lines().last().unwrap()).unwrap();
+
+    assert!(output.status.success());
+    assert!(stdout.starts_with("Hello, world!"));
+    assert!(stats["init_ms"].is_number());
+    assert!(stats["run_ms"].is_number());
+    assert!(stats["instructions"].as_u64().unwrap() > 0);
+}
+
+#[test]
+fn size() {
+    let args = [
+        "--size",
+        "-f",
+        "resources/ws/fibonacci.ws",
+        "-t",
+        "whitespace",
+    ];
+    let output = spacey(&args, b"");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        stdout,
+        "bytes                  381\nsignificant bytes      292\ninstructions            53\n"
+    );
+
+    let output = spacey(&[&args[..], &["--stats-json"]].concat(), b"");
+    let stats: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(stats["bytes"], 381);
+    assert_eq!(stats["significant_bytes"], 292);
+    assert_eq!(stats["instructions"], 53);
+}
+
+#[test]
+fn count_only() {
+    let args = [
+        "--count-only",
+        "-f",
+        "resources/ws/interpret_stack.ws",
+        "-t",
+        "whitespace",
+    ];
+    let output = spacey(&args, b"");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        stdout,
+        "push            2\ndup             3\nswap            1\ndrop            1\n\
+        slide           1\nend             1\n"
+    );
+
+    let output = spacey(&[&args[..], &["--stats-json"]].concat(), b"");
+    let stats: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(stats["dup"], 3);
+    assert_eq!(stats["end"], 1);
+    assert!(stats["add"].is_null());
+}
+
+#[test]
+fn no_banner() {
+    let output = spacey(
+        &[
+            "--no-banner",
+            "-f",
+            "resources/ws/crlf.ws",
+            "-t",
+            "whitespace",
+        ],
+        b"",
+    );
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(stdout, "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n");
+    assert!(stderr.contains("resources/ws/crlf.ws: warning: source contains 22 carriage return(s)"));
+
+    let output = spacey(
+        &["-q", "-f", "resources/ws/crlf.ws", "-t", "whitespace"],
+        b"",
+    );
+
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn check() {
+    let check = |file| spacey(&["--check", "-f", file, "-t", "whitespace"], b"");
+    let valid = check("resources/ws/hello_world.ws");
+
+    assert!(valid.status.success());
+    assert!(valid.stdout.is_empty());
+
+    let invalid = check("resources/ws/lint_undefined_labels.ws");
+    let stderr = String::from_utf8(invalid.stderr).unwrap();
+
+    assert!(!invalid.status.success());
+    assert!(stderr.contains("instruction 1: label is never marked"));
+}
+
+#[test]
+fn invalid_heap_size() {
+    let output = spacey(
+        &[
+            "-f",
+            "resources/ws/hello_world.ws",
+            "-t",
+            "whitespace",
+            "-s",
+            "big",
+        ],
+        b"",
+    );
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stderr, "invalid value big for --heap-size\n");
+}
+
+#[test]
+fn invalid_seed() {
+    let output = spacey(
+        &[
+            "-f",
+            "resources/ws/hello_world.ws",
+            "-t",
+            "whitespace",
+            "--seed",
+            "abc",
+        ],
+        b"",
+    );
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stderr, "invalid value abc for --seed\n");
+}
+
+#[test]
+fn invalid_heap_collapse() {
+    let output = spacey(
+        &[
+            "-f",
+            "resources/ws/hello_world.ws",
+            "-t",
+            "whitespace",
+            "--heap-collapse",
+            "many",
+        ],
+        b"",
+    );
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stderr, "invalid value many for --heap-collapse\n");
+}
+
+#[test]
+fn heap_size_from_env() {
+    let run = |heap_size, args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_spacey"))
+            .args(["-f", "resources/ws/store_top.ws", "-t", "whitespace", "-q"])
+            .args(args)
+            .env("SPACEY_HEAP_SIZE", heap_size)
+            .output()
+            .unwrap()
+    };
+    let output = run("16", &[]);
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"7");
+    assert!(!run("15", &[]).status.success());
+    assert!(run("15", &["--heap-size", "16"]).status.success());
+    assert!(!run("x", &[]).status.success());
+}
+
+#[test]
+fn convert_round_trip() {
+    let convert = |from, to, file: &str| {
+        let output = spacey(&["convert", "--from", from, "--to", to, "--in", file], b"");
+        assert!(output.status.success());
+        output.stdout
+    };
+    let out_dir = std::env::temp_dir().join("spacey_convert_round_trip");
+    std::fs::create_dir_all(&out_dir).unwrap();
+    let write = |name: &str, content: &[u8]| {
+        let path = out_dir.join(name);
+        std::fs::write(&path, content).unwrap();
+        path.to_str().unwrap().to_string()
+    };
+
+    let stl = convert("whitespace", "stl", "resources/ws/hello_world.ws");
+
+    assert!(stl.iter().all(|token| b"STL".contains(token)));
+
+    let assembly = convert("stl", "asm", &write("hello_world.stl", &stl));
+
+    assert!(assembly.starts_with(b"push "));
+
+    let whitespace = convert("asm", "whitespace", &write("hello_world.asm", &assembly));
+    let path = write("hello_world.ws", &whitespace);
+    let output = spacey(&["-f", &path, "-t", "whitespace", "-q"], b"");
+
+    assert_eq!(output.stdout, b"Hello, world!");
+    assert_eq!(convert("whitespace", "asm", &path), assembly);
+
+    let invalid = write("invalid.asm", b"nop");
+    let args = [
+        "convert",
+        "--from",
+        "asm",
+        "--to",
+        "whitespace",
+        "--in",
+        &invalid,
+    ];
+
+    assert!(!spacey(&args, b"").status.success());
+
+    let args = ["convert", "--from", "bf", "--to", "stl", "--in", &path];
+
+    assert!(!spacey(&args, b"").status.success());
+}
+
+#[test]
+fn record_and_replay() {
+    let out_dir = std::env::temp_dir().join("spacey_record_and_replay");
+    std::fs::create_dir_all(&out_dir).unwrap();
+    let path = out_dir.join("bug.spacey");
+    let path = path.to_str().unwrap();
+    let recorded = spacey(
+        &["record", "resources/ws/read_twice.ws", "-o", path],
+        b"12\n34\n",
+    );
+
+    assert!(recorded.status.success());
+    assert_eq!(recorded.stdout, b"1234");
+
+    let replayed = spacey(&["replay", path], b"56\n78\n");
+
+    assert!(replayed.status.success());
+    assert_eq!(replayed.stdout, recorded.stdout);
+
+    // the run options are recorded, generated input is generated again
+    let recorded = spacey(
+        &[
+            "record",
+            "resources/ws/read_twice.ws",
+            "-o",
+            path,
+            "--seed",
+            "7",
+            "--compat",
+            "wspace",
+        ],
+        b"",
+    );
+
+    assert!(recorded.status.success());
+    assert!(!recorded.stdout.is_empty());
+
+    let replayed = spacey(&["replay", path], b"");
+
+    assert!(replayed.status.success());
+    assert_eq!(replayed.stdout, recorded.stdout);
+}
+
+#[test]
+fn diff() {
+    let file = "resources/ws/redundant.ws";
+    let identical = spacey(&["diff", file, file], b"");
+    let stdout = String::from_utf8(identical.stdout).unwrap();
+
+    assert!(identical.status.success());
+    assert!(stdout.starts_with("traces are identical"));
+
+    let optimized = spacey(&["diff", file, file, "-O"], b"");
+    let stdout = String::from_utf8(optimized.stdout).unwrap();
+
+    assert!(!optimized.status.success());
+    assert!(stdout.starts_with("traces diverge at step 7: "));
+    assert!(!stdout.contains("outputs differ"));
+}
+
+#[test]
+fn long_version() {
+    let output = spacey(&["--version"], b"");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.status.success());
+    assert!(stdout.starts_with(&format!("spacey {}\n", env!("CARGO_PKG_VERSION"))));
+    assert!(stdout.contains("\ncommit: "));
+    assert!(stdout.contains("\nprofile: "));
+    assert!(stdout.contains("\nfeatures: "));
+    assert_eq!(stdout.contains("net"), cfg!(feature = "net"));
+    assert_eq!(stdout.contains("terminal"), cfg!(feature = "terminal"));
+}
+
+#[test]
+fn trace_stack_depth() {
+    let args = [
+        "-f",
+        "resources/ws/stack_depth.ws",
+        "-t",
+        "whitespace",
+        "-q",
+        "--trace-stack-depth",
+    ];
+    let output = spacey(&args, b"");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(stderr, "stack depth: ▁▄█▄█▄▁ (peak 2)\n");
+}
+
+#[test]
+fn dump_state_on_error() {
+    let args = [
+        "-f",
+        "resources/ws/underflow_in_call.ws",
+        "-t",
+        "whitespace",
+        "-q",
+        "--dump-state-on-error",
+    ];
+    let output = spacey(&args, b"");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    assert!(!output.status.success());
+    assert!(stderr.starts_with(
+        "instruction pointer: 8\n\
+        last executed: 7: add\n\
+        stack (bottom first): []\n\
+        call stack: [4]\n\
+        heap: {0: 5}\n"
+    ));
+
+    let output = spacey(&args[..5], b"");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    assert!(!output.status.success());
+    assert!(!stderr.contains("instruction pointer"));
+}
+
+#[test]
+fn no_termination_while_streaming() {
+    let output = spacey(
+        &["-f", "resources/ws/no_exit.ws", "-t", "whitespace", "-q"],
+        b"",
+    );
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    assert!(!output.status.success());
+    assert!(stderr.contains("[0, 1, 2, 3]"));
+}
+
+#[test]
+fn source_from_stdin() {
+    let source = std::fs::read("resources/ws/hello_world.ws").unwrap();
+    let output = spacey(&["-f", "-", "-t", "whitespace", "-q"], &source);
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"Hello, world!");
+}
+
+#[cfg(feature = "net")]
+#[test]
+fn source_from_url() {
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/hello_world.ws", listener.local_addr().unwrap());
+    let server = std::thread::spawn(move || {
+        let source = std::fs::read("resources/ws/hello_world.ws").unwrap();
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = [0; 1024];
+        let _ = stream.read(&mut request).unwrap();
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            source.len()
+        )
+        .unwrap();
+        stream.write_all(&source).unwrap();
+    });
+    let output = spacey(&["-f", &url, "-t", "whitespace", "-q"], b"");
+    server.join().unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"Hello, world!");
+}